@@ -5,7 +5,7 @@
 #![allow(dead_code)]
 #![allow(unreachable_code)]
 
-use std::collections::HashMap;
+use std::collections::{ BTreeMap, HashMap };
 use crate::types::*;
 use crate::gltf;
 use byteorder::{ ReadBytesExt, BigEndian, WriteBytesExt, LittleEndian };
@@ -31,7 +31,7 @@ pub struct AnimationSection {
 	values: Vec<AnimationCommand>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Transformation {
     XRotation,
     YRotation,
@@ -46,12 +46,117 @@ pub enum Transformation {
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct AnimationCommand {
-    unknown: u8,
+    interpolation: Interpolation,
     frame: u16,
     factor: f32,
 }
 
+// the top 2 bits of the frame, how the value goes from this keyframe to the next one
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Smooth,
+    Linear,
+    Step,
+    // not seen yet, sampled linearly
+    Unknown,
+}
+
+impl Interpolation {
+    fn from_bits(bits: u16) -> Self {
+        match bits & 3 {
+            0 => Interpolation::Smooth,
+            1 => Interpolation::Linear,
+            2 => Interpolation::Step,
+            _ => Interpolation::Unknown,
+        }
+    }
+
+    fn bits(&self) -> u16 {
+        match self {
+            Interpolation::Smooth => 0,
+            Interpolation::Linear => 1,
+            Interpolation::Step => 2,
+            Interpolation::Unknown => 3,
+        }
+    }
+
+    // the progress between two keyframes, t being the elapsed fraction of the time between them
+    fn ease(&self, t: f32) -> f32 {
+        match self {
+            Interpolation::Smooth => t * t * (3.0 - 2.0 * t),
+            Interpolation::Linear | Interpolation::Unknown => t,
+            Interpolation::Step => 0.0,
+        }
+    }
+
+    // the derivative of ease
+    fn slope(&self, t: f32) -> f32 {
+        match self {
+            Interpolation::Smooth => 6.0 * t * (1.0 - t),
+            Interpolation::Linear | Interpolation::Unknown => 1.0,
+            Interpolation::Step => 0.0,
+        }
+    }
+
+    fn is_linear(&self) -> bool {
+        matches!(self, Interpolation::Linear | Interpolation::Unknown)
+    }
+}
+
+// the game runs animations at 30 frames per second, glTF times are in seconds
+const FRAME_RATE: f32 = 30.0;
+// a step is turned into a ramp this short in cubic splines
+const STEP_RAMP: f32 = 0.01;
+// factors are fixed point numbers
+const FACTOR_SCALE: f32 = 64.0;
+const MAX_FRAME: u16 = 0x3FFF;
+const MAX_BONE: u16 = 0xFFF;
+
+// the transformation of a bone at a given frame, the rotation is in degrees
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BonePose {
+    pub translation: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for BonePose {
+    // the rest pose, for the channels without keyframes
+    fn default() -> Self {
+        BonePose {
+            translation: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            scale: Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+}
+
 impl Animation {
+    // the pose of every animated bone, the frame is clamped to the range of the animation
+    // (to start_frame if the range is inverted)
+    pub fn sample(&self, frame: f32) -> BTreeMap<u16, BonePose> {
+        let frame = frame.min(self.end_frame as f32).max(self.start_frame as f32);
+        let mut poses: BTreeMap<u16, BonePose> = BTreeMap::new();
+
+        for section in &self.sections {
+            let Some(value) = sample_channel(&section.values, frame) else { continue };
+            let pose = poses.entry(section.bone).or_default();
+            match section.transformation {
+                Transformation::XRotation => pose.rotation.x = value,
+                Transformation::YRotation => pose.rotation.y = value,
+                Transformation::ZRotation => pose.rotation.z = value,
+                Transformation::XScale => pose.scale.x = value,
+                Transformation::YScale => pose.scale.y = value,
+                Transformation::ZScale => pose.scale.z = value,
+                Transformation::XTranslation => pose.translation.x = value,
+                Transformation::YTranslation => pose.translation.y = value,
+                Transformation::ZTranslation => pose.translation.z = value,
+            };
+        }
+
+        poses
+    }
+
     pub fn read_bin(filename: &str) -> std::io::Result<Self> {
         let mut f = File::open(filename)?;
         let start_frame = f.read_u16::<BigEndian>()?;
@@ -81,12 +186,12 @@ impl Animation {
         	let mut values = vec![];
 	        for _ in 0..value_count {
                 let frame = f.read_u16::<BigEndian>()?;
-                let factor = (f.read_i16::<BigEndian>()? as f32) / 64.0;
-                let unknown = (frame >> 14) as u8;
-                let frame = frame & 0x3FFF;
+                let factor = (f.read_i16::<BigEndian>()? as f32) / FACTOR_SCALE;
+                let interpolation = Interpolation::from_bits(frame >> 14);
+                let frame = frame & MAX_FRAME;
 
 	        	values.push(AnimationCommand {
-                    unknown, frame, factor
+                    interpolation, frame, factor
                 });
 	        }
 
@@ -104,6 +209,150 @@ impl Animation {
     	})
     }
 
+    pub fn write_bin(&self, filename: &str) -> std::io::Result<()> {
+        let mut f = File::create(filename)?;
+        f.write_u16::<BigEndian>(self.start_frame)?;
+        f.write_u16::<BigEndian>(self.end_frame)?;
+        f.write_u16::<BigEndian>(self.sections.len() as u16)?;
+        f.write_u16::<BigEndian>(0)?;
+
+        for section in &self.sections {
+            f.write_u8((section.bone >> 4) as u8)?;
+            f.write_u8(((section.bone & 0xF) << 4) as u8 | section.transformation as u8)?;
+            f.write_u16::<BigEndian>(section.values.len() as u16)?;
+            for cmd in &section.values {
+                f.write_u16::<BigEndian>((cmd.frame & MAX_FRAME) | (cmd.interpolation.bits() << 14))?;
+                f.write_i16::<BigEndian>((cmd.factor * FACTOR_SCALE).round() as i16)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the animation with the given name (or the first one) of a .gltf or .glb file. Bones are
+    // the nodes named with their number ("bone 3"), the joints of the first skin, or the
+    // nodes themselves. The curves are sampled every frame, rotations are turned into angles,
+    // and the keyframes a linear interpolation gives back are removed
+    pub fn read_gltf(filename: &str, name: Option<&str>) -> std::io::Result<Self> {
+        let (document, buffers) = read_gltf_document(filename)?;
+        let animation = match name {
+            Some(name) => document.animations.iter().find(|animation| animation.name.as_deref() == Some(name))
+                .ok_or_else(|| invalid_data(format!("no animation named {} in {}", name, filename)))?,
+            None => document.animations.first()
+                .ok_or_else(|| invalid_data(format!("no animation in {}", filename)))?,
+        };
+
+        let mut sections = vec![];
+        let mut frames = vec![];
+        for channel in &animation.channels {
+            let sampler = animation.samplers.get(channel.sampler)
+                .ok_or_else(|| invalid_data(format!("sampler {} doesn't exist", channel.sampler)))?;
+            let node = document.nodes.get(channel.target.node)
+                .ok_or_else(|| invalid_data(format!("node {} doesn't exist", channel.target.node)))?;
+            let bone = bone_number(&document, channel.target.node);
+            if bone > MAX_BONE {
+                return Err(invalid_data(format!("bone {} is too big, the maximum is {}", bone, MAX_BONE)));
+            }
+
+            // translations are relative to the position of the node, the other values are absolute
+            let (transformations, rest) = match channel.target.path {
+                gltf::AnimationPath::Translation => (
+                    [Transformation::XTranslation, Transformation::YTranslation, Transformation::ZTranslation],
+                    node.translation.unwrap_or([0.0; 3]),
+                ),
+                gltf::AnimationPath::Rotation => ([Transformation::XRotation, Transformation::YRotation, Transformation::ZRotation], [0.0; 3]),
+                gltf::AnimationPath::Scale => ([Transformation::XScale, Transformation::YScale, Transformation::ZScale], [0.0; 3]),
+                gltf::AnimationPath::Weights => {
+                    eprintln!("warning: morph target weights of bone {} can't be imported", bone);
+                    continue;
+                },
+            };
+            let rotation = channel.target.path == gltf::AnimationPath::Rotation;
+            let components = if rotation { 4 } else { 3 };
+
+            let times = read_accessor(&document, &buffers, sampler.input)?;
+            let outputs = read_accessor(&document, &buffers, sampler.output)?;
+            let interpolation = sampler.interpolation.as_deref().unwrap_or("LINEAR");
+            let keys = if interpolation == "CUBICSPLINE" { 3 } else { 1 };
+            if times.is_empty() || outputs.len() < times.len() * keys * components {
+                return Err(invalid_data(format!("the keyframes of bone {} don't match its times", bone)));
+            }
+
+            let mut key_frames = vec![];
+            for time in &times {
+                if *time < 0.0 || time * FRAME_RATE > MAX_FRAME as f32 {
+                    return Err(invalid_data(format!("time {} is outside of the {} frames of an animation", time, MAX_FRAME)));
+                }
+                key_frames.push((time * FRAME_RATE).round() as u16);
+            }
+            frames.extend_from_slice(&key_frames);
+
+            // steps keep the values of their keyframes, as their rounded frame can be before
+            // their time. The other curves are sampled every frame
+            let mut values: Vec<(u16, Vec<f32>)> = vec![];
+            if interpolation == "STEP" {
+                for (key, frame) in key_frames.iter().enumerate() {
+                    // of the keyframes rounded to the same frame, the last one is held
+                    if values.last().is_some_and(|(previous, _)| previous == frame) {
+                        values.pop();
+                    }
+                    values.push((*frame, outputs[key * components..(key + 1) * components].to_vec()));
+                }
+            } else {
+                for frame in key_frames[0]..=key_frames[key_frames.len() - 1] {
+                    values.push((frame, sample_gltf(&times, &outputs, components, interpolation, frame as f32 / FRAME_RATE, rotation)));
+                }
+            }
+
+            let mut samples: [Vec<(u16, f32)>; 3] = Default::default();
+            let mut previous: Option<Vector3<f32>> = None;
+            for (frame, value) in values {
+                let mut value = if rotation {
+                    rotation_angles([value[0], value[1], value[2], value[3]], previous.as_ref().map_or(0.0, |previous| previous.y))
+                } else {
+                    Vector3 { x: value[0] - rest[0], y: value[1] - rest[1], z: value[2] - rest[2] }
+                };
+                // the closest angles to the previous ones, so that they don't spin around
+                if let (true, Some(previous)) = (rotation, &previous) {
+                    value.x = unwrap_angle(value.x, previous.x);
+                    value.y = unwrap_angle(value.y, previous.y);
+                    value.z = unwrap_angle(value.z, previous.z);
+                }
+                samples[0].push((frame, value.x));
+                samples[1].push((frame, value.y));
+                samples[2].push((frame, value.z));
+                previous = Some(value);
+            }
+
+            let interpolation = if interpolation == "STEP" { Interpolation::Step } else { Interpolation::Linear };
+            // the game uses the default value for the channels without keyframes, a full turn for rotations
+            let at_rest = |factor: f32| match channel.target.path {
+                gltf::AnimationPath::Rotation => factor.rem_euclid(360.0) == 0.0,
+                gltf::AnimationPath::Scale => factor == 1.0,
+                _ => factor == 0.0,
+            };
+            for (transformation, samples) in transformations.into_iter().zip(samples) {
+                // every sample, a spin goes through other angles between its keyframes
+                if samples.iter().all(|(_, value)| at_rest((value * FACTOR_SCALE).round() / FACTOR_SCALE)) {
+                    continue;
+                }
+                let values = keyframes(&samples, interpolation)
+                    .ok_or_else(|| invalid_data(format!("{:?} of bone {} doesn't fit in a factor", transformation, bone)))?;
+                sections.retain(|section: &AnimationSection| section.bone != bone || section.transformation != transformation);
+                sections.push(AnimationSection { bone, transformation, values });
+            }
+        }
+
+        sections.sort_by_key(|section| (section.bone, section.transformation as u8));
+        Ok(Animation {
+            start_frame: frames.iter().copied().min().unwrap_or(0),
+            end_frame: frames.iter().copied().max().unwrap_or(0),
+            sections,
+        })
+    }
+
+    // one node per animated bone, with a channel per transformation. Translations and scales
+    // with both smooth and linear keyframes become cubic splines, rotations are sampled every
+    // frame unless they only have steps
     pub fn write_gltf(&self, output_dir: &str) {
         let mut root = gltf::Gltf {
             asset: gltf::Asset {
@@ -111,159 +360,158 @@ impl Animation {
                 generator: "bk_level".to_string(),
             },
             accessors: vec![],
-            animations: vec![],
+            animations: vec![gltf::Animation {
+                name: Some("animation".to_string()),
+                channels: vec![],
+                samplers: vec![],
+            }],
             buffers: vec![],
             buffer_views: vec![],
+            cameras: vec![],
             images: vec![],
             materials: vec![],
             meshes: vec![],
             nodes: vec![],
             samplers: vec![],
-            scenes: vec![],
+            scenes: vec![gltf::Scene {
+                nodes: vec![],
+            }],
             textures: vec![],
+            extensions_used: vec![],
+            extensions: None,
         };
 
-        #[derive(Debug, Clone)]
-        struct MergedAnimation {
-            translation: Vector3<Option<f32>>,
-            rotation: Vector3<Option<f32>>,
-            scale: Vector3<Option<f32>>,
-        }
-
-        #[derive(Debug)]
-        struct FramedMergedAnimation {
-            frames: HashMap<u16, MergedAnimation>,
-        }
+        let mut bones: Vec<u16> = self.sections.iter().map(|section| section.bone).collect();
+        bones.sort();
+        bones.dedup();
 
-        let mut merged_animations = HashMap::new();
+        let mut data = vec![];
+        for bone in bones {
+            let node = root.nodes.len();
+            root.scenes[0].nodes.push(node);
+            root.nodes.push(gltf::Node {
+                name: Some(format!("bone {}", bone)),
+                ..Default::default()
+            });
 
-        for section in &self.sections {
-            let mut framed_merged_animation = if merged_animations.contains_key(&section.bone) {
-                merged_animations.remove(&section.bone).unwrap()
-            } else {
-                FramedMergedAnimation {
-                    frames: HashMap::new(),
-                }
-            };
+            let paths = [
+                (gltf::AnimationPath::Translation, [Transformation::XTranslation, Transformation::YTranslation, Transformation::ZTranslation], 0.0),
+                (gltf::AnimationPath::Rotation, [Transformation::XRotation, Transformation::YRotation, Transformation::ZRotation], 0.0),
+                (gltf::AnimationPath::Scale, [Transformation::XScale, Transformation::YScale, Transformation::ZScale], 1.0),
+            ];
 
-            for cmd in &section.values {
-                if !framed_merged_animation.frames.contains_key(&cmd.frame) {
-                    framed_merged_animation.frames.insert(cmd.frame, MergedAnimation {
-                        translation: Vector3 { x: None, y: None, z: None },
-                        rotation: Vector3 { x: None, y: None, z: None },
-                        scale: Vector3 { x: None, y: None, z: None },
-                    });
+            for (path, transformations, rest) in paths {
+                let channels: Vec<Option<&[AnimationCommand]>> = transformations.iter()
+                    .map(|transformation| self.channel(bone, transformation))
+                    .collect();
+                if channels.iter().all(|channel| channel.is_none()) {
+                    continue;
                 }
 
-                match section.transformation {
-                    Transformation::XRotation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().rotation.x = Some(cmd.factor);
-                    },
-                    Transformation::YRotation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().rotation.y = Some(cmd.factor);
-                    },
-                    Transformation::ZRotation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().rotation.z = Some(cmd.factor);
-                    },
-                    Transformation::XScale => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().scale.x = Some(cmd.factor);
-                    },
-                    Transformation::YScale => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().scale.y = Some(cmd.factor);
-                    },
-                    Transformation::ZScale => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().scale.z = Some(cmd.factor);
-                    },
-                    Transformation::XTranslation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().translation.x = Some(cmd.factor);
-                    },
-                    Transformation::YTranslation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().translation.y = Some(cmd.factor);
-                    },
-                    Transformation::ZTranslation => {
-                        framed_merged_animation.frames.get_mut(&cmd.frame).unwrap().translation.z = Some(cmd.factor);
-                    },
-                };
-            }
+                let mut frames: Vec<u16> = channels.iter().flatten().flat_map(|values| values.iter().map(|cmd| cmd.frame)).collect();
+                frames.sort();
+                frames.dedup();
 
-            merged_animations.insert(section.bone, framed_merged_animation);
-        }
+                // the interpolations of all the segments between keyframes
+                let interpolations: Vec<Interpolation> = channels.iter().flatten()
+                    .flat_map(|values| values.iter().filter(|cmd| values.iter().any(|other| other.frame > cmd.frame)).map(|cmd| cmd.interpolation))
+                    .collect();
+                let all_steps = interpolations.iter().all(|i| *i == Interpolation::Step);
+                let all_linear = interpolations.iter().all(|i| i.is_linear());
 
-        for (id, fm) in &mut merged_animations {
-            let mut ct = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
-            let mut cr = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
-            let mut cs = Vector3 { x: 1.0, y: 1.0, z: 1.0 };
+                let value = |frame: f32| -> [f32; 3] {
+                    let mut value = [rest; 3];
+                    for (axis, channel) in channels.iter().enumerate() {
+                        if let Some(v) = channel.and_then(|values| sample_channel(values, frame)) {
+                            value[axis] = v;
+                        }
+                    }
+                    value
+                };
 
-            let frames = fm.frames.clone();
-            let mut keys: Vec<_> = frames.keys().collect();
-            keys.sort_unstable();
+                let (times, outputs, interpolation): (Vec<f32>, Vec<Vec<f32>>, &str) = if path == gltf::AnimationPath::Rotation {
+                    let times: Vec<f32> = if all_steps {
+                        frames.iter().map(|frame| *frame as f32).collect()
+                    } else {
+                        (frames[0]..=frames[frames.len() - 1]).map(|frame| frame as f32).collect()
+                    };
+                    let outputs = times.iter().map(|frame| {
+                        let [x, y, z] = value(*frame);
+                        euler_rotation(&Vector3 { x, y, z }).to_vec()
+                    }).collect();
+                    (times, outputs, if all_steps { "STEP" } else { "LINEAR" })
+                } else if all_steps || all_linear {
+                    let times: Vec<f32> = frames.iter().map(|frame| *frame as f32).collect();
+                    let outputs = times.iter().map(|frame| value(*frame).to_vec()).collect();
+                    (times, outputs, if all_steps { "STEP" } else { "LINEAR" })
+                } else {
+                    // the value jumps just before the keyframe following a step
+                    let mut times: Vec<f32> = frames.iter().map(|frame| *frame as f32).collect();
+                    for values in channels.iter().flatten() {
+                        for cmd in values.iter().filter(|cmd| cmd.interpolation == Interpolation::Step) {
+                            if let Some(next) = values.iter().filter(|next| next.frame > cmd.frame).map(|next| next.frame).min() {
+                                times.push(next as f32 - STEP_RAMP);
+                            }
+                        }
+                    }
+                    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    times.dedup();
 
-            for k in keys {
-                let mut f = fm.frames.get_mut(&k).unwrap();
+                    let tangent = |frame: f32, side: Side| -> [f32; 3] {
+                        let mut tangent = [0.0; 3];
+                        for (axis, channel) in channels.iter().enumerate() {
+                            if let Some(values) = channel {
+                                tangent[axis] = channel_slope(values, frame, side) * FRAME_RATE;
+                            }
+                        }
+                        tangent
+                    };
 
-                ct.x = if let Some(x) = f.translation.x {
-                    x
-                } else {
-                    f.translation.x = Some(ct.x);
-                    ct.x
-                };
-                ct.y = if let Some(y) = f.translation.y {
-                    y
-                } else {
-                    f.translation.y = Some(ct.y);
-                    ct.y
-                };
-                ct.z = if let Some(z) = f.translation.z {
-                    z
-                } else {
-                    f.translation.z = Some(ct.z);
-                    ct.z
+                    // in-tangent, value, out-tangent, per second
+                    let outputs = times.iter()
+                        .map(|frame| [tangent(*frame, Side::Before), value(*frame), tangent(*frame, Side::After)].concat())
+                        .collect();
+                    (times, outputs, "CUBICSPLINE")
                 };
 
-                cs.x = if let Some(x) = f.scale.x {
-                    x
-                } else {
-                    f.scale.x = Some(cs.x);
-                    cs.x
-                };
-                cs.y = if let Some(y) = f.scale.y {
-                    y
-                } else {
-                    f.scale.y = Some(cs.y);
-                    cs.y
-                };
-                cs.z = if let Some(z) = f.scale.z {
-                    z
-                } else {
-                    f.scale.z = Some(cs.z);
-                    cs.z
-                };
+                let times: Vec<f32> = times.iter().map(|frame| frame / FRAME_RATE).collect();
+                let input = push_accessor(&mut root, &mut data, &times, "SCALAR", true);
+                let outputs: Vec<f32> = outputs.concat();
+                let output = push_accessor(&mut root, &mut data, &outputs, if path == gltf::AnimationPath::Rotation { "VEC4" } else { "VEC3" }, false);
 
-                cr.x = if let Some(x) = f.rotation.x {
-                    x
-                } else {
-                    f.rotation.x = Some(cr.x);
-                    cr.x
-                };
-                cr.y = if let Some(y) = f.rotation.y {
-                    y
-                } else {
-                    f.rotation.y = Some(cr.y);
-                    cr.y
-                };
-                cr.z = if let Some(z) = f.rotation.z {
-                    z
-                } else {
-                    f.rotation.z = Some(cr.z);
-                    cr.z
-                };
+                let animation = &mut root.animations[0];
+                animation.channels.push(gltf::AnimationChannel {
+                    sampler: animation.samplers.len(),
+                    target: gltf::AnimationChannelTarget { node, path },
+                });
+                animation.samplers.push(gltf::AnimationSampler {
+                    input,
+                    output,
+                    interpolation: Some(interpolation.to_string()),
+                });
             }
         }
 
+        if root.animations[0].channels.is_empty() {
+            root.animations.clear();
+        } else {
+            root.buffers.push(gltf::Buffer {
+                byte_length: data.len() as u32,
+                uri: "anim.bin".to_string(),
+            });
+            std::fs::write(format!("{}/anim.bin", output_dir), &data).unwrap();
+        }
+
         let writer = File::create(format!("{}/anim.gltf", output_dir)).unwrap();
         serde_json::to_writer_pretty(writer, &root).unwrap();
     }
 
+    fn channel(&self, bone: u16, transformation: &Transformation) -> Option<&[AnimationCommand]> {
+        self.sections.iter()
+            .find(|section| section.bone == bone && section.transformation == *transformation && !section.values.is_empty())
+            .map(|section| section.values.as_slice())
+    }
+
     pub fn read_yaml(filename: &str) -> Option<Self> {
         let f = File::open(filename).expect(&format!("Can't open {}", filename));
         let ret: Result<Self, serde_yaml::Error> = serde_yaml::from_reader(f);
@@ -278,3 +526,519 @@ impl Animation {
         serde_yaml::to_writer(f, &self).unwrap();
     }
 }
+
+// the keyframes are interpolated according to the first one, the first and last ones hold before and after them
+fn sample_channel(values: &[AnimationCommand], frame: f32) -> Option<f32> {
+    let previous = values.iter().filter(|cmd| cmd.frame as f32 <= frame).max_by_key(|cmd| cmd.frame);
+    let next = values.iter().filter(|cmd| cmd.frame as f32 > frame).min_by_key(|cmd| cmd.frame);
+
+    match (previous, next) {
+        (Some(previous), Some(next)) => {
+            let t = (frame - previous.frame as f32) / (next.frame - previous.frame) as f32;
+            Some(previous.factor + (next.factor - previous.factor) * previous.interpolation.ease(t))
+        },
+        (Some(cmd), None) | (None, Some(cmd)) => Some(cmd.factor),
+        (None, None) => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Side {
+    Before,
+    After,
+}
+
+// the derivative per frame, on one side of the frame as it isn't continuous at keyframes
+fn channel_slope(values: &[AnimationCommand], frame: f32, side: Side) -> f32 {
+    let previous = match side {
+        Side::Before => values.iter().filter(|cmd| (cmd.frame as f32) < frame).max_by_key(|cmd| cmd.frame),
+        Side::After => values.iter().filter(|cmd| cmd.frame as f32 <= frame).max_by_key(|cmd| cmd.frame),
+    };
+    let next = previous.and_then(|previous| values.iter().filter(|cmd| cmd.frame > previous.frame).min_by_key(|cmd| cmd.frame));
+
+    match (previous, next) {
+        (Some(previous), Some(next)) => {
+            let length = (next.frame - previous.frame) as f32;
+            let t = (frame - previous.frame as f32) / length;
+            (next.factor - previous.factor) * previous.interpolation.slope(t) / length
+        },
+        _ => 0.0,
+    }
+}
+
+// in its own buffer view, with min and max for the times
+fn push_accessor(root: &mut gltf::Gltf, data: &mut Vec<u8>, values: &[f32], type_: &str, bounds: bool) -> usize {
+    let byte_offset = data.len() as u32;
+    for value in values {
+        data.write_f32::<LittleEndian>(*value).unwrap();
+    }
+
+    root.buffer_views.push(gltf::BufferView {
+        buffer: 0,
+        byte_length: data.len() as u32 - byte_offset,
+        byte_offset,
+        byte_stride: None,
+        target: None,
+    });
+
+    let components = match type_ { "SCALAR" => 1, "VEC3" => 3, _ => 4 };
+    root.accessors.push(gltf::Accessor {
+        buffer_view: root.buffer_views.len() - 1,
+        byte_offset: 0,
+        component_type: 5126,
+        count: values.len() / components,
+        max: if bounds { values.iter().copied().reduce(f32::max).map(|max| vec![max]) } else { None },
+        min: if bounds { values.iter().copied().reduce(f32::min).map(|min| vec![min]) } else { None },
+        normalized: false,
+        type_: type_.to_string(),
+    });
+    root.accessors.len() - 1
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// the document and its buffers, from the binary chunk of a .glb file or from files next to it
+fn read_gltf_document(filename: &str) -> std::io::Result<(gltf::AnimationDocument, Vec<Vec<u8>>)> {
+    let contents = std::fs::read(filename)?;
+    let mut json = contents.as_slice();
+    let mut chunk = None;
+    if contents.starts_with(b"glTF") {
+        // a 12 bytes header, then chunks made of their length, type and contents
+        let mut offset = 12;
+        let mut chunks = vec![];
+        while let Some(header) = contents.get(offset..offset + 8) {
+            let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+            let data = contents.get(offset + 8..offset + 8 + length)
+                .ok_or_else(|| invalid_data(format!("{} is truncated", filename)))?;
+            chunks.push((&header[4..8], data));
+            offset += 8 + length;
+        }
+        json = chunks.iter().find(|(type_, _)| *type_ == b"JSON").map(|(_, data)| *data)
+            .ok_or_else(|| invalid_data(format!("no JSON chunk in {}", filename)))?;
+        chunk = chunks.iter().find(|(type_, _)| *type_ == b"BIN\0").map(|(_, data)| data.to_vec());
+    }
+
+    let document: gltf::AnimationDocument = serde_json::from_slice(json).map_err(|error| invalid_data(format!("{}: {}", filename, error)))?;
+    let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+    let mut buffers = vec![];
+    for buffer in &document.buffers {
+        if buffer.uri.is_empty() {
+            buffers.push(chunk.take().ok_or_else(|| invalid_data(format!("no binary chunk in {}", filename)))?);
+        } else if buffer.uri.starts_with("data:") {
+            return Err(invalid_data("embedded buffers aren't supported, the buffers must be in separate files".to_string()));
+        } else {
+            buffers.push(std::fs::read(directory.join(buffer.uri.replace("%20", " ")))?);
+        }
+    }
+    Ok((document, buffers))
+}
+
+// the float components of every element
+fn read_accessor(document: &gltf::AnimationDocument, buffers: &[Vec<u8>], index: usize) -> std::io::Result<Vec<f32>> {
+    let accessor = document.accessors.get(index)
+        .ok_or_else(|| invalid_data(format!("accessor {} doesn't exist", index)))?;
+    if accessor.component_type != 5126 {
+        return Err(invalid_data(format!("accessor {} isn't made of floats", index)));
+    }
+    let components = match accessor.type_.as_str() {
+        "SCALAR" => 1,
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" => 4,
+        type_ => return Err(invalid_data(format!("accessor {} is a {}", index, type_))),
+    };
+    let view = document.buffer_views.get(accessor.buffer_view)
+        .ok_or_else(|| invalid_data(format!("buffer view {} doesn't exist", accessor.buffer_view)))?;
+    let buffer = buffers.get(view.buffer)
+        .ok_or_else(|| invalid_data(format!("buffer {} doesn't exist", view.buffer)))?;
+    let stride = view.byte_stride.map(|stride| stride as usize).unwrap_or(components * 4);
+    let start = (view.byte_offset + accessor.byte_offset) as usize;
+
+    let mut values = vec![];
+    for i in 0..accessor.count {
+        for component in 0..components {
+            let offset = start + i * stride + component * 4;
+            let bytes = buffer.get(offset..offset + 4)
+                .ok_or_else(|| invalid_data(format!("accessor {} is outside of its buffer", index)))?;
+            values.push(f32::from_le_bytes(bytes.try_into().unwrap()));
+        }
+    }
+    Ok(values)
+}
+
+// the number at the end of the name of the node, its index in the skin, or the node index
+fn bone_number(document: &gltf::AnimationDocument, node: usize) -> u16 {
+    let name = document.nodes[node].name.as_deref().unwrap_or("");
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if let Ok(bone) = name[name.len() - digits..].parse() {
+        return bone;
+    }
+    match document.skins.first().and_then(|skin| skin.joints.iter().position(|joint| *joint == node)) {
+        Some(joint) => joint as u16,
+        None => node as u16,
+    }
+}
+
+// the value of a glTF sampler at a time, the keyframes are held before and after them
+fn sample_gltf(times: &[f32], outputs: &[f32], components: usize, interpolation: &str, time: f32, rotation: bool) -> Vec<f32> {
+    let cubic = interpolation == "CUBICSPLINE";
+    let value = |key: usize| {
+        let start = if cubic { (3 * key + 1) * components } else { key * components };
+        &outputs[start..start + components]
+    };
+
+    let previous = times.iter().rposition(|t| *t <= time);
+    let (previous, next) = match previous {
+        None => return value(0).to_vec(),
+        Some(previous) if previous + 1 == times.len() || interpolation == "STEP" => return value(previous).to_vec(),
+        Some(previous) => (previous, previous + 1),
+    };
+    let length = times[next] - times[previous];
+    let t = (time - times[previous]) / length;
+    let (a, b) = (value(previous), value(next));
+
+    let mut result: Vec<f32> = if cubic {
+        let out_tangent = &outputs[(3 * previous + 2) * components..(3 * previous + 3) * components];
+        let in_tangent = &outputs[3 * next * components..(3 * next + 1) * components];
+        let (t2, t3) = (t * t, t * t * t);
+        (0..components).map(|i| {
+            (2.0 * t3 - 3.0 * t2 + 1.0) * a[i] + (t3 - 2.0 * t2 + t) * length * out_tangent[i]
+                + (-2.0 * t3 + 3.0 * t2) * b[i] + (t3 - t2) * length * in_tangent[i]
+        }).collect()
+    } else if rotation {
+        // spherical interpolation, on the shortest path
+        let mut dot: f32 = (0..4).map(|i| a[i] * b[i]).sum();
+        let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+        dot = dot.abs().min(1.0);
+        let angle = dot.acos();
+        let (wa, wb) = if angle < 1e-4 {
+            (1.0 - t, t)
+        } else {
+            (((1.0 - t) * angle).sin() / angle.sin(), (t * angle).sin() / angle.sin())
+        };
+        (0..4).map(|i| wa * a[i] + sign * wb * b[i]).collect()
+    } else {
+        (0..components).map(|i| a[i] + (b[i] - a[i]) * t).collect()
+    };
+
+    if rotation {
+        let norm = result.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            result.iter_mut().for_each(|x| *x /= norm);
+        }
+    }
+    result
+}
+
+// the angle plus or minus full turns, closest to the previous one
+fn unwrap_angle(angle: f32, previous: f32) -> f32 {
+    angle - ((angle - previous) / 360.0).round() * 360.0
+}
+
+// quantized to factors, without the keyframes a smooth or linear interpolation between their
+// neighbours gives back. None if a value doesn't fit in a factor
+fn keyframes(samples: &[(u16, f32)], interpolation: Interpolation) -> Option<Vec<AnimationCommand>> {
+    let mut quantized = vec![];
+    for (frame, value) in samples {
+        let factor = (value * FACTOR_SCALE).round();
+        if factor < i16::MIN as f32 || factor > i16::MAX as f32 {
+            return None;
+        }
+        // without negative zeros
+        quantized.push((*frame, factor / FACTOR_SCALE + 0.0));
+    }
+
+    let mut kept: Vec<AnimationCommand> = vec![];
+    if interpolation == Interpolation::Step {
+        for (i, (frame, factor)) in quantized.iter().copied().enumerate() {
+            if kept.last().is_none_or(|previous| previous.factor != factor) || i + 1 == quantized.len() {
+                kept.push(AnimationCommand { interpolation, frame, factor });
+            }
+        }
+    } else {
+        // from each keyframe to the farthest sample an interpolation goes through the samples between them
+        let fits = |start: usize, end: usize, candidate: Interpolation| {
+            let ((start_frame, start_factor), (end_frame, end_factor)) = (quantized[start], quantized[end]);
+            quantized[start + 1..end].iter().all(|(frame, factor)| {
+                let t = (frame - start_frame) as f32 / (end_frame - start_frame) as f32;
+                (start_factor + (end_factor - start_factor) * candidate.ease(t) - factor).abs() <= 1.0 / FACTOR_SCALE
+            })
+        };
+
+        let mut start = 0;
+        while start + 1 < quantized.len() {
+            let mut end = (start + 1, Interpolation::Linear);
+            for i in start + 2..quantized.len() {
+                if let Some(candidate) = [Interpolation::Linear, Interpolation::Smooth].into_iter().find(|candidate| fits(start, i, *candidate)) {
+                    end = (i, candidate);
+                }
+            }
+            let (frame, factor) = quantized[start];
+            kept.push(AnimationCommand { interpolation: end.1, frame, factor });
+            start = end.0;
+        }
+        let (frame, factor) = quantized[quantized.len() - 1];
+        kept.push(AnimationCommand { interpolation, frame, factor });
+    }
+
+    // a constant channel only needs one keyframe
+    if kept.len() == 2 && kept[0].factor == kept[1].factor {
+        kept.pop();
+    }
+    Some(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("bk_level_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn commands(values: &[(Interpolation, u16, f32)]) -> Vec<AnimationCommand> {
+        values.iter().map(|(interpolation, frame, factor)| AnimationCommand { interpolation: *interpolation, frame: *frame, factor: *factor }).collect()
+    }
+
+    fn section(bone: u16, transformation: Transformation, values: &[(Interpolation, u16, f32)]) -> AnimationSection {
+        AnimationSection { bone, transformation, values: commands(values) }
+    }
+
+    // every frame of a channel
+    fn sampled(values: &[(Interpolation, u16, f32)]) -> Vec<(u16, f32)> {
+        let values = commands(values);
+        let last = values[values.len() - 1].frame;
+        (values[0].frame..=last).map(|frame| (frame, sample_channel(&values, frame as f32).unwrap())).collect()
+    }
+
+    #[test]
+    fn linear_keyframes() {
+        let curve = [(Interpolation::Linear, 0, 0.0), (Interpolation::Linear, 30, 3.0)];
+        assert_eq!(keyframes(&sampled(&curve), Interpolation::Linear).unwrap(), commands(&curve));
+
+        let curve = [(Interpolation::Linear, 0, 0.0), (Interpolation::Linear, 10, 5.0), (Interpolation::Linear, 20, -5.0)];
+        assert_eq!(keyframes(&sampled(&curve), Interpolation::Linear).unwrap(), commands(&curve));
+    }
+
+    #[test]
+    fn smooth_keyframes() {
+        let curve = [(Interpolation::Smooth, 0, 0.0), (Interpolation::Linear, 10, 90.0), (Interpolation::Smooth, 20, 45.0), (Interpolation::Linear, 40, 45.5)];
+        assert_eq!(keyframes(&sampled(&curve), Interpolation::Linear).unwrap(), commands(&curve));
+    }
+
+    #[test]
+    fn step_keyframes() {
+        let samples = [(0, 1.0), (15, 2.0), (20, 2.0), (30, 1.0)];
+        let expected = [(Interpolation::Step, 0, 1.0), (Interpolation::Step, 15, 2.0), (Interpolation::Step, 30, 1.0)];
+        assert_eq!(keyframes(&samples, Interpolation::Step).unwrap(), commands(&expected));
+    }
+
+    #[test]
+    fn quantized_keyframes() {
+        // to 1/64, without negative zeros
+        let keys = keyframes(&[(0, -0.001), (10, 0.123)], Interpolation::Linear).unwrap();
+        assert_eq!(keys[0].factor.to_bits(), 0.0f32.to_bits());
+        assert_eq!(keys[1].factor, 0.125);
+
+        // a constant channel has one keyframe
+        assert_eq!(keyframes(&[(0, 2.0), (5, 2.0), (10, 2.0)], Interpolation::Linear).unwrap().len(), 1);
+
+        assert!(keyframes(&[(0, 512.0)], Interpolation::Linear).is_none());
+        assert!(keyframes(&[(0, -512.0)], Interpolation::Linear).is_some());
+    }
+
+    #[test]
+    fn inverted_range() {
+        let animation = Animation {
+            start_frame: 20,
+            end_frame: 10,
+            sections: vec![section(1, Transformation::XRotation, &[(Interpolation::Linear, 10, 0.0), (Interpolation::Linear, 20, 90.0)])],
+        };
+        assert_eq!(animation.sample(15.0)[&1].rotation.x, 90.0);
+    }
+
+    fn round_trip(animation: &Animation, name: &str) -> Animation {
+        let dir = temp_dir(name);
+        animation.write_gltf(&dir);
+        let imported = Animation::read_gltf(&format!("{}/anim.gltf", dir), None).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        imported
+    }
+
+    #[test]
+    fn gltf_round_trip() {
+        let animation = Animation {
+            start_frame: 0,
+            end_frame: 40,
+            sections: vec![
+                section(1, Transformation::YRotation, &[(Interpolation::Smooth, 0, 0.0), (Interpolation::Linear, 10, 90.0), (Interpolation::Linear, 40, -30.0)]),
+                section(1, Transformation::XTranslation, &[(Interpolation::Linear, 0, 0.0), (Interpolation::Smooth, 20, 10.0), (Interpolation::Linear, 40, 2.5)]),
+                section(2, Transformation::XScale, &[(Interpolation::Step, 0, 1.0), (Interpolation::Step, 15, 2.0), (Interpolation::Step, 40, 1.5)]),
+                section(12, Transformation::ZTranslation, &[(Interpolation::Linear, 0, -3.0), (Interpolation::Linear, 40, 3.0)]),
+            ],
+        };
+        assert_eq!(round_trip(&animation, "round_trip"), animation);
+    }
+
+    #[test]
+    fn gimbal_lock_round_trip() {
+        // yaw and roll aren't invented at a pitch of 90°
+        let animation = Animation {
+            start_frame: 0,
+            end_frame: 30,
+            sections: vec![section(1, Transformation::XRotation, &[(Interpolation::Linear, 0, 0.0), (Interpolation::Linear, 10, 90.0), (Interpolation::Linear, 20, 90.0), (Interpolation::Linear, 30, -90.0)])],
+        };
+        assert_eq!(round_trip(&animation, "gimbal_lock"), animation);
+    }
+
+    #[test]
+    fn full_turns() {
+        // a spin is kept, a full turn that doesn't move is at rest
+        let animation = Animation {
+            start_frame: 0,
+            end_frame: 20,
+            sections: vec![
+                section(3, Transformation::ZRotation, &[(Interpolation::Linear, 0, 0.0), (Interpolation::Linear, 20, 360.0)]),
+                section(4, Transformation::YRotation, &[(Interpolation::Step, 0, 360.0), (Interpolation::Step, 20, -720.0)]),
+            ],
+        };
+        let imported = round_trip(&animation, "full_turns");
+        assert_eq!(imported.sections.len(), 1);
+        assert_eq!(imported.sections[0].bone, 3);
+        assert_eq!(imported.sections[0].transformation, Transformation::ZRotation);
+        assert_eq!(imported.sections[0].values.last().unwrap().factor.abs(), 360.0);
+    }
+
+    // a translation of node 0, in a .gltf with the buffer next to it, or in a .glb
+    fn write_translation(dir: &str, name: &str, times: &[f32], values: &[f32], interpolation: &str, glb: bool, node_name: &str) -> String {
+        let mut data = vec![];
+        for value in times.iter().chain(values) {
+            data.write_f32::<LittleEndian>(*value).unwrap();
+        }
+        let times_length = times.len() * 4;
+        let document = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "nodes": [{ "name": node_name, "translation": [1.0, 0.0, 0.0] }],
+            "buffers": if glb { serde_json::json!([{ "byteLength": data.len() }]) } else { serde_json::json!([{ "byteLength": data.len(), "uri": "my data.bin" }]) },
+            "bufferViews": [
+                { "buffer": 0, "byteLength": times_length },
+                { "buffer": 0, "byteLength": data.len() - times_length, "byteOffset": times_length },
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": times.len(), "type": "SCALAR" },
+                { "bufferView": 1, "componentType": 5126, "count": values.len() / 3, "type": "VEC3" },
+            ],
+            "animations": [
+                { "name": "other", "channels": [], "samplers": [] },
+                {
+                    "name": "walk",
+                    "channels": [{ "sampler": 0, "target": { "node": 0, "path": "translation" } }],
+                    "samplers": [{ "input": 0, "output": 1, "interpolation": interpolation }],
+                },
+            ],
+        });
+
+        let filename = format!("{}/{}", dir, name);
+        let mut json = serde_json::to_vec(&document).unwrap();
+        if glb {
+            while !json.len().is_multiple_of(4) {
+                json.push(b' ');
+            }
+            let mut file = b"glTF".to_vec();
+            file.write_u32::<LittleEndian>(2).unwrap();
+            file.write_u32::<LittleEndian>((12 + 8 + json.len() + 8 + data.len()) as u32).unwrap();
+            file.write_u32::<LittleEndian>(json.len() as u32).unwrap();
+            file.extend_from_slice(b"JSON");
+            file.extend_from_slice(&json);
+            file.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+            file.extend_from_slice(b"BIN\0");
+            file.extend_from_slice(&data);
+            std::fs::write(&filename, file).unwrap();
+        } else {
+            std::fs::write(&filename, json).unwrap();
+            std::fs::write(format!("{}/my data.bin", dir), data).unwrap();
+        }
+        filename
+    }
+
+    #[test]
+    fn step_keyframes_between_frames() {
+        // at 24 frames per second, 1/24 is rounded to frame 1, before the keyframe
+        let dir = temp_dir("step_24fps");
+        let values = [1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 3.0, 0.0, 0.0];
+        let filename = write_translation(&dir, "steps.gltf", &[0.0, 1.0 / 24.0, 2.0 / 24.0], &values, "STEP", false, "bone 4");
+        let animation = Animation::read_gltf(&filename, Some("walk")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // relative to the translation of the node
+        let expected = Animation {
+            start_frame: 0,
+            end_frame: 3,
+            sections: vec![section(4, Transformation::XTranslation, &[(Interpolation::Step, 0, 0.0), (Interpolation::Step, 1, 1.0), (Interpolation::Step, 3, 2.0)])],
+        };
+        assert_eq!(animation, expected);
+    }
+
+    #[test]
+    fn glb() {
+        let dir = temp_dir("glb");
+        let values = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 4.0, 0.0];
+        let filename = write_translation(&dir, "walk.glb", &[0.0, 0.5, 1.0], &values, "LINEAR", true, "Bone.007");
+
+        let (document, buffers) = read_gltf_document(&filename).unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(buffers[0].len(), 12 * 4);
+        assert_eq!(read_accessor(&document, &buffers, 0).unwrap(), vec![0.0, 0.5, 1.0]);
+        assert_eq!(bone_number(&document, 0), 7);
+
+        let animation = Animation::read_gltf(&filename, Some("walk")).unwrap();
+        assert!(Animation::read_gltf(&filename, None).unwrap().sections.is_empty());
+        assert!(Animation::read_gltf(&filename, Some("run")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let expected = Animation {
+            start_frame: 0,
+            end_frame: 30,
+            sections: vec![section(7, Transformation::YTranslation, &[(Interpolation::Linear, 0, 0.0), (Interpolation::Linear, 15, 0.0), (Interpolation::Linear, 30, 4.0)])],
+        };
+        assert_eq!(animation, expected);
+    }
+
+    #[test]
+    fn bone_numbers() {
+        let document: gltf::AnimationDocument = serde_json::from_value(serde_json::json!({
+            "nodes": [{ "name": "bone 12" }, { "name": "Armature" }, { "name": "Hip" }, {}, { "name": "Bone.003" }],
+            "skins": [{ "joints": [3, 2, 1] }],
+        })).unwrap();
+        let bones: Vec<u16> = (0..5).map(|node| bone_number(&document, node)).collect();
+        assert_eq!(bones, vec![12, 2, 1, 0, 3]);
+
+        // without a skin, the node index
+        let document: gltf::AnimationDocument = serde_json::from_value(serde_json::json!({
+            "nodes": [{ "name": "Root" }, { "name": "Hip" }],
+        })).unwrap();
+        assert_eq!(bone_number(&document, 1), 1);
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        let animation = Animation {
+            start_frame: 1,
+            end_frame: 0x3FFF,
+            sections: vec![
+                section(0xFFF, Transformation::ZTranslation, &[(Interpolation::Smooth, 1, -512.0), (Interpolation::Step, 0x3FFF, i16::MAX as f32 / FACTOR_SCALE)]),
+                section(1, Transformation::XScale, &[(Interpolation::Linear, 5, 1.5), (Interpolation::Unknown, 6, 0.015625)]),
+            ],
+        };
+        let dir = temp_dir("bin");
+        let filename = format!("{}/test.anim.bin", dir);
+        animation.write_bin(&filename).unwrap();
+        let read = Animation::read_bin(&filename).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(read, animation);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+// a level of Banjo's Backpack's setups.xml, with the files of its tmp/ directory
+#[derive(Debug)]
+pub struct Level {
+    pub name: String,
+    pub model_a: Option<PathBuf>,
+    pub model_b: Option<PathBuf>,
+    pub setup: Option<PathBuf>,
+}
+
+impl Level {
+    // copies the files of the level to <output_dir>/<name>_a.model.bin, <name>_b.model.bin and <name>.lvl_setup.bin
+    pub fn extract(&self, output_dir: &str) -> io::Result<Vec<String>> {
+        let mut filenames = vec![];
+        let files = [
+            (&self.model_a, format!("{}_a.model.bin", self.name)),
+            (&self.model_b, format!("{}_b.model.bin", self.name)),
+            (&self.setup, format!("{}.lvl_setup.bin", self.name)),
+        ];
+
+        for (source, filename) in files {
+            if let Some(source) = source {
+                let filename = format!("{}/{}", output_dir, filename);
+                std::fs::copy(source, &filename)?;
+                filenames.push(filename);
+            }
+        }
+
+        Ok(filenames)
+    }
+}
+
+// each element with a `pointer` (as an attribute or a child element) is a level
+pub fn read_levels(setups_xml: &str, tmp_dir: &str) -> io::Result<Vec<Level>> {
+    let text = std::fs::read_to_string(setups_xml)?;
+    let document = roxmltree::Document::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let tmp_files = tmp_files(tmp_dir)?;
+
+    let mut levels = vec![];
+    let mut used_names: HashMap<String, usize> = HashMap::new();
+    for node in document.descendants().filter(|node| node.is_element()) {
+        let Some(pointer) = value(&node, "pointer") else { continue };
+
+        let name = value(&node, "name").map(file_name).filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("setup_{}", normalize_pointer(&pointer)));
+        let count = used_names.entry(name.clone()).or_default();
+        *count += 1;
+        let name = if *count > 1 { format!("{}_{}", name, count) } else { name };
+
+        let find = |field: &str, pointer: Option<String>| -> Option<PathBuf> {
+            let pointer = normalize_pointer(&pointer?);
+            if pointer == "0" {
+                return None;
+            }
+            let path = tmp_files.get(&pointer).cloned();
+            if path.is_none() {
+                eprintln!("warning: {}: no file for {} {} in {}", name, field, pointer, tmp_dir);
+            }
+            path
+        };
+
+        levels.push(Level {
+            model_a: find("modelAPointer", value(&node, "modelAPointer")),
+            model_b: find("modelBPointer", value(&node, "modelBPointer")),
+            setup: find("pointer", Some(pointer)),
+            name,
+        });
+    }
+
+    Ok(levels)
+}
+
+// the files of tmp/ are named after their pointer, in hexadecimal
+fn tmp_files(tmp_dir: &str) -> io::Result<HashMap<String, PathBuf>> {
+    let mut files = HashMap::new();
+    for entry in std::fs::read_dir(tmp_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let stem = path.file_name().unwrap().to_string_lossy();
+        let stem = stem.split('.').next().unwrap();
+        files.insert(normalize_pointer(stem), path);
+    }
+    Ok(files)
+}
+
+fn value(node: &roxmltree::Node, key: &str) -> Option<String> {
+    if let Some(attribute) = node.attributes().find(|attribute| attribute.name().eq_ignore_ascii_case(key)) {
+        return Some(attribute.value().trim().to_string());
+    }
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name().eq_ignore_ascii_case(key))
+        .map(|child| child.text().unwrap_or("").trim().to_string())
+}
+
+fn normalize_pointer(pointer: &str) -> String {
+    let pointer = pointer.trim().to_lowercase();
+    let pointer = pointer.strip_prefix("0x").unwrap_or(&pointer);
+    let pointer = pointer.trim_start_matches('0');
+    if pointer.is_empty() { "0".to_string() } else { pointer.to_string() }
+}
+
+// "Mumbo's Mountain" -> "mumbos_mountain"
+fn file_name(name: String) -> String {
+    let name: String = name.to_lowercase().chars()
+        .filter(|c| *c != '\'')
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    name.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_")
+}
+
+// setups.xml is in <BB>/resources/, the files are in <BB>/tmp/
+pub fn default_tmp_dir(setups_xml: &str) -> io::Result<String> {
+    let path = std::fs::canonicalize(setups_xml)?;
+    let bb = path.parent().and_then(Path::parent).unwrap_or(Path::new("/"));
+    Ok(bb.join("tmp").to_string_lossy().to_string())
+}
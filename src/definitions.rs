@@ -0,0 +1,154 @@
+use serde::{ Serialize, Deserialize };
+use std::collections::{ HashMap, HashSet };
+
+const BUILTIN_DEFINITIONS: &str = include_str!("object_types.yaml");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComplexClass {
+    Actor,
+    Timed,
+    Script,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SmallClass {
+    Sprite,
+    Static,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DefinitionsFile {
+    #[serde(default)]
+    actors: Vec<u16>,
+    #[serde(default)]
+    timers: Vec<u16>,
+    #[serde(default)]
+    scripts: Vec<u16>,
+    #[serde(default)]
+    radius: Vec<u8>,
+    #[serde(default)]
+    sprites: Vec<u16>,
+    #[serde(default)]
+    statics: Vec<u16>,
+}
+
+#[derive(Debug, Default)]
+pub struct ObjectDefinitions {
+    complex: HashMap<u16, ComplexClass>,
+    radius: HashSet<u8>,
+    small: HashMap<u16, SmallClass>,
+}
+
+impl ObjectDefinitions {
+    pub fn builtin() -> ObjectDefinitions {
+        let file: DefinitionsFile = serde_yaml::from_str(BUILTIN_DEFINITIONS).unwrap();
+        ObjectDefinitions::from_file(&file, "built-in definitions")
+    }
+
+    // IDs classified by the user file replace their built-in classification
+    pub fn load(filename: Option<&str>) -> ObjectDefinitions {
+        let mut definitions = ObjectDefinitions::builtin();
+
+        if let Some(filename) = filename {
+            let f = std::fs::File::open(filename).unwrap();
+            let file: DefinitionsFile = serde_yaml::from_reader(f).unwrap();
+            let user = ObjectDefinitions::from_file(&file, filename);
+            definitions.complex.extend(user.complex);
+            definitions.radius.extend(user.radius);
+            definitions.small.extend(user.small);
+        }
+
+        definitions
+    }
+
+    fn from_file(file: &DefinitionsFile, source: &str) -> ObjectDefinitions {
+        let mut definitions = ObjectDefinitions::default();
+
+        classify(&mut definitions.complex, &file.actors, ComplexClass::Actor, source);
+        classify(&mut definitions.complex, &file.timers, ComplexClass::Timed, source);
+        classify(&mut definitions.complex, &file.scripts, ComplexClass::Script, source);
+        classify(&mut definitions.small, &file.sprites, SmallClass::Sprite, source);
+        classify(&mut definitions.small, &file.statics, SmallClass::Static, source);
+
+        for id in &file.radius {
+            if !definitions.radius.insert(*id) {
+                eprintln!("warning: {}: radius {:#04X} is listed more than once", source, id);
+            }
+        }
+
+        definitions
+    }
+
+    pub fn complex_class(&self, object: u16) -> Option<ComplexClass> {
+        self.complex.get(&object).copied()
+    }
+
+    pub fn is_radius(&self, object: u8) -> bool {
+        self.radius.contains(&object)
+    }
+
+    pub fn small_class(&self, object: u16) -> Option<SmallClass> {
+        self.small.get(&object).copied()
+    }
+}
+
+// the first class an ID is listed in wins
+fn classify<T: Copy + PartialEq + std::fmt::Debug>(classes: &mut HashMap<u16, T>, ids: &[u16], class: T, source: &str) {
+    for id in ids {
+        match classes.get(id) {
+            Some(previous) if *previous == class => {
+                eprintln!("warning: {}: {:?} {:#06X} is listed more than once", source, class, id);
+            },
+            Some(previous) => {
+                eprintln!("warning: {}: {:#06X} is listed as both {:?} and {:?}, keeping {:?}", source, id, previous, class, previous);
+            },
+            None => {
+                classes.insert(*id, class);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicts() {
+        let file: DefinitionsFile = serde_yaml::from_str("
+actors: [0x0010, 0x0011, 0x0010]
+timers: [0x0011, 0x0012]
+radius: [3, 3]
+sprites: [0x0020]
+statics: [0x0020, 0x0021]
+").unwrap();
+        let definitions = ObjectDefinitions::from_file(&file, "test");
+
+        // the first class wins
+        assert_eq!(definitions.complex_class(0x0010), Some(ComplexClass::Actor));
+        assert_eq!(definitions.complex_class(0x0011), Some(ComplexClass::Actor));
+        assert_eq!(definitions.complex_class(0x0012), Some(ComplexClass::Timed));
+        assert_eq!(definitions.complex_class(0x0013), None);
+        assert_eq!(definitions.small_class(0x0020), Some(SmallClass::Sprite));
+        assert_eq!(definitions.small_class(0x0021), Some(SmallClass::Static));
+        assert!(definitions.is_radius(3));
+        assert!(!definitions.is_radius(4));
+    }
+
+    #[test]
+    fn user_file() {
+        let builtin = ObjectDefinitions::builtin();
+        let (&id, &class) = builtin.complex.iter().find(|(_, class)| **class != ComplexClass::Script).unwrap();
+
+        let filename = std::env::temp_dir().join("bk_level_definitions.yaml");
+        std::fs::write(&filename, format!("scripts: [{}]\nsprites: [0xFFFE]\n", id)).unwrap();
+        let definitions = ObjectDefinitions::load(filename.to_str());
+        std::fs::remove_file(&filename).unwrap();
+
+        // listed by the user, it isn't also the built-in class
+        assert_ne!(class, ComplexClass::Script);
+        assert_eq!(definitions.complex_class(id), Some(ComplexClass::Script));
+        assert_eq!(definitions.small_class(0xFFFE), Some(SmallClass::Sprite));
+        assert_eq!(definitions.complex.len(), builtin.complex.len());
+    }
+}
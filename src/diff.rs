@@ -0,0 +1,545 @@
+use crate::setupfile::{ SetupFile, object_label };
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Moved,
+    Modified,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub change: ChangeKind,
+    pub what: String,
+    // where it is in the new file, or was in the old one when removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SetupDiff {
+    pub objects: Vec<Change>,
+    pub cameras: Vec<Change>,
+    pub lightings: Vec<Change>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LineChange {
+    pub change: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_index: Option<usize>,
+    pub line: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModelDiff {
+    pub textures: Vec<Change>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertex_count: Option<FieldChange>,
+    pub vertices: Vec<Change>,
+    pub commands: Vec<LineChange>,
+    pub collisions: Vec<Change>,
+    pub geometry: Vec<FieldChange>,
+}
+
+// anything that can be matched between two files: objects, cameras and lights
+struct Item {
+    // only items with the same key can match
+    key: String,
+    what: String,
+    position: Option<[f32; 3]>,
+    fields: Value,
+}
+
+impl SetupDiff {
+    // objects and lights further apart than max_distance are reported as removed and added,
+    // cameras are matched by id wherever they are
+    pub fn new(old: &SetupFile, new: &SetupFile, max_distance: f32) -> SetupDiff {
+        SetupDiff {
+            objects: diff_items(object_items(old), object_items(new), max_distance),
+            cameras: diff_items(camera_items(old), camera_items(new), f32::INFINITY),
+            lightings: diff_items(lighting_items(old), lighting_items(new), max_distance),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty() && self.cameras.is_empty() && self.lightings.is_empty()
+    }
+}
+
+impl fmt::Display for SetupDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (title, changes) in [("objects", &self.objects), ("cameras", &self.cameras), ("lightings", &self.lightings)] {
+            if changes.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "{}:", title)?;
+            for change in changes {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ModelDiff {
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty() && self.vertex_count.is_none() && self.vertices.is_empty()
+            && self.commands.is_empty() && self.collisions.is_empty() && self.geometry.is_empty()
+    }
+}
+
+impl fmt::Display for ModelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.textures.is_empty() {
+            writeln!(f, "textures:")?;
+            for change in &self.textures {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        if let Some(count) = &self.vertex_count {
+            writeln!(f, "vertex count: {} -> {}", count.old, count.new)?;
+        }
+        if !self.vertices.is_empty() {
+            writeln!(f, "vertices:")?;
+            for change in &self.vertices {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        if !self.commands.is_empty() {
+            writeln!(f, "display list:")?;
+            for change in &self.commands {
+                match change.change {
+                    ChangeKind::Removed => writeln!(f, "  - [{}] {}", change.old_index.unwrap(), change.line)?,
+                    _ => writeln!(f, "  + [{}] {}", change.new_index.unwrap(), change.line)?,
+                };
+            }
+        }
+
+        if !self.collisions.is_empty() {
+            writeln!(f, "collisions:")?;
+            for change in &self.collisions {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        if !self.geometry.is_empty() {
+            writeln!(f, "geometry layout:")?;
+            for field in &self.geometry {
+                writeln!(f, "  {}: {} -> {}", field.field, field.old, field.new)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self.change {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Moved | ChangeKind::Modified => '~',
+        };
+        write!(f, "{} {}", symbol, self.what)?;
+
+        if let Some(p) = self.position {
+            write!(f, " at ({}, {}, {})", p[0], p[1], p[2])?;
+        }
+        if let Some(d) = self.delta {
+            write!(f, " moved by ({}, {}, {})", d[0], d[1], d[2])?;
+        }
+        for field in &self.fields {
+            write!(f, "\n      {}: {} -> {}", field.field, field.old, field.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+// objects of both lists are matched by type and ID, the closest ones first, up to max_distance apart
+fn diff_items(old: Vec<Item>, new: Vec<Item>, max_distance: f32) -> Vec<Change> {
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut changes = vec![];
+
+    // unchanged items first, so they can't be taken by a closer modified one
+    for (i, o) in old.iter().enumerate() {
+        let found = new.iter().enumerate().find(|(j, n)| {
+            !new_matched[*j] && n.key == o.key && n.position == o.position && n.fields == o.fields
+        });
+        if let Some((j, _)) = found {
+            old_matched[i] = true;
+            new_matched[j] = true;
+        }
+    }
+
+    let mut pairs = vec![];
+    for (i, o) in old.iter().enumerate().filter(|(i, _)| !old_matched[*i]) {
+        for (j, n) in new.iter().enumerate().filter(|(j, _)| !new_matched[*j]) {
+            let distance = distance(o.position, n.position);
+            if n.key == o.key && distance <= max_distance {
+                pairs.push((distance, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    for (_, i, j) in pairs {
+        if old_matched[i] || new_matched[j] {
+            continue;
+        }
+        old_matched[i] = true;
+        new_matched[j] = true;
+
+        let (o, n) = (&old[i], &new[j]);
+        let delta = match (o.position, n.position) {
+            (Some(a), Some(b)) if a != b => Some([b[0] - a[0], b[1] - a[1], b[2] - a[2]]),
+            _ => None,
+        };
+
+        let mut fields = vec![];
+        diff_values("", &o.fields, &n.fields, &mut fields);
+
+        changes.push(Change {
+            change: if delta.is_some() { ChangeKind::Moved } else { ChangeKind::Modified },
+            what: n.what.clone(),
+            position: n.position,
+            delta,
+            fields,
+        });
+    }
+
+    for (_, o) in old.iter().enumerate().filter(|(i, _)| !old_matched[*i]) {
+        changes.push(Change { change: ChangeKind::Removed, what: o.what.clone(), position: o.position, delta: None, fields: vec![] });
+    }
+    for (_, n) in new.iter().enumerate().filter(|(j, _)| !new_matched[*j]) {
+        changes.push(Change { change: ChangeKind::Added, what: n.what.clone(), position: n.position, delta: None, fields: vec![] });
+    }
+
+    changes
+}
+
+fn distance(a: Option<[f32; 3]>, b: Option<[f32; 3]>) -> f32 {
+    match (a, b) {
+        (Some(a), Some(b)) => ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt(),
+        _ => 0.0,
+    }
+}
+
+// nested values are compared field by field, as `angles.y` or `commands[2].len`
+pub fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            for (key, value) in o {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                diff_values(&path, value, n.get(key).unwrap_or(&Value::Null), changes);
+            }
+            for (key, value) in n {
+                if !o.contains_key(key) {
+                    let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                    diff_values(&path, &Value::Null, value, changes);
+                }
+            }
+        },
+        (Value::Array(o), Value::Array(n)) => {
+            for i in 0..o.len().max(n.len()) {
+                let path = format!("{}[{}]", path, i);
+                diff_values(&path, o.get(i).unwrap_or(&Value::Null), n.get(i).unwrap_or(&Value::Null), changes);
+            }
+        },
+        _ if old != new => changes.push(FieldChange { field: path.to_string(), old: old.clone(), new: new.clone() }),
+        _ => {},
+    }
+}
+
+// the fields of an enum variant, without the ones compared separately
+fn variant_fields<T: Serialize>(value: &T, skipped: &[&str]) -> Value {
+    let value = serde_json::to_value(value).unwrap();
+    let mut fields = match value {
+        Value::Object(o) => match o.into_iter().next() {
+            Some((_, Value::Object(fields))) => fields,
+            _ => serde_json::Map::new(),
+        },
+        _ => serde_json::Map::new(),
+    };
+
+    for key in skipped {
+        fields.remove(*key);
+    }
+
+    Value::Object(fields)
+}
+
+fn object_items(setup: &SetupFile) -> Vec<Item> {
+    let mut items = vec![];
+
+    for voxel in &setup.voxels {
+        for complex in &voxel.complex_objects {
+            let p = complex.position();
+            items.push(Item {
+                key: format!("complex {} {}", complex.kind(), complex.object_id()),
+                what: object_label(complex.kind(), complex.object_id(), complex.name()),
+                position: Some([p.x as f32, p.y as f32, p.z as f32]),
+                fields: variant_fields(complex, &["name", "x", "y", "z"]),
+            });
+        }
+
+        // unknown small objects don't have coordinates, their voxel is compared instead
+        for small in &voxel.small_objects {
+            let mut fields = variant_fields(small, &["name", "x", "y", "z"]);
+            if small.position().is_none() {
+                fields["voxel"] = serde_json::to_value(&voxel.position).unwrap();
+            }
+
+            items.push(Item {
+                key: format!("small {} {}", small.kind(), small.object_id()),
+                what: object_label(small.kind(), small.object_id(), small.name()),
+                position: small.position().map(|p| [p.x as f32, p.y as f32, p.z as f32]),
+                fields,
+            });
+        }
+    }
+
+    items
+}
+
+fn camera_items(setup: &SetupFile) -> Vec<Item> {
+    setup.cameras.iter().map(|camera| {
+        let mut fields = variant_fields(camera, &["position"]);
+        fields["type"] = Value::from(camera.kind());
+
+        Item {
+            key: format!("camera {}", camera.id()),
+            what: format!("camera {}", camera.id()),
+            position: camera.placement().map(|(p, _)| [p.x, p.y, p.z]),
+            fields,
+        }
+    }).collect()
+}
+
+fn lighting_items(setup: &SetupFile) -> Vec<Item> {
+    setup.lightings.iter().map(|lighting| {
+        let mut fields = serde_json::to_value(lighting).unwrap();
+        fields.as_object_mut().unwrap().remove("position");
+
+        Item {
+            key: "light".to_string(),
+            what: "light".to_string(),
+            position: Some(lighting.position()),
+            fields,
+        }
+    }).collect()
+}
+
+// shortest edit script between two sequences (Myers' algorithm), only the removed and added lines are returned
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<LineChange> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = vec![];
+
+    'search: for d in 0..=(max as isize) {
+        // only the diagonals -d-1..=d+1 are read when walking back
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // walk the trace back from the end to find the edits
+    let mut changes = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            if x == previous_x {
+                changes.push(LineChange { change: ChangeKind::Added, old_index: None, new_index: Some(previous_y as usize), line: new[previous_y as usize].clone() });
+            } else {
+                changes.push(LineChange { change: ChangeKind::Removed, old_index: Some(previous_x as usize), new_index: None, line: old[previous_x as usize].clone() });
+            }
+        }
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    changes.reverse();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(key: &str, x: f32, fields: Value) -> Item {
+        Item { key: key.to_string(), what: key.to_string(), position: Some([x, 0.0, 0.0]), fields }
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, &str, Option<[f32; 3]>)> {
+        changes.iter().map(|change| (change.change, change.what.as_str(), change.position)).collect()
+    }
+
+    #[test]
+    fn moved() {
+        let changes = diff_items(vec![item("jiggy", 100.0, json!({}))], vec![item("jiggy", 350.0, json!({}))], 1000.0);
+        assert_eq!(changes, [Change {
+            change: ChangeKind::Moved, what: "jiggy".to_string(), position: Some([350.0, 0.0, 0.0]), delta: Some([250.0, 0.0, 0.0]), fields: vec![],
+        }]);
+        assert_eq!(changes[0].to_string(), "~ jiggy at (350, 0, 0) moved by (250, 0, 0)");
+
+        assert!(diff_items(vec![item("jiggy", 100.0, json!({}))], vec![item("jiggy", 100.0, json!({}))], 1000.0).is_empty());
+    }
+
+    #[test]
+    fn modified_fields() {
+        let old = json!({ "script": 1, "angles": { "x": 0.0, "y": 90.0 }, "list": [1, 2], "removed": true });
+        let new = json!({ "script": 1, "angles": { "x": 0.0, "y": 180.0 }, "list": [1, 3, 4], "added": "a" });
+        let changes = diff_items(vec![item("camera 1", 0.0, old)], vec![item("camera 1", 0.0, new)], 1000.0);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].change, changes[0].delta), (ChangeKind::Modified, None));
+        let fields: Vec<(&str, String, String)> = changes[0].fields.iter()
+            .map(|field| (field.field.as_str(), field.old.to_string(), field.new.to_string())).collect();
+        assert_eq!(fields, [
+            ("angles.y", "90.0".to_string(), "180.0".to_string()),
+            ("list[1]", "2".to_string(), "3".to_string()),
+            ("list[2]", "null".to_string(), "4".to_string()),
+            ("removed", "true".to_string(), "null".to_string()),
+            ("added", "null".to_string(), "\"a\"".to_string()),
+        ]);
+        assert!(changes[0].to_string().starts_with("~ camera 1 at (0, 0, 0)\n      angles.y: 90.0 -> 180.0\n      list[1]: 2 -> 3"));
+    }
+
+    #[test]
+    fn max_distance() {
+        let changes = diff_items(vec![item("note", 0.0, json!({}))], vec![item("note", 1500.0, json!({}))], 1000.0);
+        assert_eq!(summary(&changes), [
+            (ChangeKind::Removed, "note", Some([0.0, 0.0, 0.0])),
+            (ChangeKind::Added, "note", Some([1500.0, 0.0, 0.0])),
+        ]);
+
+        // exactly at the limit, or anywhere without one
+        assert_eq!(diff_items(vec![item("note", 0.0, json!({}))], vec![item("note", 1000.0, json!({}))], 1000.0)[0].change, ChangeKind::Moved);
+        assert_eq!(diff_items(vec![item("note", 0.0, json!({}))], vec![item("note", 1500.0, json!({}))], f32::INFINITY).len(), 1);
+
+        // only items with the same key are matched
+        let changes = diff_items(vec![item("note", 0.0, json!({}))], vec![item("jiggy", 0.0, json!({}))], 1000.0);
+        assert_eq!(summary(&changes), [(ChangeKind::Removed, "note", Some([0.0; 3])), (ChangeKind::Added, "jiggy", Some([0.0; 3]))]);
+    }
+
+    #[test]
+    fn closest_first() {
+        // an unchanged item isn't taken by a modified one as close
+        let old = vec![item("jinjo", 0.0, json!({ "colour": 2 })), item("jinjo", 0.0, json!({ "colour": 1 }))];
+        let new = vec![item("jinjo", 0.0, json!({ "colour": 1 }))];
+        let changes = diff_items(old, new, 1000.0);
+        assert_eq!(summary(&changes), [(ChangeKind::Removed, "jinjo", Some([0.0; 3]))]);
+        assert!(changes[0].fields.is_empty());
+
+        let old = vec![item("jinjo", 5.0, json!({ "colour": 2 })), item("jinjo", 100.0, json!({ "colour": 1 }))];
+        let new = vec![item("jinjo", 100.0, json!({ "colour": 1 })), item("jinjo", 200.0, json!({ "colour": 2 }))];
+        let changes = diff_items(old, new, 1000.0);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].delta, Some([195.0, 0.0, 0.0]));
+
+        // the closest pairs are matched first, whatever the order of the lists
+        let old = vec![item("note", 0.0, json!({})), item("note", 100.0, json!({}))];
+        let new = vec![item("note", 90.0, json!({})), item("note", 5.0, json!({}))];
+        let deltas: Vec<_> = diff_items(old, new, 1000.0).iter().map(|change| change.delta.unwrap()[0]).collect();
+        assert_eq!(deltas, [5.0, -10.0]);
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.chars().map(String::from).collect()
+    }
+
+    fn edits(old: &str, new: &str) -> Vec<(ChangeKind, Option<usize>, Option<usize>, String)> {
+        diff_lines(&lines(old), &lines(new)).into_iter()
+            .map(|change| (change.change, change.old_index, change.new_index, change.line)).collect()
+    }
+
+    #[test]
+    fn lines_empty() {
+        assert!(edits("", "").is_empty());
+        assert!(edits("abc", "abc").is_empty());
+        assert_eq!(edits("", "ab"), [
+            (ChangeKind::Added, None, Some(0), "a".to_string()),
+            (ChangeKind::Added, None, Some(1), "b".to_string()),
+        ]);
+        assert_eq!(edits("ab", ""), [
+            (ChangeKind::Removed, Some(0), None, "a".to_string()),
+            (ChangeKind::Removed, Some(1), None, "b".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn lines_middle() {
+        assert_eq!(edits("abcd", "abXcd"), [(ChangeKind::Added, None, Some(2), "X".to_string())]);
+        assert_eq!(edits("abcd", "abd"), [(ChangeKind::Removed, Some(2), None, "c".to_string())]);
+        assert_eq!(edits("abcd", "abXd"), [
+            (ChangeKind::Removed, Some(2), None, "c".to_string()),
+            (ChangeKind::Added, None, Some(2), "X".to_string()),
+        ]);
+
+        // the shortest edit, whatever the lengths
+        for (old, new, count) in [("abcabba", "cbabac", 5), ("a", "bbbb", 5), ("xaxbx", "ab", 3)] {
+            let changes = diff_lines(&lines(old), &lines(new));
+            assert_eq!(changes.len(), count, "{} -> {}", old, new);
+            let removed = changes.iter().filter(|change| change.change == ChangeKind::Removed).count();
+            assert_eq!(old.len() - removed, new.len() - (count - removed));
+        }
+    }
+}
@@ -12,43 +12,119 @@ pub enum Extension {
     },
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "UPPERCASE")]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AnimationPath {
-    Position,
+    Translation,
     Rotation,
     Scale,
-    Weight,
+    Weights,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Gltf {
     pub asset: Asset,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub accessors: Vec<Accessor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub animations: Vec<Animation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub buffers: Vec<Buffer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub buffer_views: Vec<BufferView>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cameras: Vec<Camera>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub images: Vec<Image>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub materials: Vec<Material>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub meshes: Vec<Mesh>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub nodes: Vec<Node>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub samplers: Vec<Sampler>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub scenes: Vec<Scene>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub textures: Vec<Texture>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions_used: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<GltfExtensions>,
+}
+
+// the parts of a document needed to import its animations, the other ones are ignored
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimationDocument {
+    #[serde(default)]
+    pub accessors: Vec<Accessor>,
+    #[serde(default)]
+    pub animations: Vec<Animation>,
+    #[serde(default)]
+    pub buffers: Vec<Buffer>,
+    #[serde(default)]
+    pub buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub skins: Vec<Skin>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Skin {
+    pub joints: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GltfExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    pub khr_lights_punctual: LightsPunctual,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightsPunctual {
+    pub lights: Vec<Light>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename(serialize = "type", deserialize = "type"))]
+    pub type_: String,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<f32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    pub khr_lights_punctual: NodeLight,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeLight {
+    pub light: usize,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Accessor {
     pub buffer_view: usize,
+    #[serde(default)]
     pub byte_offset: u32,
     pub component_type: u32,
     pub count: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub max: Option<[f32; 3]>,
+    pub max: Option<Vec<f32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub min: Option<[f32; 3]>,
+    pub min: Option<Vec<f32>>,
+    #[serde(default)]
     pub normalized: bool,
     #[serde(rename(serialize = "type", deserialize = "type"))]
     pub type_: String,
@@ -57,29 +133,34 @@ pub struct Accessor {
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Animation {
-    channels: Vec<AnimationChannel>,
-    samplers: Vec<AnimationSampler>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub channels: Vec<AnimationChannel>,
+    pub samplers: Vec<AnimationSampler>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AnimationChannel {
-    sampler: usize,
-    target: AnimationChannelTarget,
+    pub sampler: usize,
+    pub target: AnimationChannelTarget,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AnimationChannelTarget {
-    node: usize,
-    path: AnimationPath,
+    pub node: usize,
+    pub path: AnimationPath,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AnimationSampler {
-    input: usize,
-    output: usize,
+    pub input: usize,
+    pub output: usize,
+    // LINEAR when omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -93,6 +174,8 @@ pub struct Asset {
 #[serde(rename_all = "camelCase")]
 pub struct Buffer {
     pub byte_length: u32,
+    // the binary chunk of a .glb file when empty
+    #[serde(default)]
     pub uri: String,
 }
 
@@ -101,9 +184,22 @@ pub struct Buffer {
 pub struct BufferView {
     pub buffer: usize,
     pub byte_length: u32,
+    #[serde(default)]
     pub byte_offset: u32,
-    pub byte_stride: u32,
-    pub target: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_stride: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Camera {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename(serialize = "type", deserialize = "type"))]
+    pub type_: String,
+    pub perspective: Perspective,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -117,6 +213,9 @@ pub struct Image {
 pub struct Material {
     pub pbr_metallic_roughness: PbrMetallicRoughness,
     pub alpha_mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_cutoff: Option<f32>,
+    pub double_sided: bool,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -125,31 +224,58 @@ pub struct Mesh {
     pub primitives: Vec<Primitive>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Node {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mesh: Option<usize>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub children: Vec<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub translation: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<[f32; 4]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<NodeExtensions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PbrMetallicRoughness {
-    pub base_color_texture: TextureInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_color_texture: Option<TextureInfo>,
     pub metallic_factor: f32,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Perspective {
+    pub yfov: f32,
+    pub znear: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zfar: Option<f32>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Primitive {
     pub attributes: HashMap<String, usize>,
-    pub material: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
 }
 
+pub const PRIMITIVE_LINE_STRIP: u32 = 3;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Sampler {
@@ -177,4 +303,114 @@ pub struct Texture {
 pub struct TextureInfo {
     pub index: usize,
     pub extensions: HashMap<String, Extension>,
-}
\ No newline at end of file
+}
+impl Gltf {
+    // moves the contents of another document into this one, its files being in the directory uri_prefix,
+    // and returns the nodes of its first scene
+    pub fn append(&mut self, other: Gltf, uri_prefix: &str) -> Vec<usize> {
+        let accessors = self.accessors.len();
+        let buffers = self.buffers.len();
+        let buffer_views = self.buffer_views.len();
+        let cameras = self.cameras.len();
+        let images = self.images.len();
+        let materials = self.materials.len();
+        let meshes = self.meshes.len();
+        let nodes = self.nodes.len();
+        let samplers = self.samplers.len();
+        let textures = self.textures.len();
+        let lights = self.extensions.as_ref().map_or(0, |e| e.khr_lights_punctual.lights.len());
+
+        for mut accessor in other.accessors {
+            accessor.buffer_view += buffer_views;
+            self.accessors.push(accessor);
+        }
+
+        for mut animation in other.animations {
+            for channel in &mut animation.channels {
+                channel.target.node += nodes;
+            }
+            for sampler in &mut animation.samplers {
+                sampler.input += accessors;
+                sampler.output += accessors;
+            }
+            self.animations.push(animation);
+        }
+
+        for mut buffer in other.buffers {
+            buffer.uri = format!("{}{}", uri_prefix, buffer.uri);
+            self.buffers.push(buffer);
+        }
+
+        for mut buffer_view in other.buffer_views {
+            buffer_view.buffer += buffers;
+            self.buffer_views.push(buffer_view);
+        }
+
+        self.cameras.extend(other.cameras);
+
+        for mut image in other.images {
+            image.uri = format!("{}{}", uri_prefix, image.uri);
+            self.images.push(image);
+        }
+
+        for mut material in other.materials {
+            if let Some(texture) = &mut material.pbr_metallic_roughness.base_color_texture {
+                texture.index += textures;
+            }
+            self.materials.push(material);
+        }
+
+        for mut mesh in other.meshes {
+            for primitive in &mut mesh.primitives {
+                for accessor in primitive.attributes.values_mut() {
+                    *accessor += accessors;
+                }
+                if let Some(material) = &mut primitive.material {
+                    *material += materials;
+                }
+            }
+            self.meshes.push(mesh);
+        }
+
+        for mut node in other.nodes {
+            if let Some(mesh) = &mut node.mesh {
+                *mesh += meshes;
+            }
+            if let Some(camera) = &mut node.camera {
+                *camera += cameras;
+            }
+            for child in &mut node.children {
+                *child += nodes;
+            }
+            if let Some(extensions) = &mut node.extensions {
+                extensions.khr_lights_punctual.light += lights;
+            }
+            self.nodes.push(node);
+        }
+
+        self.samplers.extend(other.samplers);
+
+        for mut texture in other.textures {
+            texture.sampler += samplers;
+            texture.source += images;
+            self.textures.push(texture);
+        }
+
+        for extension in other.extensions_used {
+            if !self.extensions_used.contains(&extension) {
+                self.extensions_used.push(extension);
+            }
+        }
+
+        if let Some(extensions) = other.extensions {
+            match &mut self.extensions {
+                Some(own) => own.khr_lights_punctual.lights.extend(extensions.khr_lights_punctual.lights),
+                None => self.extensions = Some(extensions),
+            }
+        }
+
+        other.scenes.into_iter().next()
+            .map(|scene| scene.nodes.into_iter().map(|node| node + nodes).collect())
+            .unwrap_or_default()
+    }
+}
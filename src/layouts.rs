@@ -0,0 +1,293 @@
+use serde::{ Serialize, Deserialize };
+use std::collections::{ BTreeMap, HashMap };
+use std::io;
+
+// user-supplied layouts of the bytes of unknown objects, by object ID.
+// the payload of an unknown small object is unk1..unk10 (10 bytes),
+// the one of an unknown complex object is script and unk_0a..unk_0f (8 bytes)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ObjectLayouts {
+    #[serde(default)]
+    pub complex: HashMap<u16, Vec<FieldLayout>>,
+    #[serde(default)]
+    pub small: HashMap<u16, Vec<FieldLayout>>,
+}
+
+// bits are counted from the most significant bit of the first byte
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldLayout {
+    pub name: String,
+    pub offset: u32,
+    pub bits: u32,
+    #[serde(rename = "type")]
+    pub type_: FieldType,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Unsigned,
+    Signed,
+    Bool,
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f32),
+    // the hexadecimal bits of a NaN float, YAML would lose its payload
+    Bits(String),
+}
+
+pub type Fields = BTreeMap<String, FieldValue>;
+
+pub const COMPLEX_PAYLOAD_SIZE: usize = 8;
+pub const SMALL_PAYLOAD_SIZE: usize = 10;
+
+impl ObjectLayouts {
+    pub fn load(filename: Option<&str>) -> io::Result<ObjectLayouts> {
+        let layouts: ObjectLayouts = match filename {
+            Some(filename) => {
+                let f = std::fs::File::open(filename).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", filename, e)))?;
+                serde_yaml::from_reader(f).map_err(|e| invalid_data(format!("{}: {}", filename, e)))?
+            },
+            None => ObjectLayouts::default(),
+        };
+
+        for (id, fields) in &layouts.complex {
+            check_layout("complex", *id, fields, COMPLEX_PAYLOAD_SIZE).map_err(invalid_data)?;
+        }
+        for (id, fields) in &layouts.small {
+            check_layout("small", *id, fields, SMALL_PAYLOAD_SIZE).map_err(invalid_data)?;
+        }
+
+        Ok(layouts)
+    }
+}
+
+fn check_layout(kind: &str, id: u16, fields: &[FieldLayout], payload_size: usize) -> Result<(), String> {
+    let mut used = vec![false; payload_size * 8];
+
+    for field in fields {
+        let valid_size = match field.type_ {
+            FieldType::Bool => field.bits == 1,
+            FieldType::Float => field.bits == 32,
+            FieldType::Unsigned | FieldType::Signed => field.bits > 0 && field.bits <= 32,
+        };
+        if !valid_size {
+            return Err(format!("{} {:#06X}: field {} can't be a {:?} of {} bits", kind, id, field.name, field.type_, field.bits));
+        }
+        if field.offset as usize + field.bits as usize > used.len() {
+            return Err(format!("{} {:#06X}: field {} goes past the {} bits of the object", kind, id, field.name, used.len()));
+        }
+
+        for bit in field.offset..(field.offset + field.bits) {
+            if used[bit as usize] {
+                return Err(format!("{} {:#06X}: field {} overlaps another field at bit {}", kind, id, field.name, bit));
+            }
+            used[bit as usize] = true;
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn decode(layout: &[FieldLayout], payload: &[u8]) -> Fields {
+    let mut fields = Fields::new();
+
+    for field in layout {
+        let raw = get_bits(payload, field.offset, field.bits);
+        let value = match field.type_ {
+            FieldType::Unsigned => FieldValue::Integer(raw as i64),
+            FieldType::Signed => FieldValue::Integer(((raw << (32 - field.bits)) as i32 >> (32 - field.bits)) as i64),
+            FieldType::Bool => FieldValue::Bool(raw != 0),
+            FieldType::Float if f32::from_bits(raw).is_nan() => FieldValue::Bits(format!("{:#010X}", raw)),
+            FieldType::Float => FieldValue::Float(f32::from_bits(raw)),
+        };
+        fields.insert(field.name.clone(), value);
+    }
+
+    fields
+}
+
+// the bits that aren't covered by a field are kept as they are
+pub fn encode(layout: &[FieldLayout], fields: &Fields, payload: &mut [u8]) -> Result<(), String> {
+    for name in fields.keys() {
+        if !layout.iter().any(|field| &field.name == name) {
+            return Err(format!("unknown field {}", name));
+        }
+    }
+
+    for field in layout {
+        let value = match fields.get(&field.name) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let raw = match (field.type_, value) {
+            (FieldType::Unsigned, FieldValue::Integer(value)) => {
+                if *value < 0 || *value >= (1i64 << field.bits) {
+                    return Err(format!("{} = {} doesn't fit in {} unsigned bits", field.name, value, field.bits));
+                }
+                *value as u32
+            },
+            (FieldType::Signed, FieldValue::Integer(value)) => {
+                let limit = 1i64 << (field.bits - 1);
+                if *value < -limit || *value >= limit {
+                    return Err(format!("{} = {} doesn't fit in {} signed bits", field.name, value, field.bits));
+                }
+                (*value as u32) & (u32::MAX >> (32 - field.bits))
+            },
+            (FieldType::Bool, FieldValue::Bool(value)) => *value as u32,
+            (FieldType::Float, FieldValue::Float(value)) => value.to_bits(),
+            (FieldType::Float, FieldValue::Integer(value)) => (*value as f32).to_bits(),
+            (FieldType::Float, FieldValue::Bits(bits)) => {
+                let digits = bits.strip_prefix("0x").or_else(|| bits.strip_prefix("0X"))
+                    .ok_or_else(|| format!("{} = {} isn't hexadecimal bits", field.name, bits))?;
+                u32::from_str_radix(digits, 16).map_err(|_| format!("{} = {} isn't 32 hexadecimal bits", field.name, bits))?
+            },
+            (type_, value) => return Err(format!("{} = {:?} isn't a {:?}", field.name, value, type_)),
+        };
+
+        set_bits(payload, field.offset, field.bits, raw);
+    }
+
+    Ok(())
+}
+
+fn get_bits(payload: &[u8], offset: u32, bits: u32) -> u32 {
+    let mut value = 0;
+    for bit in offset..(offset + bits) {
+        let byte = payload[(bit / 8) as usize];
+        value = (value << 1) | (((byte >> (7 - bit % 8)) & 1) as u32);
+    }
+    value
+}
+
+fn set_bits(payload: &mut [u8], offset: u32, bits: u32, value: u32) {
+    for (i, bit) in (offset..(offset + bits)).enumerate() {
+        let set = (value >> (bits - 1 - i as u32)) & 1;
+        let byte = &mut payload[(bit / 8) as usize];
+        let mask = 1 << (7 - bit % 8);
+        *byte = if set != 0 { *byte | mask } else { *byte & !mask };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, offset: u32, bits: u32, type_: FieldType) -> FieldLayout {
+        FieldLayout { name: name.to_string(), offset, bits, type_ }
+    }
+
+    fn load(yaml: &str) -> io::Result<ObjectLayouts> {
+        let filename = std::env::temp_dir().join(format!("bk_level_{}_layouts.yaml", std::process::id()));
+        std::fs::write(&filename, yaml).unwrap();
+        let layouts = ObjectLayouts::load(filename.to_str());
+        std::fs::remove_file(&filename).unwrap();
+        layouts
+    }
+
+    #[test]
+    fn layouts() {
+        let layouts = load("complex:\n  0x1F5:\n    - { name: speed, offset: 16, bits: 12, type: signed }\n").unwrap();
+        assert_eq!(layouts.complex[&0x1F5][0].bits, 12);
+        assert!(layouts.small.is_empty());
+        assert!(ObjectLayouts::load(None).unwrap().complex.is_empty());
+
+        let error = ObjectLayouts::load(Some("/nonexistent/layouts.yaml")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("/nonexistent/layouts.yaml: "));
+
+        let errors = [
+            ("complex: [", "invalid type"),
+            ("small: {", ""),
+            ("small:\n  1:\n    - { name: flag, offset: 0, bits: 2, type: bool }\n", "small 0x0001: field flag can't be a Bool of 2 bits"),
+            ("small:\n  1:\n    - { name: f, offset: 0, bits: 16, type: float }\n", "small 0x0001: field f can't be a Float of 16 bits"),
+            ("small:\n  1:\n    - { name: n, offset: 0, bits: 0, type: unsigned }\n", "small 0x0001: field n can't be a Unsigned of 0 bits"),
+            ("complex:\n  2:\n    - { name: n, offset: 60, bits: 8, type: unsigned }\n", "complex 0x0002: field n goes past the 64 bits of the object"),
+            ("complex:\n  2:\n    - { name: n, offset: 4294967295, bits: 8, type: unsigned }\n", "complex 0x0002: field n goes past the 64 bits of the object"),
+            ("small:\n  3:\n    - { name: a, offset: 0, bits: 12, type: unsigned }\n    - { name: b, offset: 8, bits: 8, type: unsigned }\n",
+                "small 0x0003: field b overlaps another field at bit 8"),
+        ];
+        for (yaml, message) in errors {
+            let error = load(yaml).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn bits() {
+        let payload = [0b1011_0110, 0b0101_1100, 0xFF, 0x00];
+        assert_eq!(get_bits(&payload, 0, 1), 1);
+        assert_eq!(get_bits(&payload, 1, 3), 0b011);
+        assert_eq!(get_bits(&payload, 5, 6), 0b110_010);
+        assert_eq!(get_bits(&payload, 12, 12), 0b1100_1111_1111);
+        assert_eq!(get_bits(&payload, 0, 32), 0xB65CFF00);
+
+        let mut payload = [0u8; 4];
+        set_bits(&mut payload, 5, 6, 0b111_111);
+        assert_eq!(payload, [0b0000_0111, 0b1110_0000, 0, 0]);
+        set_bits(&mut payload, 6, 3, 0);
+        assert_eq!(payload, [0b0000_0100, 0b0110_0000, 0, 0]);
+        set_bits(&mut payload, 0, 32, 0x12345678);
+        assert_eq!(payload, [0x12, 0x34, 0x56, 0x78]);
+    }
+
+    #[test]
+    fn fields() {
+        let layout = [
+            field("signed", 3, 5, FieldType::Signed),
+            field("flag", 8, 1, FieldType::Bool),
+            field("unsigned", 9, 11, FieldType::Unsigned),
+            field("float", 24, 32, FieldType::Float),
+        ];
+        let mut payload = [0xA5; 10];
+        let original = payload;
+
+        let mut fields = Fields::new();
+        fields.insert("signed".to_string(), FieldValue::Integer(-16));
+        fields.insert("flag".to_string(), FieldValue::Bool(false));
+        fields.insert("unsigned".to_string(), FieldValue::Integer(0x7FF));
+        fields.insert("float".to_string(), FieldValue::Float(-1.5));
+        encode(&layout, &fields, &mut payload).unwrap();
+        assert_eq!(decode(&layout, &payload), fields);
+        assert_eq!(&payload[3..7], (-1.5f32).to_bits().to_be_bytes());
+
+        // the bits outside of the fields are unchanged
+        let covered = [0b0001_1111u8, 0xFF, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0];
+        for i in 0..payload.len() {
+            assert_eq!(payload[i] & !covered[i], original[i] & !covered[i], "byte {}", i);
+        }
+
+        // limits of the signed and unsigned fields
+        for (name, value) in [("signed", -17), ("signed", 16), ("unsigned", 0x800), ("unsigned", -1)] {
+            let fields = Fields::from([(name.to_string(), FieldValue::Integer(value))]);
+            assert!(encode(&layout, &fields, &mut payload).is_err());
+        }
+        let fields = Fields::from([("signed".to_string(), FieldValue::Integer(15))]);
+        encode(&layout, &fields, &mut payload).unwrap();
+        assert_eq!(decode(&layout, &payload)["signed"], FieldValue::Integer(15));
+
+        // a NaN keeps its payload
+        let nan = f32::from_bits(0x7FC01234);
+        let fields = Fields::from([("float".to_string(), FieldValue::Float(nan))]);
+        encode(&layout, &fields, &mut payload).unwrap();
+        assert_eq!(decode(&layout, &payload)["float"], FieldValue::Bits("0x7FC01234".to_string()));
+        let fields = Fields::from([("float".to_string(), FieldValue::Bits("0x7F800001".to_string()))]);
+        encode(&layout, &fields, &mut payload).unwrap();
+        assert_eq!(&payload[3..7], [0x7F, 0x80, 0x00, 0x01]);
+
+        let fields = Fields::from([("unknown".to_string(), FieldValue::Integer(0))]);
+        assert_eq!(encode(&layout, &fields, &mut payload), Err("unknown field unknown".to_string()));
+    }
+}
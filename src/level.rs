@@ -0,0 +1,80 @@
+use std::fs::File;
+
+use crate::gltf;
+use crate::model::Model;
+use crate::setupfile::SetupFile;
+
+// a whole level: the opaque model A, the translucent model B drawn over it, and the setup file
+pub struct Level {
+    pub model_a: Model,
+    pub model_b: Option<Model>,
+    pub setup: SetupFile,
+}
+
+impl Level {
+    // the files of each part are in its own directory, level.gltf references all of them
+    pub fn write_gltf(&self, output_dir: &str) {
+        let mut root = gltf::Gltf {
+            asset: gltf::Asset {
+                version: "2.0".to_string(),
+                generator: "bk_level".to_string(),
+            },
+            accessors: vec![],
+            animations: vec![],
+            buffers: vec![],
+            buffer_views: vec![],
+            cameras: vec![],
+            images: vec![],
+            materials: vec![],
+            meshes: vec![],
+            nodes: vec![],
+            samplers: vec![],
+            scenes: vec![gltf::Scene {
+                nodes: vec![],
+            }],
+            textures: vec![],
+            extensions_used: vec![],
+            extensions: None,
+        };
+
+        // the lights are overlaid as lights, not baked into the models
+        std::fs::create_dir_all(format!("{}/model_a", output_dir)).unwrap();
+        let model_a = self.model_a.build_gltf(&format!("{}/model_a", output_dir), &[]);
+        add_model(&mut root, model_a, "model A", "model_a/", false);
+
+        // model B is drawn after model A, and blended with it
+        if let Some(model_b) = &self.model_b {
+            std::fs::create_dir_all(format!("{}/model_b", output_dir)).unwrap();
+            let model_b = model_b.build_gltf(&format!("{}/model_b", output_dir), &[]);
+            add_model(&mut root, model_b, "model B", "model_b/", true);
+        }
+
+        // objects, cameras, lights and paths
+        std::fs::create_dir_all(format!("{}/setup", output_dir)).unwrap();
+        let setup = self.setup.build_gltf(&format!("{}/setup", output_dir));
+        let nodes = root.append(setup, "setup/");
+        root.scenes[0].nodes.extend(nodes);
+
+        let writer = File::create(format!("{}/level.gltf", output_dir)).unwrap();
+        serde_json::to_writer_pretty(writer, &root).unwrap();
+    }
+}
+
+fn add_model(root: &mut gltf::Gltf, mut model: gltf::Gltf, name: &str, uri_prefix: &str, translucent: bool) {
+    if translucent {
+        for material in &mut model.materials {
+            if material.alpha_mode == "OPAQUE" {
+                material.alpha_mode = "BLEND".to_string();
+            }
+        }
+    }
+
+    let children = root.append(model, uri_prefix);
+    let index = root.nodes.len();
+    root.scenes[0].nodes.push(index);
+    root.nodes.push(gltf::Node {
+        name: Some(name.to_string()),
+        children,
+        ..Default::default()
+    });
+}
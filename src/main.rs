@@ -3,9 +3,22 @@ use std::path::Path;
 
 mod types;
 mod gltf;
+mod rdp;
+
+mod names;
+use names::ObjectNames;
+
+mod definitions;
+use definitions::ObjectDefinitions;
+
+mod layouts;
+use layouts::ObjectLayouts;
+
+mod diff;
+use diff::SetupDiff;
 
 mod setupfile;
-use setupfile::SetupFile;
+use setupfile::{ SetupFile, FlatSetupFile, Lighting, Severity, VOXEL_SIZE };
 
 mod model;
 use model::Model;
@@ -13,6 +26,18 @@ use model::Model;
 mod anim;
 use anim::Animation;
 
+mod backpack;
+
+mod level;
+use level::Level;
+
+mod rarezip;
+
+mod rom;
+use rom::{ Rom, AssetType };
+
+mod patch;
+
 /// Convert models and level setup files
 #[derive(Parser, Debug)]
 #[command(author = None, version = None, about = None, long_about = None)]
@@ -27,6 +52,86 @@ struct Args {
     /// Output format 
     #[arg(short, long)]
     output: Option<OutputFormat>,
+
+    /// YAML file of object names, overriding the built-in ones
+    #[arg(long)]
+    names: Option<String>,
+
+    /// YAML file classifying object IDs, overriding the built-in classification
+    #[arg(long)]
+    definitions: Option<String>,
+
+    /// List setup file objects with their world coordinates instead of per voxel
+    #[arg(long)]
+    flat: bool,
+
+    /// Omit the missing voxels of setup files
+    #[arg(long)]
+    sparse: bool,
+
+    /// Only check that the setup file can be written back, without converting it
+    #[arg(long)]
+    validate: bool,
+
+    /// Write setup files with links to missing nodes or node ids used twice, reporting them as warnings
+    #[arg(long)]
+    allow_broken_links: bool,
+
+    /// Setup file whose lights are baked into the vertex colours of the model's glTF
+    #[arg(long)]
+    lights: Option<String>,
+
+    /// YAML file describing the fields of unknown objects, by object ID
+    #[arg(long)]
+    layouts: Option<String>,
+
+    /// Compare the file with another one, instead of converting it
+    #[arg(long)]
+    diff: Option<String>,
+
+    /// Print the differences as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Distance beyond which objects and lights aren't matched when comparing setup files, they are reported as removed and added
+    #[arg(long, default_value_t = VOXEL_SIZE as f32)]
+    max_distance: f32,
+
+    /// Distance under which vertices aren't reported as moved when comparing models
+    #[arg(long, default_value_t = 0.0)]
+    tolerance: f32,
+
+    /// Banjo's Backpack tmp/ directory, when extracting the levels of setups.xml [default: <BB>/tmp]
+    #[arg(long)]
+    tmp: Option<String>,
+
+    /// Print the pose of every bone of the animation at this frame, instead of converting it
+    #[arg(long)]
+    frame: Option<f32>,
+
+    /// Name of the glTF animation to import, instead of the first one
+    #[arg(long)]
+    animation: Option<String>,
+
+    /// Write the repacked file in Rare's compressed format, as the assets of the ROM
+    #[arg(long)]
+    compress: bool,
+
+    /// ID of the asset to extract from the ROM, the assets are listed without it
+    #[arg(long, value_parser = rom::parse_number)]
+    asset: Option<usize>,
+
+    /// Offset of the asset table in the ROM, instead of looking for it
+    #[arg(long, value_parser = rom::parse_number)]
+    asset_table: Option<usize>,
+
+    /// File to insert into the ROM as the asset given by --asset
+    #[arg(long)]
+    patch_rom: Option<String>,
+
+    /// Write the patched ROM, or a patch to apply to the original one
+    #[arg(long, default_value = "rom")]
+    patch_format: PatchFormat,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -34,6 +139,14 @@ enum OutputFormat {
     Yaml,
     Gltf,
     Bin,
+    C,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum PatchFormat {
+    Rom,
+    Ips,
+    Bps,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -42,6 +155,10 @@ enum InputFormat {
     Setup,
     Anim,
     Yaml,
+    Backpack,
+    Level,
+    Rom,
+    Gltf,
 }
 
 fn main() {
@@ -53,29 +170,71 @@ fn main() {
 
     let input = if let Some(input) = args.input {
         input
-    } else if filename.ends_with(".lvl_setup.bin") {
-        InputFormat::Setup
+    } else if let Some(input) = detect_format(filename) {
+        input
+    } else {
+        panic!("Can't detect the format. Rename the file to .anim.bin/.model.bin/.lvl_setup.bin or use the --input argument.");
+    };
+
+    if let Some(other) = &args.diff {
+        return diff(filename, other, input, &args);
+    }
+
+    convert(filename, output_name, input, &args);
+}
+
+fn detect_format(filename: &str) -> Option<InputFormat> {
+    if filename.ends_with(".lvl_setup.bin") {
+        Some(InputFormat::Setup)
     } else if filename.ends_with(".model.bin") {
-        InputFormat::Model
+        Some(InputFormat::Model)
     } else if filename.ends_with(".anim.bin") {
-        InputFormat::Anim
+        Some(InputFormat::Anim)
     } else if filename.ends_with(".yaml") {
-        InputFormat::Yaml
+        Some(InputFormat::Yaml)
+    } else if filename.ends_with(".xml") {
+        Some(InputFormat::Backpack)
+    } else if filename.ends_with(".z64") || filename.ends_with(".n64") || filename.ends_with(".v64") {
+        Some(InputFormat::Rom)
+    } else if filename.ends_with(".gltf") || filename.ends_with(".glb") {
+        Some(InputFormat::Gltf)
     } else {
-        panic!("Can't detect the format. Rename the file to .anim.bin/.model.bin/.lvl_setup.bin or use the --input argument.");
-    };
+        None
+    }
+}
 
+// the outputs are named after output_name, which can include a directory
+fn convert(filename: &str, output_name: &str, input: InputFormat, args: &Args) {
     match input {
         InputFormat::Setup => {
-            if let Some(format) = args.output {
-                if format != OutputFormat::Yaml {
-                    panic!("level setup files can only be converted to YAML.");
-                }
-            }
+            match SetupFile::read_bin(filename, &ObjectDefinitions::load(args.definitions.as_deref())) {
+                Ok(mut file) => {
+                    if args.validate {
+                        return validate(&file, args.allow_broken_links);
+                    }
 
-            let output_name = format!("{}.yaml", output_name);
-            match SetupFile::read_bin(filename) {
-                Ok(file) => file.write_yaml(&output_name),
+                    file.fill_names(&ObjectNames::load(args.names.as_deref()));
+                    file.decode_layouts(&ObjectLayouts::load(args.layouts.as_deref()).unwrap());
+                    let format = if let Some(format) = args.output { format } else { OutputFormat::Yaml };
+                    match format {
+                        OutputFormat::Yaml => {
+                            let output_name = format!("{}.yaml", output_name);
+                            if args.flat {
+                                FlatSetupFile::from_setup(file, VOXEL_SIZE).write_yaml(&output_name);
+                            } else {
+                                if args.sparse {
+                                    file.make_sparse();
+                                }
+                                file.write_yaml(&output_name);
+                            }
+                        },
+                        OutputFormat::Gltf => {
+                            std::fs::create_dir_all(output_name).unwrap();
+                            file.write_gltf(output_name);
+                        },
+                        _ => panic!("level setup files can only be converted to YAML or glTF."),
+                    };
+                },
                 Err(e) => panic!("{:?}", e)
             };
         },
@@ -90,7 +249,12 @@ fn main() {
                         },
                         OutputFormat::Gltf => {
                             std::fs::create_dir_all(output_name).unwrap();
-                            model.write_gltf(&output_name);
+                            model.write_gltf(&output_name, &read_lights(args));
+                        },
+                        OutputFormat::C => {
+                            let symbol = Path::new(output_name).file_name().unwrap().to_str().unwrap();
+                            let symbol = symbol.strip_suffix(".model").unwrap_or(symbol);
+                            model.write_c(&format!("{}.c", output_name), symbol).unwrap();
                         },
                         OutputFormat::Bin => panic!("Why would you want to convert .bin to .bin?"),
                     };
@@ -101,6 +265,11 @@ fn main() {
         InputFormat::Anim => {
             match Animation::read_bin(filename) {
                 Ok(anim) => {
+                    if let Some(frame) = args.frame {
+                        print!("{}", serde_yaml::to_string(&anim.sample(frame)).unwrap());
+                        return;
+                    }
+
                     let format = if let Some(format) = args.output { format } else { OutputFormat::Yaml };
                     match format {
                         OutputFormat::Yaml => {
@@ -111,21 +280,49 @@ fn main() {
                             std::fs::create_dir_all(output_name).unwrap();
                             anim.write_gltf(&output_name);
                         },
+                        OutputFormat::C => panic!("Can't convert animation to C"),
                         OutputFormat::Bin => panic!("Why would you want to convert .bin to .bin?"),
                     };
                 },
                 Err(e) => panic!("{:?}", e),
             }
         },
+        InputFormat::Gltf => {
+            let anim = Animation::read_gltf(filename, args.animation.as_deref()).unwrap();
+            let output_name = output_name.strip_suffix(".anim").unwrap_or(output_name);
+            let format = if let Some(format) = args.output { format } else { OutputFormat::Bin };
+            match format {
+                OutputFormat::Bin => anim.write_bin(&format!("{}.anim.bin", output_name)).unwrap(),
+                OutputFormat::Yaml => anim.write_yaml(&format!("{}.anim.yaml", output_name)),
+                _ => panic!("glTF animations can only be converted to bin or yaml."),
+            };
+        },
         InputFormat::Yaml => {
-            if let Some(setupfile) = SetupFile::read_yaml(filename) {
+            let setupfile = SetupFile::read_yaml(filename)
+                .or_else(|| FlatSetupFile::read_yaml(filename).map(|flat| flat.into_setup()));
+
+            if let Some(mut setupfile) = setupfile {
+                setupfile.encode_layouts(&ObjectLayouts::load(args.layouts.as_deref()).unwrap()).unwrap();
+
+                if args.validate {
+                    return validate(&setupfile, args.allow_broken_links);
+                }
+
                 let format = if let Some(format) = args.output { format } else { OutputFormat::Bin };
                 match format {
                     OutputFormat::Bin => {
                         let output_name = format!("{}_repack.bin", output_name);
-                        setupfile.write_bin(&output_name).unwrap();
+                        setupfile.check_names(&ObjectNames::load(args.names.as_deref()));
+                        setupfile.write_bin(&output_name, args.allow_broken_links).unwrap();
+                        if args.compress {
+                            compress_file(&output_name);
+                        }
+                    },
+                    OutputFormat::Gltf => {
+                        std::fs::create_dir_all(output_name).unwrap();
+                        setupfile.write_gltf(output_name);
                     },
-                    OutputFormat::Gltf => panic!("Can't convert setup file to .gltf"),
+                    OutputFormat::C => panic!("Can't convert setup file to C"),
                     OutputFormat::Yaml => panic!("Why would you want to convert .yaml to .yaml?"),
                 };
             } else if let Some(model) = Model::read_yaml(filename) {
@@ -134,9 +331,17 @@ fn main() {
                     OutputFormat::Bin => {
                         let output_name = format!("{}_repack.bin", output_name);
                         model.write_bin(&output_name).unwrap();
+                        if args.compress {
+                            compress_file(&output_name);
+                        }
                     },
                     OutputFormat::Gltf => {
-                        model.write_gltf(&output_name);
+                        model.write_gltf(&output_name, &read_lights(args));
+                    },
+                    OutputFormat::C => {
+                        let symbol = Path::new(output_name).file_name().unwrap().to_str().unwrap();
+                        let symbol = symbol.strip_suffix(".model").unwrap_or(symbol);
+                        model.write_c(&format!("{}.c", output_name), symbol).unwrap();
                     },
                     OutputFormat::Yaml => panic!("Why would you want to convert .yaml to .yaml?"),
                 };
@@ -144,5 +349,194 @@ fn main() {
                 panic!("{} is not a valid YAML file.", filename);
             }
         },
+        InputFormat::Backpack => extract_levels(filename, args),
+        InputFormat::Level => {
+            let format = if let Some(format) = args.output { format } else { OutputFormat::Gltf };
+            if format != OutputFormat::Gltf {
+                panic!("levels can only be converted to glTF.");
+            }
+
+            let output_dir = format!("{}.level", output_name);
+            std::fs::create_dir_all(&output_dir).unwrap();
+            read_level(filename, args).write_gltf(&output_dir);
+        },
+        InputFormat::Rom => {
+            let rom = Rom::read(filename, args.asset_table).unwrap();
+            match (args.asset, &args.patch_rom) {
+                (Some(id), Some(asset)) => patch_rom(rom, id, asset, filename, output_name, args.patch_format),
+                (Some(id), None) => extract_asset(&rom, id, output_name, args),
+                (None, Some(_)) => panic!("--patch-rom needs the ID of the asset to replace, with --asset."),
+                (None, None) => list_assets(&rom),
+            }
+        },
+    };
+}
+
+// the asset is written as <rom>_<id>.model.bin, .lvl_setup.bin, .anim.bin or .bin, and converted
+fn extract_asset(rom: &Rom, id: usize, output_name: &str, args: &Args) {
+    let data = rom.asset(id).unwrap();
+    let asset_type = AssetType::detect(&data);
+    let filename = format!("{}_{:04X}.{}", output_name, id, asset_type.extension());
+    std::fs::write(&filename, &data).unwrap();
+    println!("{}", filename);
+
+    if asset_type != AssetType::Unknown {
+        let output_name = filename.strip_suffix(".bin").unwrap();
+        convert(&filename, output_name, detect_format(&filename).unwrap(), args);
+    }
+}
+
+// the patch is made against the original ROM, in big-endian (.z64) byte order
+fn patch_rom(mut rom: Rom, id: usize, asset: &str, filename: &str, output_name: &str, format: PatchFormat) {
+    let original = rom.data.clone();
+    rom.replace_asset(id, &std::fs::read(asset).unwrap()).unwrap();
+    rom.update_checksum().unwrap();
+
+    let output_name = match format {
+        PatchFormat::Rom => {
+            let extension = Path::new(filename).extension().unwrap().to_str().unwrap();
+            let output_name = format!("{}_patched.{}", output_name, extension);
+            rom.write(&output_name).unwrap();
+            output_name
+        },
+        PatchFormat::Ips | PatchFormat::Bps => {
+            let (patch, extension) = if format == PatchFormat::Ips {
+                (patch::ips(&original, &rom.data).unwrap(), "ips")
+            } else {
+                (patch::bps(&original, &rom.data), "bps")
+            };
+            let output_name = format!("{}.{}", output_name, extension);
+            std::fs::write(&output_name, patch).unwrap();
+            output_name
+        },
+    };
+    println!("{}", output_name);
+}
+
+fn list_assets(rom: &Rom) {
+    println!("asset table at {:#X}", rom.table_offset);
+    for (id, entry) in rom.assets.iter().enumerate() {
+        if entry.size == 0 {
+            continue;
+        }
+        let asset_type = match rom.asset(id) {
+            Ok(data) => format!("{:?}", AssetType::detect(&data)),
+            Err(e) => format!("error: {}", e),
+        };
+        println!("{:#06X} at {:#08X}, {:#X} bytes{}, flags {:#X}: {}", id, entry.offset, entry.size,
+            if entry.compressed { " compressed" } else { "" }, entry.flags, asset_type);
+    }
+}
+
+// a level is named after its files: <level>_a.model.bin, <level>_b.model.bin (optional) and <level>.lvl_setup.bin
+fn read_level(level: &str, args: &Args) -> Level {
+    let model_a = Model::read_bin(&format!("{}_a.model.bin", level)).unwrap();
+    let model_b = format!("{}_b.model.bin", level);
+    let model_b = if Path::new(&model_b).exists() { Some(Model::read_bin(&model_b).unwrap()) } else { None };
+
+    let mut setup = read_setup(&format!("{}.lvl_setup.bin", level), args);
+    setup.fill_names(&ObjectNames::load(args.names.as_deref()));
+    setup.decode_layouts(&ObjectLayouts::load(args.layouts.as_deref()).unwrap());
+
+    Level { model_a, model_b, setup }
+}
+
+// every level of setups.xml is copied from BB's tmp/ directory to levels/, and converted
+fn extract_levels(setups_xml: &str, args: &Args) {
+    if matches!(args.output, Some(OutputFormat::Bin) | Some(OutputFormat::C)) {
+        panic!("levels can only be converted to YAML or glTF.");
+    }
+
+    let tmp_dir = match &args.tmp {
+        Some(tmp_dir) => tmp_dir.clone(),
+        None => backpack::default_tmp_dir(setups_xml).unwrap(),
     };
+    let levels = backpack::read_levels(setups_xml, &tmp_dir).unwrap();
+
+    let output_dir = "levels";
+    std::fs::create_dir_all(output_dir).unwrap();
+    for level in &levels {
+        println!("{}", level.name);
+        for filename in level.extract(output_dir).unwrap() {
+            let output_name = filename.strip_suffix(".bin").unwrap();
+            convert(&filename, output_name, detect_format(&filename).unwrap(), args);
+        }
+
+        // and the whole level in one scene
+        if args.output == Some(OutputFormat::Gltf) && level.model_a.is_some() && level.setup.is_some() {
+            let name = format!("{}/{}", output_dir, level.name);
+            convert(&name, &name, InputFormat::Level, args);
+        }
+    }
+}
+
+fn compress_file(filename: &str) {
+    let data = std::fs::read(filename).unwrap();
+    std::fs::write(filename, rarezip::compress(&data).unwrap()).unwrap();
+}
+
+fn validate(setupfile: &SetupFile, allow_broken_links: bool) {
+    let issues = setupfile.validate(allow_broken_links);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        std::process::exit(1);
+    } else if issues.is_empty() {
+        println!("no issues found");
+    }
+}
+
+// a setup file is read from its binary or YAML (regular or flat) version
+fn read_setup(filename: &str, args: &Args) -> SetupFile {
+    if filename.ends_with(".yaml") {
+        SetupFile::read_yaml(filename)
+            .or_else(|| FlatSetupFile::read_yaml(filename).map(|flat| flat.into_setup()))
+            .unwrap_or_else(|| panic!("{} is not a valid setup file.", filename))
+    } else {
+        SetupFile::read_bin(filename, &ObjectDefinitions::load(args.definitions.as_deref())).unwrap()
+    }
+}
+
+fn read_lights(args: &Args) -> Vec<Lighting> {
+    match &args.lights {
+        Some(filename) => read_setup(filename, args).lightings,
+        None => vec![],
+    }
+}
+
+fn diff(filename: &str, other: &str, input: InputFormat, args: &Args) {
+    match input {
+        InputFormat::Setup | InputFormat::Yaml => {
+            let names = ObjectNames::load(args.names.as_deref());
+            let mut old = read_setup(filename, args);
+            let mut new = read_setup(other, args);
+            old.fill_names(&names);
+            new.fill_names(&names);
+
+            let diff = SetupDiff::new(&old, &new, args.max_distance);
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+            } else if diff.is_empty() {
+                println!("no differences");
+            } else {
+                print!("{}", diff);
+            }
+        },
+        InputFormat::Model => {
+            let old = Model::read_bin(filename).unwrap();
+            let new = Model::read_bin(other).unwrap();
+
+            let diff = old.diff(&new, args.tolerance);
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+            } else if diff.is_empty() {
+                println!("no differences");
+            } else {
+                print!("{}", diff);
+            }
+        },
+        InputFormat::Anim | InputFormat::Backpack | InputFormat::Level | InputFormat::Rom | InputFormat::Gltf => panic!("Only setup files and models can be compared."),
+    }
 }
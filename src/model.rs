@@ -7,6 +7,9 @@
 
 use crate::types::*;
 use crate::gltf;
+use crate::rdp::*;
+use crate::setupfile::Lighting;
+use crate::diff::{ ModelDiff, Change, ChangeKind, FieldChange, diff_values, diff_lines };
 use std::collections::HashMap;
 use image::RgbaImage;
 use byteorder::{ ReadBytesExt, BigEndian, WriteBytesExt, LittleEndian };
@@ -19,6 +22,9 @@ use std::io::Read;
 use std::io::Write;
 use hex::ToHex;
 
+// position, uv, colour and normal
+const GLTF_VERTEX_SIZE: usize = 36;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Model {
     textures: Vec<Texture>,
@@ -67,6 +73,9 @@ pub struct Vertex {
     g: u8,
     b: u8,
     a: u8,
+    // for lit geometry, written into r/g/b when reading the YAML
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normal: Option<[f32; 3]>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -94,7 +103,41 @@ pub struct Texture {
     raw: String,
 }
 
+impl Vertex {
+    // with G_LIGHTING, r/g/b hold a signed normal instead of a colour. None when it's all zeros
+    fn normal(&self) -> Option<[f32; 3]> {
+        unit([self.r as i8 as f32, self.g as i8 as f32, self.b as i8 as f32])
+    }
+
+    // the unit vector scaled to -127..127, all zeros when there's no direction
+    fn set_normal(&mut self, normal: [f32; 3]) {
+        let [x, y, z] = unit(normal).unwrap_or([0.0; 3]).map(|c| (c * 127.0).round() as i8 as u8);
+        (self.r, self.g, self.b) = (x, y, z);
+    }
+}
+
+fn unit(vector: [f32; 3]) -> Option<[f32; 3]> {
+    let length = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if length == 0.0 {
+        return None;
+    }
+    Some([vector[0] / length, vector[1] / length, vector[2] / length])
+}
+
 impl Texture {
+    // the pixels are compared by their CRC32, which is the same across runs and builds
+    fn summary(&self) -> serde_json::Value {
+        let mut crc = flate2::Crc::new();
+        crc.update(self.raw.as_bytes());
+
+        serde_json::json!({
+            "format": self.format,
+            "width": self.width,
+            "height": self.height,
+            "hash": format!("{:08x}", crc.sum()),
+        })
+    }
+
     fn new() -> Self {
         Self {
             offset: 0,
@@ -130,6 +173,16 @@ pub struct TriColl {
     flags: u32,
 }
 
+impl TriColl {
+    fn vertices(&self) -> [u16; 3] {
+        [self.vtx_indx_1, self.vtx_indx_2, self.vtx_indx_3]
+    }
+
+    fn label(&self) -> String {
+        format!("triangle ({}, {}, {}) flags {:#X}", self.vtx_indx_1, self.vtx_indx_2, self.vtx_indx_3, self.flags)
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Collisions {
     min: Vector3<i16>,
@@ -226,19 +279,29 @@ pub enum TextureFormat {
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum F3dex {
     SPNoOp,
+    Matrix { projection: bool, load: bool, push: bool, size: u16, address: u32 },
+    MoveMem { index: u8, size: u16, address: u32 },
     Vertex { index: u16, count: u8, address: u32 },
     DisplayList { store_ra: bool, address: u32 },
+    BranchZ { vertex: u16, z: u32 },
     Triangle2 { v1: u8, v2: u8, v3: u8, v4: u8, v5: u8, v6: u8 },
+    ModifyVertex { vertex: u16, location: u8, value: u32 },
+    RdpHalf2(u32),
+    RdpHalf1(u32),
     ClearGeometryMode(u32),
     SetGeometryMode(u32),
     EndDisplayList,
-    SetOtherModeL { amount: u8, count: u8, mode: u32 }, // to improve
-    SetOtherModeH { amount: u8, count: u8, mode: u32 }, // to improve
+    SetOtherModeL { shift: u8, length: u8, mode: OtherModeL },
+    SetOtherModeH { shift: u8, length: u8, mode: OtherModeH },
     Texture { mipmaps: u8, descriptor: u8, enable: bool, scalex: f32, scaley: f32 },
+    MoveWord { index: u8, offset: u16, value: u32 },
     PopMatrix { unk1: u8, unk2: u8, unk3: u8, count: u32 },
+    CullDisplayList { vstart: u16, vend: u16 },
     Triangle1 { v1: u8, v2: u8, v3: u8 },
     RdpLoadSync,
     RdpPipeSync,
+    RdpTileSync,
+    RdpFullSync,
     LoadTlut { descriptor: u8, colour_count: u16 },
     SetTileSize { upper_left_s: u16, upper_left_t: u16, descriptor: u8, width: u16, height: u16 },
     LoadBlock { upper_left_s: u16, upper_left_t: u16, descriptor: u8, texels_count: u16, dxt: u16 },
@@ -246,7 +309,12 @@ pub enum F3dex {
     SetTile { format: ColourFormat, depth: u8, values_per_row: u16,
         tmem_offset: u16, descriptor: u8, palette: u8,
         clamp_mirror: Vector2<u8>, unwrapped: Vector2<u8>, perspective_div: Vector2<u8> },
-    SetCombine { unk1: u8, unk2: u16, unk3: u32 },
+    SetFillColor(u32),
+    SetFogColor { r: u8, g: u8, b: u8, a: u8 },
+    SetBlendColor { r: u8, g: u8, b: u8, a: u8 },
+    SetPrimColor { min_level: u8, lod_fraction: u8, r: u8, g: u8, b: u8, a: u8 },
+    SetEnvColor { r: u8, g: u8, b: u8, a: u8 },
+    SetCombine(Combiner),
     SettImg { format: ColourFormat, depth: u8, address: u32 },
 }
 
@@ -284,19 +352,19 @@ impl Model {
         let unk28 = f.read_u32::<BigEndian>()?;
         let animated_textures_offset = f.read_u32::<BigEndian>()?;
 
-        println!("===============================");
-        println!("texture_setup_offset {:#X}", texture_setup_offset);
-        println!("display_list_setup_offset {:#X}", display_list_setup_offset);
-        println!("vertex_store_setup_offset {:#X}", vertex_store_setup_offset);
-        println!("unk14_offset {:#X}", unk14_offset);
-        println!("collision_setup {:#X}", collision_setup);
-        println!("effects_setup {:#X}", effects_setup);
-        println!("unk28 {:#X}", unk28);
-        println!("animation_setup {:#X}", animation_setup);
-        println!("unk20 {:#X}", unk20); // order not sure
-        println!("animated_textures_offset {:#X}", animated_textures_offset);
-        println!("geometry_offset {:#X}", geometry_offset);
-        println!("===============================");
+        eprintln!("===============================");
+        eprintln!("texture_setup_offset {:#X}", texture_setup_offset);
+        eprintln!("display_list_setup_offset {:#X}", display_list_setup_offset);
+        eprintln!("vertex_store_setup_offset {:#X}", vertex_store_setup_offset);
+        eprintln!("unk14_offset {:#X}", unk14_offset);
+        eprintln!("collision_setup {:#X}", collision_setup);
+        eprintln!("effects_setup {:#X}", effects_setup);
+        eprintln!("unk28 {:#X}", unk28);
+        eprintln!("animation_setup {:#X}", animation_setup);
+        eprintln!("unk20 {:#X}", unk20); // order not sure
+        eprintln!("animated_textures_offset {:#X}", animated_textures_offset);
+        eprintln!("geometry_offset {:#X}", geometry_offset);
+        eprintln!("===============================");
         
         let unk30 = f.read_u16::<BigEndian>()?;
         let vertices_count = f.read_u16::<BigEndian>()?;
@@ -402,7 +470,7 @@ impl Model {
             };
 
             vertices.push(Vertex {
-                position, flag, uv, r, g, b, a
+                position, flag, uv, r, g, b, a, normal: None
             });
         }
 
@@ -664,7 +732,14 @@ impl Model {
         let f = File::open(filename).expect(&format!("Can't open {}", filename));
         let ret: Result<Self, serde_yaml::Error> = serde_yaml::from_reader(f);
         match ret {
-            Ok(file) => Some(file),
+            Ok(mut file) => {
+                for vertex in &mut file.vertex_data.vertices {
+                    if let Some(normal) = vertex.normal.take() {
+                        vertex.set_normal(normal);
+                    }
+                }
+                Some(file)
+            },
             Err(_) => None,
         }
     }
@@ -1017,7 +1092,15 @@ impl Model {
         texture_output_path
     }
 
-    pub fn write_gltf(&self, output_dir: &str) {
+    // the lights are baked into the vertex colours, to preview the level lighting
+    pub fn write_gltf(&self, output_dir: &str, lights: &[Lighting]) {
+        let root = self.build_gltf(output_dir, lights);
+        let writer = File::create(format!("{}/model.gltf", output_dir)).unwrap();
+        serde_json::to_writer_pretty(writer, &root).unwrap();
+    }
+
+    // the textures and the buffer are written to output_dir, the document referencing them is returned
+    pub fn build_gltf(&self, output_dir: &str, lights: &[Lighting]) -> gltf::Gltf {
         let mut root = gltf::Gltf {
             asset: gltf::Asset {
                 version: "2.0".to_string(),
@@ -1027,6 +1110,7 @@ impl Model {
             animations: vec![],
             buffers: vec![],
             buffer_views: vec![],
+            cameras: vec![],
             images: vec![],
             materials: vec![],
             meshes: vec![gltf::Mesh {
@@ -1034,8 +1118,7 @@ impl Model {
             }],
             nodes: vec![gltf::Node {
                 mesh: Some(0),
-                children: vec![],
-                translation: None,
+                ..Default::default()
             }],
             samplers: vec![gltf::Sampler {
                 mag_filter: 9728,
@@ -1047,6 +1130,8 @@ impl Model {
                 nodes: vec![0],
             }],
             textures: vec![],
+            extensions_used: vec![],
+            extensions: None,
         };
 
         for texture in &self.textures {
@@ -1060,31 +1145,6 @@ impl Model {
             root.images.push(gltf::Image {
                 uri: filename,
             });
-
-            root.materials.push(gltf::Material {
-                pbr_metallic_roughness: gltf::PbrMetallicRoughness {
-                    base_color_texture: gltf::TextureInfo {
-                        index: root.textures.len() - 1,
-                        extensions: {
-                            let mut map = HashMap::new();
-                            map.insert("KHR_texture_transform".to_string(), gltf::Extension::KhrTextureTransform {
-                                scale: [20.0, 20.0],
-                                offset: [0.0, 0.0],
-                                rotation: 0.0,
-                            });
-                            map
-                        },
-                    },
-                    metallic_factor: 0.0,
-                },
-                alpha_mode: match texture.format {
-                    TextureFormat::Rgba16
-                    | TextureFormat::Rgba32
-                    | TextureFormat::C4
-                    | TextureFormat::C8  => "BLEND".to_string(),
-                    TextureFormat::IA8 => "OPAQUE".to_string(),
-                },
-            });
         }
 
         let min = &self.vertex_data.min_coord;
@@ -1094,36 +1154,165 @@ impl Model {
 
         let mut cache_vtx = [0u32; 32usize];
 
-        fn write_vertex_vector(s: &Model, triangle_vertices: &mut Vec<u8>, face: &Vector3<usize>) {
+        fn write_vertex_vector(s: &Model, triangle_vertices: &mut Vec<u8>, face: &Vector3<usize>, lights: &[Lighting]) {
+            // glTF normals must be unit vectors, the face normal is used for the zero ones
+            let corners = [face.x, face.y, face.z].map(|i| {
+                let p = &s.vertex_data.vertices[i].position;
+                [p.x as f32, p.y as f32, p.z as f32]
+            });
+            let u = [0, 1, 2].map(|i| corners[1][i] - corners[0][i]);
+            let w = [0, 1, 2].map(|i| corners[2][i] - corners[0][i]);
+            let face_normal = unit([u[1] * w[2] - u[2] * w[1], u[2] * w[0] - u[0] * w[2], u[0] * w[1] - u[1] * w[0]])
+                .unwrap_or([0.0, 1.0, 0.0]);
+
             let v = &s.vertex_data.vertices[ face.x as usize ];
-            write_vertex(triangle_vertices, &v);
+            write_vertex(triangle_vertices, &v, lights, face_normal);
             let v = &s.vertex_data.vertices[ face.y as usize ];
-            write_vertex(triangle_vertices, &v);
+            write_vertex(triangle_vertices, &v, lights, face_normal);
             let v = &s.vertex_data.vertices[ face.z as usize ];
-            write_vertex(triangle_vertices, &v);
+            write_vertex(triangle_vertices, &v, lights, face_normal);
         }
 
-        fn write_vertex(triangle_vertices: &mut Vec<u8>, v: &Vertex) {
-            triangle_vertices.append(&mut (v.position.x as f32).to_le_bytes().to_vec());
-            triangle_vertices.append(&mut (v.position.y as f32).to_le_bytes().to_vec());
-            triangle_vertices.append(&mut (v.position.z as f32).to_le_bytes().to_vec());
+        fn write_vertex(triangle_vertices: &mut Vec<u8>, v: &Vertex, lights: &[Lighting], face_normal: [f32; 3]) {
+            let position = [v.position.x as f32, v.position.y as f32, v.position.z as f32];
+            let mut colour = [v.r as f32, v.g as f32, v.b as f32];
+            for light in lights {
+                let added = light.light_at(position);
+                for i in 0..3 {
+                    colour[i] += added[i] * 255.0;
+                }
+            }
+
+            triangle_vertices.append(&mut position[0].to_le_bytes().to_vec());
+            triangle_vertices.append(&mut position[1].to_le_bytes().to_vec());
+            triangle_vertices.append(&mut position[2].to_le_bytes().to_vec());
             triangle_vertices.append(&mut (v.uv.x as f32).to_le_bytes().to_vec());
             triangle_vertices.append(&mut (v.uv.y as f32).to_le_bytes().to_vec());
-            triangle_vertices.push(v.r);
-            triangle_vertices.push(v.g);
-            triangle_vertices.push(v.b);
+            triangle_vertices.push(colour[0].min(255.0) as u8);
+            triangle_vertices.push(colour[1].min(255.0) as u8);
+            triangle_vertices.push(colour[2].min(255.0) as u8);
             triangle_vertices.push(v.a);
+
+            let normal = v.normal().unwrap_or(face_normal);
+            triangle_vertices.append(&mut normal[0].to_le_bytes().to_vec());
+            triangle_vertices.append(&mut normal[1].to_le_bytes().to_vec());
+            triangle_vertices.append(&mut normal[2].to_le_bytes().to_vec());
         }
 
         let mut triangle_vertices_len = 0usize;
         let mut triangle_vertices = Vec::new();
 
-        let mut current_texture = 999999usize;
+        let mut current_texture = None;
+        let mut render_state = RenderState::default();
+        let mut materials = HashMap::new();
+        let mut current_material = None;
         let mut buffer_view_start = 0usize;
         let mut buffer_view_count = vec![];
 
         for cmd in &self.commands {
             println!("{:?}", cmd);
+
+            // a new primitive is started every time triangles are drawn with a different material
+            if matches!(cmd, F3dex::Triangle1 { .. } | F3dex::Triangle2 { .. }) {
+                let material = render_state.material(current_texture, &self.textures);
+
+                if current_material != Some(material) {
+                    current_material = Some(material);
+
+                    let material_index = *materials.entry(material).or_insert_with(|| {
+                        root.materials.push(material.to_gltf());
+                        root.materials.len() - 1
+                    });
+
+                    let next_buffer_view_start = triangle_vertices_len * GLTF_VERTEX_SIZE * 3;
+                    buffer_view_count.push(triangle_vertices_len * 3);
+
+                    let bvl = root.buffer_views.len();
+                    if bvl > 0 {
+                        root.buffer_views[bvl - 1].byte_length = (next_buffer_view_start as u32) - root.buffer_views[bvl - 1].byte_offset;
+                    }
+
+                    // buffer view
+                    root.buffer_views.push(gltf::BufferView {
+                        buffer: 0,
+                        byte_length: 0,
+                        byte_offset: next_buffer_view_start as u32,
+                        byte_stride: Some(GLTF_VERTEX_SIZE as u32),
+                        target: Some(34962),
+                    });
+
+                    // accessors
+                    let accessors_count = root.accessors.len();
+
+                    // position
+                    root.accessors.push(gltf::Accessor {
+                        buffer_view: bvl,
+                        byte_offset: 0,
+                        component_type: 5126,
+                        count: 0,
+                        max: Some(vec![0.0, 0.0, 0.0]),
+                        min: Some(vec![0.0, 0.0, 0.0]),
+                        normalized: false,
+                        type_: "VEC3".to_string(),
+                    });
+                    // uv
+                    root.accessors.push(gltf::Accessor {
+                        buffer_view: bvl,
+                        byte_offset: 12,
+                        component_type: 5126,
+                        count: 0,
+                        max: None,
+                        min: None,
+                        normalized: false,
+                        type_: "VEC2".to_string(),
+                    });
+                    // colour
+                    root.accessors.push(gltf::Accessor {
+                        buffer_view: bvl,
+                        byte_offset: 20,
+                        component_type: 5121,
+                        count: 0,
+                        max: None,
+                        min: None,
+                        normalized: true,
+                        type_: "VEC4".to_string(),
+                    });
+                    // normal
+                    root.accessors.push(gltf::Accessor {
+                        buffer_view: bvl,
+                        byte_offset: 24,
+                        component_type: 5126,
+                        count: 0,
+                        max: None,
+                        min: None,
+                        normalized: false,
+                        type_: "VEC3".to_string(),
+                    });
+                    // primitives
+                    root.meshes[0].primitives.push(gltf::Primitive {
+                        attributes: {
+                            let mut map = HashMap::new();
+                            map.insert("POSITION".to_string(), accessors_count);
+                            map.insert("TEXCOORD_0".to_string(), accessors_count+1);
+                            // the baked lights are only seen through the vertex colours
+                            if material.lit {
+                                map.insert("NORMAL".to_string(), accessors_count+3);
+                            } else if material.vertex_colour || !lights.is_empty() {
+                                map.insert("COLOR_0".to_string(), accessors_count+2);
+                            }
+                            map
+                        },
+                        material: Some(material_index),
+                        mode: None,
+                    });
+
+                    buffer_view_start = next_buffer_view_start;
+                }
+            }
+
+            // lit vertices hold normals instead of colours
+            let baked_lights = if current_material.is_some_and(|m: MaterialKey| m.lit) { &[] } else { lights };
+
             match cmd {
                 F3dex::Vertex { index, count, address } => {
                     let address = (*address & 0xFFFFFF) / 16;
@@ -1140,7 +1329,7 @@ impl Model {
                         y: cache_vtx[*v2 as usize] as usize,
                         z: cache_vtx[*v3 as usize] as usize,
                     };
-                    write_vertex_vector(&self, &mut triangle_vertices, &face);
+                    write_vertex_vector(&self, &mut triangle_vertices, &face, baked_lights);
 
                     triangle_vertices_len += 1;
                 },
@@ -1150,99 +1339,48 @@ impl Model {
                         y: cache_vtx[*v2 as usize] as usize,
                         z: cache_vtx[*v3 as usize] as usize,
                     };
-                    write_vertex_vector(&self, &mut triangle_vertices, &face);
+                    write_vertex_vector(&self, &mut triangle_vertices, &face, baked_lights);
 
                     let face = Vector3 {
                         x: cache_vtx[*v4 as usize] as usize,
                         y: cache_vtx[*v5 as usize] as usize,
                         z: cache_vtx[*v6 as usize] as usize,
                     };
-                    write_vertex_vector(&self, &mut triangle_vertices, &face);
+                    write_vertex_vector(&self, &mut triangle_vertices, &face, baked_lights);
 
                     triangle_vertices_len += 2;
                 },
+                F3dex::SetGeometryMode(flags) => {
+                    render_state.geometry_mode |= *flags;
+                },
+                F3dex::ClearGeometryMode(flags) => {
+                    render_state.geometry_mode &= !*flags;
+                },
+                F3dex::SetOtherModeL { shift, length, mode } => {
+                    let previous = render_state.other_mode_l.unwrap_or(0);
+                    render_state.other_mode_l = Some(update_other_mode(previous, *shift, *length, mode.encode(*shift, *length)));
+                },
+                F3dex::SetOtherModeH { shift, length, mode } => {
+                    render_state.other_mode_h = update_other_mode(render_state.other_mode_h, *shift, *length, mode.encode(*shift, *length));
+                },
+                F3dex::SetCombine(combiner) => {
+                    render_state.combiner = Some(*combiner);
+                },
+                F3dex::SetBlendColor { a, .. } => {
+                    render_state.blend_alpha = *a;
+                },
                 F3dex::SettImg { format, depth, address } => {
                     let mut found = false;
-                    let mut prev = current_texture;
                     for (i, tex) in self.textures.iter().enumerate() {
                         let addr1 = *address & 0xFFFFFF;
                         let addr2 = if addr1 < 32 { addr1 } else { addr1 - 32 };
                         
                         if tex.offset == addr1 || tex.offset == addr2 {
                             found = true;
-                            current_texture = i;
+                            current_texture = Some(i);
                         }
                     }
                     assert_eq!(found, true);
-
-                    if current_texture != prev {
-                        let next_buffer_view_start = triangle_vertices_len * 24 * 3;
-                        buffer_view_count.push(triangle_vertices_len * 3);
-
-                        let bvl = root.buffer_views.len();
-                        if bvl > 0 {
-                            root.buffer_views[bvl - 1].byte_length = (next_buffer_view_start as u32) - root.buffer_views[bvl - 1].byte_offset;
-                        }
-
-                        // buffer view
-                        root.buffer_views.push(gltf::BufferView {
-                            buffer: 0,
-                            byte_length: 0,
-                            byte_offset: next_buffer_view_start as u32,
-                            byte_stride: 24,
-                            target: 34962,
-                        });
-
-                        // accessors
-                        let accessors_count = root.accessors.len();
-
-                        // position
-                        root.accessors.push(gltf::Accessor {
-                            buffer_view: bvl,
-                            byte_offset: 0,
-                            component_type: 5126,
-                            count: 0,
-                            max: Some([0.0, 0.0, 0.0]),
-                            min: Some([0.0, 0.0, 0.0]),
-                            normalized: false,
-                            type_: "VEC3".to_string(),
-                        });
-                        // uv
-                        root.accessors.push(gltf::Accessor {
-                            buffer_view: bvl,
-                            byte_offset: 12,
-                            component_type: 5126,
-                            count: 0,
-                            max: None,
-                            min: None,
-                            normalized: false,
-                            type_: "VEC2".to_string(),
-                        });
-                        // colour
-                        root.accessors.push(gltf::Accessor {
-                            buffer_view: bvl,
-                            byte_offset: 20,
-                            component_type: 5121,
-                            count: 0,
-                            max: None,
-                            min: None,
-                            normalized: true,
-                            type_: "VEC4".to_string(),
-                        });
-                        // primitives
-                        root.meshes[0].primitives.push(gltf::Primitive {
-                            attributes: {
-                                let mut map = HashMap::new();
-                                map.insert("POSITION".to_string(), accessors_count);
-                                map.insert("TEXCOORD_0".to_string(), accessors_count+1);
-                                map.insert("COLOR_0".to_string(), accessors_count+2);
-                                map
-                            },
-                            material: current_texture,
-                        });
-
-                        buffer_view_start = next_buffer_view_start;
-                    }
                 },
                 _ => {},
             };
@@ -1260,8 +1398,8 @@ impl Model {
                 let mut max = [-9999.0, -9999.0, -9999.0];
                 let mut min = [9999.0, 9999.0, 9999.0];
 
-                let start_byte = buffer_view_count[bvl] * 24;
-                let end_byte = start_byte + count * 24;
+                let start_byte = buffer_view_count[bvl] * GLTF_VERTEX_SIZE;
+                let end_byte = start_byte + count * GLTF_VERTEX_SIZE;
                 let mut buf: &[u8] = &triangle_vertices[start_byte..end_byte];
                 for _ in 0..count {
                     let x = buf.read_f32::<LittleEndian>().unwrap();
@@ -1270,6 +1408,7 @@ impl Model {
                     let _ = buf.read_f32::<LittleEndian>().unwrap();
                     let _ = buf.read_f32::<LittleEndian>().unwrap();
                     let _ = buf.read_u32::<LittleEndian>().unwrap();
+                    let _ = read_3_floats(&mut buf);
                     
                     min[0] = if x < min[0] { x } else { min[0] };
                     min[1] = if y < min[1] { y } else { min[1] };
@@ -1279,17 +1418,17 @@ impl Model {
                     max[2] = if z > max[2] { z } else { max[2] };
                 }
 
-                root.accessors[i].max = Some(max);
-                root.accessors[i].min = Some(min);
+                root.accessors[i].max = Some(max.to_vec());
+                root.accessors[i].min = Some(min.to_vec());
             }
         }
 
         let bvl = root.buffer_views.len();
-        root.buffer_views[bvl - 1].byte_length = (triangle_vertices_len as u32 * 24 * 3) - root.buffer_views[bvl - 1].byte_offset;
+        root.buffer_views[bvl - 1].byte_length = (triangle_vertices_len * GLTF_VERTEX_SIZE * 3) as u32 - root.buffer_views[bvl - 1].byte_offset;
 
         let triangle_vertices_len = triangle_vertices_len;
         let triangle_vertices = triangle_vertices;
-        let vertex_size = 24u32;
+        let vertex_size = GLTF_VERTEX_SIZE as u32;
         let buffer_length = triangle_vertices.len() as u32;
 
         root.buffers.push(gltf::Buffer {
@@ -1300,9 +1439,8 @@ impl Model {
         if let Some(skeleton) = &self.animation_list {
             for (id, bone) in skeleton.animations.iter().enumerate() {
                 root.nodes.push(gltf::Node {
-                    mesh: None,
-                    children: vec![],
                     translation: Some([bone.position.x, bone.position.y, bone.position.z]),
+                    ..Default::default()
                 });
 
                 if bone.parent != -1 {
@@ -1313,11 +1451,350 @@ impl Model {
             }
         }
 
-        let writer = File::create(format!("{}/model.gltf", output_dir)).unwrap();
-        serde_json::to_writer_pretty(writer, &root).unwrap();
-
         let mut writer = File::create(format!("{}/buffer0.bin", output_dir)).unwrap();
         writer.write_all(&triangle_vertices).unwrap();
+
+        root
+    }
+
+    // vertices are moved when their position changed by more than the tolerance
+    pub fn diff(&self, other: &Model, tolerance: f32) -> ModelDiff {
+        let mut textures = vec![];
+        for i in 0..self.textures.len().max(other.textures.len()) {
+            let what = format!("texture {}", i);
+            match (self.textures.get(i), other.textures.get(i)) {
+                (Some(_), None) => textures.push(Change { change: ChangeKind::Removed, what, position: None, delta: None, fields: vec![] }),
+                (None, Some(_)) => textures.push(Change { change: ChangeKind::Added, what, position: None, delta: None, fields: vec![] }),
+                (Some(old), Some(new)) => {
+                    let mut fields = vec![];
+                    diff_values("", &old.summary(), &new.summary(), &mut fields);
+                    if !fields.is_empty() {
+                        textures.push(Change { change: ChangeKind::Modified, what, position: None, delta: None, fields });
+                    }
+                },
+                (None, None) => {},
+            }
+        }
+
+        let old_vertices = &self.vertex_data.vertices;
+        let new_vertices = &other.vertex_data.vertices;
+        let vertex_count = if old_vertices.len() != new_vertices.len() {
+            Some(FieldChange { field: "vertex count".to_string(), old: old_vertices.len().into(), new: new_vertices.len().into() })
+        } else {
+            None
+        };
+
+        let mut vertices = vec![];
+        for (i, (old, new)) in old_vertices.iter().zip(new_vertices).enumerate() {
+            let delta = [
+                new.position.x as f32 - old.position.x as f32,
+                new.position.y as f32 - old.position.y as f32,
+                new.position.z as f32 - old.position.z as f32,
+            ];
+            let moved = (delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2]).sqrt() > tolerance;
+
+            let mut fields = vec![];
+            diff_values("", &serde_json::json!({ "flag": old.flag, "uv": old.uv, "rgba": [old.r, old.g, old.b, old.a] }),
+                &serde_json::json!({ "flag": new.flag, "uv": new.uv, "rgba": [new.r, new.g, new.b, new.a] }), &mut fields);
+
+            if moved || !fields.is_empty() {
+                vertices.push(Change {
+                    change: if moved { ChangeKind::Moved } else { ChangeKind::Modified },
+                    what: format!("vertex {}", i),
+                    position: Some([new.position.x as f32, new.position.y as f32, new.position.z as f32]),
+                    delta: if moved { Some(delta) } else { None },
+                    fields,
+                });
+            }
+        }
+
+        let old_commands: Vec<String> = self.commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        let new_commands: Vec<String> = other.commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        let commands = diff_lines(&old_commands, &new_commands);
+
+        let mut collisions = vec![];
+        match (&self.collisions, &other.collisions) {
+            (Some(old), Some(new)) => {
+                let mut fields = vec![];
+                let grid = |c: &Collisions| serde_json::json!({ "min": c.min, "max": c.max, "stride": c.stride, "scale": c.scale, "geo": c.geo });
+                diff_values("", &grid(old), &grid(new), &mut fields);
+                if !fields.is_empty() {
+                    collisions.push(Change { change: ChangeKind::Modified, what: "collision grid".to_string(), position: None, delta: None, fields });
+                }
+
+                // triangles are compared as sets, by their vertices
+                let mut new_tris: Vec<Option<&TriColl>> = new.tri.iter().map(Some).collect();
+                for tri in &old.tri {
+                    let same = new_tris.iter().position(|t| t.is_some_and(|t| t == tri));
+                    let same_vertices = || new_tris.iter().position(|t| t.is_some_and(|t| t.vertices() == tri.vertices()));
+
+                    if let Some(i) = same {
+                        new_tris[i] = None;
+                    } else if let Some(i) = same_vertices() {
+                        let mut fields = vec![];
+                        diff_values("", &serde_json::to_value(tri).unwrap(), &serde_json::to_value(new_tris[i].unwrap()).unwrap(), &mut fields);
+                        collisions.push(Change { change: ChangeKind::Modified, what: tri.label(), position: None, delta: None, fields });
+                        new_tris[i] = None;
+                    } else {
+                        collisions.push(Change { change: ChangeKind::Removed, what: tri.label(), position: None, delta: None, fields: vec![] });
+                    }
+                }
+                for tri in new_tris.into_iter().flatten() {
+                    collisions.push(Change { change: ChangeKind::Added, what: tri.label(), position: None, delta: None, fields: vec![] });
+                }
+            },
+            (Some(_), None) => collisions.push(Change { change: ChangeKind::Removed, what: "collisions".to_string(), position: None, delta: None, fields: vec![] }),
+            (None, Some(_)) => collisions.push(Change { change: ChangeKind::Added, what: "collisions".to_string(), position: None, delta: None, fields: vec![] }),
+            (None, None) => {},
+        };
+
+        let mut geometry = vec![];
+        diff_values("geometry", &serde_json::to_value(&self.geometry).unwrap(), &serde_json::to_value(&other.geometry).unwrap(), &mut geometry);
+
+        ModelDiff { textures, vertex_count, vertices, commands, collisions, geometry }
+    }
+
+    pub fn write_c(&self, filename: &str, symbol: &str) -> std::io::Result<()> {
+        let mut f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(filename)?;
+        let symbol = c_identifier(symbol);
+
+        writeln!(f, "#include <ultra64.h>")?;
+        writeln!(f)?;
+        writeln!(f, "#ifndef BK_LEVEL_TYPES")?;
+        writeln!(f, "#define BK_LEVEL_TYPES")?;
+        writeln!(f, "typedef struct {{")?;
+        writeln!(f, "    s16 min[3];")?;
+        writeln!(f, "    s16 max[3];")?;
+        writeln!(f, "    s16 stride[2];")?;
+        writeln!(f, "    u16 geo_count;")?;
+        writeln!(f, "    u16 scale;")?;
+        writeln!(f, "    u16 tri_count;")?;
+        writeln!(f, "    u16 padding;")?;
+        writeln!(f, "}} BKLevelCollisions;")?;
+        writeln!(f)?;
+        writeln!(f, "typedef struct {{")?;
+        writeln!(f, "    u16 start_tri_index;")?;
+        writeln!(f, "    u16 tri_count;")?;
+        writeln!(f, "}} BKLevelGeoColl;")?;
+        writeln!(f)?;
+        writeln!(f, "typedef struct {{")?;
+        writeln!(f, "    u16 vtx_indx[3];")?;
+        writeln!(f, "    u16 unk;")?;
+        writeln!(f, "    u32 flags;")?;
+        writeln!(f, "}} BKLevelTriColl;")?;
+        write!(f, "{}", C_GEOMETRY_TYPES)?;
+        writeln!(f, "#endif")?;
+
+        for tex in &self.textures {
+            let raw = hex::decode(&tex.raw).unwrap();
+
+            writeln!(f)?;
+            writeln!(f, "/* {:?} {}x{} */", tex.format, tex.width, tex.height)?;
+            writeln!(f, "u8 {}_texture_{:X}[] = {{", symbol, tex.offset)?;
+            for line in raw.chunks(16) {
+                let bytes: Vec<String> = line.iter().map(|b| format!("0x{:02X}", b)).collect();
+                writeln!(f, "    {},", bytes.join(", "))?;
+            }
+            writeln!(f, "}};")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Gfx {}_gfx[] = {{", symbol)?;
+        for cmd in &self.commands {
+            let mut words = vec![];
+            write_command(&mut words, cmd)?;
+            let mut words = &words[..];
+            let w0 = words.read_u32::<BigEndian>()?;
+            let w1 = words.read_u32::<BigEndian>()?;
+            writeln!(f, "    {{{{0x{:08X}, 0x{:08X}}}}}, /* {:?} */", w0, w1, cmd)?;
+        }
+        writeln!(f, "}};")?;
+
+        writeln!(f)?;
+        writeln!(f, "Vtx {}_vtx[] = {{", symbol)?;
+        for vert in &self.vertex_data.vertices {
+            let s = (vert.uv.x * 65536.0) as i16;
+            let t = (vert.uv.y * 65536.0) as i16;
+            writeln!(f, "    {{{{{{{}, {}, {}}}, 0x{:X}, {{{}, {}}}, {{0x{:02X}, 0x{:02X}, 0x{:02X}, 0x{:02X}}}}}}},",
+                vert.position.x, vert.position.y, vert.position.z, vert.flag,
+                s, t, vert.r, vert.g, vert.b, vert.a)?;
+        }
+        writeln!(f, "}};")?;
+
+        if let Some(collisions) = &self.collisions {
+            writeln!(f)?;
+            writeln!(f, "BKLevelCollisions {}_collisions = {{", symbol)?;
+            writeln!(f, "    {{{}, {}, {}}},", collisions.min.x, collisions.min.y, collisions.min.z)?;
+            writeln!(f, "    {{{}, {}, {}}},", collisions.max.x, collisions.max.y, collisions.max.z)?;
+            writeln!(f, "    {{{}, {}}},", collisions.stride.x, collisions.stride.y)?;
+            writeln!(f, "    {}, {}, {}, 0,", collisions.geo.len(), collisions.scale, collisions.tri.len())?;
+            writeln!(f, "}};")?;
+
+            writeln!(f)?;
+            writeln!(f, "BKLevelGeoColl {}_geo_coll[] = {{", symbol)?;
+            for geo in &collisions.geo {
+                writeln!(f, "    {{{}, {}}},", geo.start_tri_index, geo.tri_count)?;
+            }
+            writeln!(f, "}};")?;
+
+            writeln!(f)?;
+            writeln!(f, "BKLevelTriColl {}_tri_coll[] = {{", symbol)?;
+            for tri in &collisions.tri {
+                writeln!(f, "    {{{{{}, {}, {}}}, 0x{:X}, 0x{:X}}},", tri.vtx_indx_1, tri.vtx_indx_2, tri.vtx_indx_3, tri.unk, tri.flags)?;
+            }
+            writeln!(f, "}};")?;
+        }
+
+        // the layout is a tree of variable-length commands, so it's a struct
+        // with a member per command, followed by the members of its children
+        let mut members = vec![];
+        for geocmd in &self.geometry {
+            c_geometry(geocmd, &mut members);
+        }
+        if !members.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "struct {{")?;
+            for (declaration, _) in &members {
+                writeln!(f, "    {};", declaration)?;
+            }
+            writeln!(f, "}} {}_geometry = {{", symbol)?;
+            for (_, value) in &members {
+                writeln!(f, "    {},", value)?;
+            }
+            writeln!(f, "}};")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+// everything that decides how a triangle looks in the glTF
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct MaterialKey {
+    texture: Option<usize>,
+    double_sided: bool,
+    alpha_mode: AlphaMode,
+    alpha_cutoff: u8,
+    vertex_colour: bool,
+    lit: bool,
+}
+
+impl MaterialKey {
+    fn to_gltf(self) -> gltf::Material {
+        gltf::Material {
+            pbr_metallic_roughness: gltf::PbrMetallicRoughness {
+                base_color_texture: self.texture.map(|index| gltf::TextureInfo {
+                    index,
+                    extensions: {
+                        let mut map = HashMap::new();
+                        map.insert("KHR_texture_transform".to_string(), gltf::Extension::KhrTextureTransform {
+                            scale: [20.0, 20.0],
+                            offset: [0.0, 0.0],
+                            rotation: 0.0,
+                        });
+                        map
+                    },
+                }),
+                metallic_factor: 0.0,
+            },
+            alpha_mode: match self.alpha_mode {
+                AlphaMode::Opaque => "OPAQUE".to_string(),
+                AlphaMode::Mask => "MASK".to_string(),
+                AlphaMode::Blend => "BLEND".to_string(),
+            },
+            alpha_cutoff: if self.alpha_mode == AlphaMode::Mask {
+                Some(self.alpha_cutoff as f32 / 255.0)
+            } else {
+                None
+            },
+            double_sided: self.double_sided,
+        }
+    }
+}
+
+// RSP/RDP state tracked while walking the display list
+#[derive(Debug, Default)]
+struct RenderState {
+    geometry_mode: u32,
+    other_mode_l: Option<u32>,
+    other_mode_h: u32,
+    combiner: Option<Combiner>,
+    blend_alpha: u8,
+}
+
+impl RenderState {
+    fn combiner_cycles(&self) -> Vec<CombinerCycle> {
+        let other_mode_h = OtherModeH::decode(0, 32, self.other_mode_h);
+
+        match &self.combiner {
+            Some(combiner) if other_mode_h.cycle_type == Some(CycleType::TwoCycle) => vec![combiner.cycle1, combiner.cycle2],
+            Some(combiner) => vec![combiner.cycle1],
+            None => vec![],
+        }
+    }
+
+    fn material(&self, texture: Option<usize>, textures: &[Texture]) -> MaterialKey {
+        let cycles = self.combiner_cycles();
+        let uses = |inputs: &[CombinerInput]| {
+            cycles.iter().any(|cycle| {
+                [cycle.colour, cycle.alpha].iter().any(|eq| {
+                    [eq.a, eq.b, eq.c, eq.d].iter().any(|input| inputs.contains(input))
+                })
+            })
+        };
+
+        // without a combiner, assume the usual texture * shade
+        let textured = self.combiner.is_none() || uses(&[CombinerInput::Texel0, CombinerInput::Texel1,
+            CombinerInput::Texel0Alpha, CombinerInput::Texel1Alpha]);
+        let vertex_colour = self.combiner.is_none() || uses(&[CombinerInput::Shade, CombinerInput::ShadeAlpha]);
+        let texture = if textured { texture } else { None };
+
+        let mut alpha_cutoff = 0;
+        let alpha_mode = if let Some(other_mode_l) = self.other_mode_l {
+            let other_mode_l = OtherModeL::decode(0, 32, other_mode_l);
+            let other_mode_h = OtherModeH::decode(0, 32, self.other_mode_h);
+            let render_mode = other_mode_l.render_mode.unwrap();
+            let blender = if other_mode_h.cycle_type == Some(CycleType::TwoCycle) {
+                render_mode.cycle2
+            } else {
+                render_mode.cycle1
+            };
+
+            if render_mode.force_blend && blender.m == BlenderColour::Memory && blender.a != BlenderAlpha::Zero {
+                AlphaMode::Blend
+            } else if other_mode_l.alpha_compare == Some(AlphaCompare::Threshold) {
+                alpha_cutoff = self.blend_alpha;
+                AlphaMode::Mask
+            } else if render_mode.coverage_x_alpha && render_mode.alpha_coverage_select {
+                alpha_cutoff = 128;
+                AlphaMode::Mask
+            } else {
+                AlphaMode::Opaque
+            }
+        } else {
+            // the display list never sets a render mode, guess from the texture
+            match texture.map(|t| &textures[t].format) {
+                Some(TextureFormat::IA8) | None => AlphaMode::Opaque,
+                Some(_) => AlphaMode::Blend,
+            }
+        };
+
+        // glTF can't cull only the front faces (G_CULL_FRONT), these stay double sided
+        MaterialKey {
+            texture,
+            double_sided: self.geometry_mode & G_CULL_BACK == 0,
+            alpha_mode,
+            alpha_cutoff,
+            vertex_colour,
+            lit: self.geometry_mode & G_LIGHTING != 0,
+        }
     }
 }
 
@@ -1512,7 +1989,7 @@ fn read_geometry_layout(f: &mut File) -> std::io::Result<Vec<Geometry>> {
     Ok(geometry)
 }
 
-fn write_geometry_layout_command(f: &mut File, geocmd: &Geometry) -> std::io::Result<()> {
+fn write_geometry_layout_command(f: &mut impl Write, geocmd: &Geometry) -> std::io::Result<()> {
     match geocmd {
         Geometry::Unknown0x00 { len, unk1, unk2, unk3 } => {
             f.write_u32::<BigEndian>(0x0)?;
@@ -1650,7 +2127,7 @@ fn write_align_8bytes(f: &mut File) {
     }
 }
 
-fn read_command(f: &mut File) -> std::io::Result<F3dex> {
+fn read_command(f: &mut impl Read) -> std::io::Result<F3dex> {
     let cmd = f.read_u8()?;
     let command = match cmd {
         0x00 => {
@@ -1660,6 +2137,24 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
             
             F3dex::SPNoOp
         },
+        0x01 => {
+            let params = f.read_u8()?; assert_eq!(params & 0xF8, 0);
+            let size = f.read_u16::<BigEndian>()?;
+            let address = f.read_u32::<BigEndian>()?;
+
+            let projection = params & 0b001 != 0;
+            let load = params & 0b010 != 0;
+            let push = params & 0b100 != 0;
+
+            F3dex::Matrix { projection, load, push, size, address }
+        },
+        0x03 => {
+            let index = f.read_u8()?;
+            let size = f.read_u16::<BigEndian>()?;
+            let address = f.read_u32::<BigEndian>()?;
+
+            F3dex::MoveMem { index, size, address }
+        },
         0x04 => {
             let index = (f.read_u8()? as u16) * 2;
             let data = f.read_u16::<BigEndian>()?;
@@ -1678,6 +2173,15 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
 
             F3dex::DisplayList { store_ra, address }
         },
+        0xB0 => {
+            let data = ((f.read_u8()? as u32) << 16) + (f.read_u16::<BigEndian>()? as u32);
+            let z = f.read_u32::<BigEndian>()?;
+
+            let vertex = ((data & 0xFFF) / 2) as u16;
+            assert_eq!(data >> 12, (vertex as u32) * 5);
+
+            F3dex::BranchZ { vertex, z }
+        },
         0xB1 => {
             let v1 = f.read_u8()? / 2;
             let v2 = f.read_u8()? / 2;
@@ -1689,6 +2193,27 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
 
             F3dex::Triangle2 { v1, v2, v3, v4, v5, v6 }
         },
+        0xB2 => {
+            let location = f.read_u8()?;
+            let vertex = f.read_u16::<BigEndian>()? / 2;
+            let value = f.read_u32::<BigEndian>()?;
+
+            F3dex::ModifyVertex { vertex, location, value }
+        },
+        0xB3 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let value = f.read_u32::<BigEndian>()?;
+
+            F3dex::RdpHalf2(value)
+        },
+        0xB4 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let value = f.read_u32::<BigEndian>()?;
+
+            F3dex::RdpHalf1(value)
+        },
         0xB6 => {
             let padding = f.read_u8()?; assert_eq!(padding, 0);
             let padding = f.read_u8()?; assert_eq!(padding, 0);
@@ -1714,21 +2239,23 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
         },
         0xB9 => {
             let padding = f.read_u8()?; assert_eq!(padding, 0);
-            let amount = f.read_u8()?;
-            let count = f.read_u8()?;
+            let shift = f.read_u8()?;
+            let length = f.read_u8()?;
             let mode = f.read_u32::<BigEndian>()?;
 
-            // TODO
-            F3dex::SetOtherModeL { amount, count, mode }
+            let mode = OtherModeL::decode(shift, length, mode);
+
+            F3dex::SetOtherModeL { shift, length, mode }
         },
         0xBA => {
             let padding = f.read_u8()?; assert_eq!(padding, 0);
-            let amount = f.read_u8()?;
-            let count = f.read_u8()?;
+            let shift = f.read_u8()?;
+            let length = f.read_u8()?;
             let mode = f.read_u32::<BigEndian>()?;
 
-            // TODO
-            F3dex::SetOtherModeH { amount, count, mode }
+            let mode = OtherModeH::decode(shift, length, mode);
+
+            F3dex::SetOtherModeH { shift, length, mode }
         },
         0xBB => {
             let padding = f.read_u8()?; assert_eq!(padding, 0);
@@ -1744,6 +2271,13 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
 
             F3dex::Texture { mipmaps, descriptor, enable, scalex, scaley }
         },
+        0xBC => {
+            let offset = f.read_u16::<BigEndian>()?;
+            let index = f.read_u8()?;
+            let value = f.read_u32::<BigEndian>()?;
+
+            F3dex::MoveWord { index, offset, value }
+        },
         0xBD => {
             let unk1 = f.read_u8()?;
             let unk2 = f.read_u8()?;
@@ -1752,6 +2286,14 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
 
             F3dex::PopMatrix { unk1, unk2, unk3, count }
         },
+        0xBE => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let vstart = f.read_u16::<BigEndian>()? / 2;
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let vend = f.read_u16::<BigEndian>()? / 2;
+
+            F3dex::CullDisplayList { vstart, vend }
+        },
         0xBF => {
             let padding = f.read_u32::<BigEndian>()?; assert_eq!(padding, 0);
             let v1 = f.read_u8()? / 2;
@@ -1774,6 +2316,20 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
             
             F3dex::RdpPipeSync
         },
+        0xE8 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let padding = f.read_u32::<BigEndian>()?; assert_eq!(padding, 0);
+
+            F3dex::RdpTileSync
+        },
+        0xE9 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let padding = f.read_u32::<BigEndian>()?; assert_eq!(padding, 0);
+
+            F3dex::RdpFullSync
+        },
         0xF0 => {
             let descriptor = f.read_u32::<BigEndian>()?; assert_eq!(descriptor & 0xFFFFFFF0, 0);
             let colour_count = f.read_u16::<BigEndian>()?;
@@ -1865,14 +2421,59 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
                 }
             }
         },
-        0xFC => {
-            let unk1 = f.read_u8()?;
-            let unk2 = f.read_u16::<BigEndian>()?;
-            let unk3 = f.read_u32::<BigEndian>()?;
+        0xF7 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let colour = f.read_u32::<BigEndian>()?;
+
+            F3dex::SetFillColor(colour)
+        },
+        0xF8 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let r = f.read_u8()?;
+            let g = f.read_u8()?;
+            let b = f.read_u8()?;
+            let a = f.read_u8()?;
+
+            F3dex::SetFogColor { r, g, b, a }
+        },
+        0xF9 => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let r = f.read_u8()?;
+            let g = f.read_u8()?;
+            let b = f.read_u8()?;
+            let a = f.read_u8()?;
+
+            F3dex::SetBlendColor { r, g, b, a }
+        },
+        0xFA => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let min_level = f.read_u8()?;
+            let lod_fraction = f.read_u8()?;
+            let r = f.read_u8()?;
+            let g = f.read_u8()?;
+            let b = f.read_u8()?;
+            let a = f.read_u8()?;
 
-            // [aaaa] [ccccc] [eee] [ggg] [iiii] [kkkkk] [bbbb] [jjjj] [mmm] [ooo] [ddd] [fff] [hhh] [lll] [nnn] [ppp]
+            F3dex::SetPrimColor { min_level, lod_fraction, r, g, b, a }
+        },
+        0xFB => {
+            let padding = f.read_u8()?; assert_eq!(padding, 0);
+            let padding = f.read_u16::<BigEndian>()?; assert_eq!(padding, 0);
+            let r = f.read_u8()?;
+            let g = f.read_u8()?;
+            let b = f.read_u8()?;
+            let a = f.read_u8()?;
+
+            F3dex::SetEnvColor { r, g, b, a }
+        },
+        0xFC => {
+            let w0 = ((f.read_u8()? as u32) << 16) + (f.read_u16::<BigEndian>()? as u32);
+            let w1 = f.read_u32::<BigEndian>()?;
 
-            F3dex::SetCombine { unk1, unk2, unk3 }
+            F3dex::SetCombine(Combiner::decode(w0, w1))
         },
         0xFD => {
             let flags = f.read_u8()?;
@@ -1898,11 +2499,25 @@ fn read_command(f: &mut File) -> std::io::Result<F3dex> {
     Ok(command)
 }
 
-fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
+fn write_command(f: &mut impl Write, cmd: &F3dex) -> std::io::Result<()> {
     match cmd {
         F3dex::SPNoOp => {
             f.write_u64::<BigEndian>(0)?;
         },
+        F3dex::Matrix { projection, load, push, size, address } => {
+            let params = (*projection as u8) + ((*load as u8) << 1) + ((*push as u8) << 2);
+
+            f.write_u8(0x01)?;
+            f.write_u8(params)?;
+            f.write_u16::<BigEndian>(*size)?;
+            f.write_u32::<BigEndian>(*address)?;
+        },
+        F3dex::MoveMem { index, size, address } => {
+            f.write_u8(0x03)?;
+            f.write_u8(*index)?;
+            f.write_u16::<BigEndian>(*size)?;
+            f.write_u32::<BigEndian>(*address)?;
+        },
         F3dex::Vertex { index, count, address } => {
             let count = (*count) as u16;
             let size = count * 0x10 - 1;
@@ -1919,6 +2534,14 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u16::<BigEndian>(0)?;
             f.write_u32::<BigEndian>(*address)?;
         },
+        F3dex::BranchZ { vertex, z } => {
+            let data = (((*vertex as u32) * 5) << 12) + (*vertex as u32) * 2;
+
+            f.write_u8(0xB0)?;
+            f.write_u8((data >> 16) as u8)?;
+            f.write_u16::<BigEndian>(data as u16)?;
+            f.write_u32::<BigEndian>(*z)?;
+        },
         F3dex::Triangle2 { v1, v2, v3, v4, v5, v6 } => {
             f.write_u8(0xB1)?;
             f.write_u8(*v1 * 2)?;
@@ -1929,6 +2552,20 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u8(*v5 * 2)?;
             f.write_u8(*v6 * 2)?;
         },
+        F3dex::ModifyVertex { vertex, location, value } => {
+            f.write_u8(0xB2)?;
+            f.write_u8(*location)?;
+            f.write_u16::<BigEndian>(*vertex * 2)?;
+            f.write_u32::<BigEndian>(*value)?;
+        },
+        F3dex::RdpHalf2(value) => {
+            f.write_u32::<BigEndian>(0xB3000000)?;
+            f.write_u32::<BigEndian>(*value)?;
+        },
+        F3dex::RdpHalf1(value) => {
+            f.write_u32::<BigEndian>(0xB4000000)?;
+            f.write_u32::<BigEndian>(*value)?;
+        },
         F3dex::ClearGeometryMode(flags) => {
             f.write_u32::<BigEndian>(0xB6000000)?;
             f.write_u32::<BigEndian>(*flags)?;
@@ -1941,17 +2578,17 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u32::<BigEndian>(0xB8000000)?;
             f.write_u32::<BigEndian>(0)?;
         },
-        F3dex::SetOtherModeL { amount, count, mode } => {
+        F3dex::SetOtherModeL { shift, length, mode } => {
             f.write_u16::<BigEndian>(0xB900)?;
-            f.write_u8(*amount)?;
-            f.write_u8(*count)?;
-            f.write_u32::<BigEndian>(*mode)?;
+            f.write_u8(*shift)?;
+            f.write_u8(*length)?;
+            f.write_u32::<BigEndian>(mode.encode(*shift, *length))?;
         },
-        F3dex::SetOtherModeH { amount, count, mode } => {
+        F3dex::SetOtherModeH { shift, length, mode } => {
             f.write_u16::<BigEndian>(0xBA00)?;
-            f.write_u8(*amount)?;
-            f.write_u8(*count)?;
-            f.write_u32::<BigEndian>(*mode)?;
+            f.write_u8(*shift)?;
+            f.write_u8(*length)?;
+            f.write_u32::<BigEndian>(mode.encode(*shift, *length))?;
         },
         F3dex::Texture { mipmaps, descriptor, enable, scalex, scaley } => {
             let flags = (mipmaps << 3) + descriptor;
@@ -1964,6 +2601,12 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u16::<BigEndian>(frac_x)?;
             f.write_u16::<BigEndian>(frac_y)?;
         },
+        F3dex::MoveWord { index, offset, value } => {
+            f.write_u8(0xBC)?;
+            f.write_u16::<BigEndian>(*offset)?;
+            f.write_u8(*index)?;
+            f.write_u32::<BigEndian>(*value)?;
+        },
         F3dex::PopMatrix { unk1, unk2, unk3, count } => {
             f.write_u8(0xBD)?;
             f.write_u8(*unk1)?;
@@ -1971,6 +2614,12 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u8(*unk3)?;
             f.write_u32::<BigEndian>(*count)?;
         },
+        F3dex::CullDisplayList { vstart, vend } => {
+            f.write_u16::<BigEndian>(0xBE00)?;
+            f.write_u16::<BigEndian>(*vstart * 2)?;
+            f.write_u16::<BigEndian>(0)?;
+            f.write_u16::<BigEndian>(*vend * 2)?;
+        },
         F3dex::Triangle1 { v1, v2, v3 } => {
             f.write_u8(0xBF)?;
             f.write_u32::<BigEndian>(0)?;
@@ -1986,6 +2635,14 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u32::<BigEndian>(0xE7000000)?;
             f.write_u32::<BigEndian>(0)?;
         },
+        F3dex::RdpTileSync => {
+            f.write_u32::<BigEndian>(0xE8000000)?;
+            f.write_u32::<BigEndian>(0)?;
+        },
+        F3dex::RdpFullSync => {
+            f.write_u32::<BigEndian>(0xE9000000)?;
+            f.write_u32::<BigEndian>(0)?;
+        },
         F3dex::LoadTlut { descriptor, colour_count } => {
             let colour_count = ((*colour_count / 4) + 1) << 4;
 
@@ -2065,13 +2722,33 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
             f.write_u8(b6)?;
             f.write_u8(b7)?;
         },
-        F3dex::SetCombine { unk1, unk2, unk3 } => {
-            f.write_u8(0xFC)?;
+        F3dex::SetFillColor(colour) => {
+            f.write_u32::<BigEndian>(0xF7000000)?;
+            f.write_u32::<BigEndian>(*colour)?;
+        },
+        F3dex::SetFogColor { r, g, b, a } => {
+            f.write_u32::<BigEndian>(0xF8000000)?;
+            f.write_all(&[*r, *g, *b, *a])?;
+        },
+        F3dex::SetBlendColor { r, g, b, a } => {
+            f.write_u32::<BigEndian>(0xF9000000)?;
+            f.write_all(&[*r, *g, *b, *a])?;
+        },
+        F3dex::SetPrimColor { min_level, lod_fraction, r, g, b, a } => {
+            f.write_u16::<BigEndian>(0xFA00)?;
+            f.write_u8(*min_level)?;
+            f.write_u8(*lod_fraction)?;
+            f.write_all(&[*r, *g, *b, *a])?;
+        },
+        F3dex::SetEnvColor { r, g, b, a } => {
+            f.write_u32::<BigEndian>(0xFB000000)?;
+            f.write_all(&[*r, *g, *b, *a])?;
+        },
+        F3dex::SetCombine(combiner) => {
+            let (w0, w1) = combiner.encode();
 
-            // TODO
-            f.write_u8(*unk1)?;
-            f.write_u16::<BigEndian>(*unk2)?;
-            f.write_u32::<BigEndian>(*unk3)?;
+            f.write_u32::<BigEndian>(0xFC000000 + w0)?;
+            f.write_u32::<BigEndian>(w1)?;
         },
         F3dex::SettImg { format, depth, address } => {
             let format = match *format {
@@ -2093,3 +2770,401 @@ fn write_command(f: &mut File, cmd: &F3dex) -> std::io::Result<()> {
 
     Ok(())
 }
+
+fn geometry_name(geocmd: &Geometry) -> &'static str {
+    match geocmd {
+        Geometry::Unknown0x00 { .. } => "Unknown0x00",
+        Geometry::Sort { .. } => "Sort",
+        Geometry::Bone { .. } => "Bone",
+        Geometry::LoadDisplayList { .. } => "LoadDisplayList",
+        Geometry::Skinning => "Skinning",
+        Geometry::Lod { .. } => "Lod",
+        Geometry::ReferencePoint { .. } => "ReferencePoint",
+        Geometry::Selector { .. } => "Selector",
+        Geometry::DrawDistance { .. } => "DrawDistance",
+        Geometry::Unknown0x0e { .. } => "Unknown0x0e",
+        Geometry::Group0x0f { .. } => "Group0x0f",
+        Geometry::Unknown0x10 { .. } => "Unknown0x10",
+    }
+}
+
+// the commands as they are written in the model file. the game reads them
+// as structs, so the variable-length parts keep the next command aligned
+const C_GEOMETRY_TYPES: &str = "
+typedef struct {
+    u32 cmd;
+    u32 len;
+    u16 unk1;
+    u16 unk2;
+    f32 unk3[3];
+} BKGeoUnknown0x00;
+
+typedef struct {
+    u32 cmd;
+    u32 size;
+    f32 pos1[3];
+    f32 pos2[3];
+    u16 draw_only_nearest;
+    u16 unk1;
+    u32 unk2;
+} BKGeoSort;
+
+typedef struct {
+    u32 cmd;
+    u32 address;
+    u8 len;
+    u8 id;
+    u16 unk;
+} BKGeoBone;
+
+typedef struct {
+    u32 cmd;
+    u32 len;
+    u16 offset;
+    u16 tri_count;
+    u32 padding;
+} BKGeoLoadDisplayList;
+
+typedef struct {
+    u32 cmd;
+    u16 unk[8];
+} BKGeoSkinning;
+
+typedef struct {
+    u32 cmd;
+    u32 layout_offset;
+    f32 max_dist;
+    f32 min_dist;
+    f32 test[3];
+    u32 len;
+} BKGeoLod;
+
+typedef struct {
+    u32 cmd;
+    u32 len;
+    u16 index;
+    u16 bone;
+    f32 pos[3];
+} BKGeoReferencePoint;
+
+/* followed by s32 indices[child_count] */
+typedef struct {
+    u32 cmd;
+    u32 len;
+    u16 child_count;
+    u16 selector;
+} BKGeoSelector;
+
+typedef struct {
+    u32 cmd;
+    u32 unk1;
+    s16 min[3];
+    s16 max[3];
+    u16 len;
+    u16 unk2;
+} BKGeoDrawDistance;
+
+typedef struct {
+    u32 cmd;
+    u32 len;
+    s16 vec1[3];
+    s16 vec2[3];
+    u32 padding;
+} BKGeoUnknown0x0e;
+
+/* followed by u16 header_size and u8 header[header_size - 10] */
+typedef struct {
+    u32 cmd;
+    u32 len;
+} BKGeoGroup0x0f;
+";
+
+// appends the declarations and initializers of the members written for the command,
+// the same bytes as write_geometry_layout_command
+fn c_geometry(geocmd: &Geometry, members: &mut Vec<(String, String)>) {
+    let index = members.iter().filter(|(declaration, _)| declaration.starts_with("BKGeo")).count();
+    let name = format!("cmd_{}", index);
+    let floats = |v: &Vector3<f32>| format!("{{{:?}f, {:?}f, {:?}f}}", v.x, v.y, v.z);
+    let shorts = |v: &Vector3<i16>| format!("{{{}, {}, {}}}", v.x, v.y, v.z);
+    let hex = |values: Vec<String>| format!("{{{}}}", values.join(", "));
+
+    let mut extra = vec![];
+    let mut children: &[Geometry] = &[];
+    let value = match geocmd {
+        Geometry::Unknown0x00 { len, unk1, unk2, unk3 } => {
+            format!("{{0x0, {}, {}, {}, {}}}", len, unk1, unk2, floats(unk3))
+        },
+        Geometry::Sort { pos1, pos2, draw_only_nearest, unk1, unk2 } => {
+            format!("{{0x1, 0, {}, {}, {}, {}, 0x{:X}}}", floats(pos1), floats(pos2), *draw_only_nearest as u16, unk1, unk2)
+        },
+        Geometry::Bone { address, len, id, unk } => {
+            format!("{{0x2, 0x{:X}, {}, {}, {}}}", address, len, id, unk)
+        },
+        Geometry::LoadDisplayList { len, offset, tri_count } => {
+            format!("{{0x3, {}, {}, {}, 0}}", len, offset, tri_count)
+        },
+        Geometry::Skinning => {
+            "{0x5, {0, 0, 0, 0, 0, 0, 0, 0}}".to_string()
+        },
+        Geometry::Lod { layout_offset, max_dist, min_dist, test } => {
+            format!("{{0x8, 0x{:X}, {:?}f, {:?}f, {}, 0x20}}", layout_offset, max_dist, min_dist, floats(test))
+        },
+        Geometry::ReferencePoint { len, index, bone, pos } => {
+            format!("{{0xA, {}, {}, {}, {}}}", len, index, bone, floats(pos))
+        },
+        Geometry::Selector { selector, indices, commands, garbage } => {
+            if !indices.is_empty() {
+                extra.push((format!("s32 {}_indices[{}]", name, indices.len()), hex(indices.iter().map(|i| i.to_string()).collect())));
+            }
+            if !garbage.is_empty() {
+                extra.push((format!("u32 {}_garbage[{}]", name, garbage.len()), hex(garbage.iter().map(|g| format!("0x{:X}", g)).collect())));
+            }
+            children = commands;
+            format!("{{0xC, 0, {}, {}}}", indices.len(), selector)
+        },
+        Geometry::DrawDistance { len, min, max, unk1, unk2, commands } => {
+            children = commands;
+            format!("{{0xD, 0x{:X}, {}, {}, {}, {}}}", unk1, shorts(min), shorts(max), len, unk2)
+        },
+        Geometry::Unknown0x0e { len, vec1, vec2, commands } => {
+            children = commands;
+            format!("{{0xE, {}, {}, {}, 0}}", len, shorts(vec1), shorts(vec2))
+        },
+        Geometry::Group0x0f { len, header, commands } => {
+            extra.push((format!("u16 {}_header_size", name), (header.len() + 10).to_string()));
+            if !header.is_empty() {
+                extra.push((format!("u8 {}_header[{}]", name, header.len()), hex(header.iter().map(|b| format!("0x{:02X}", b)).collect())));
+            }
+            children = commands;
+            format!("{{0xF, {}}}", len)
+        },
+        // not written
+        Geometry::Unknown0x10 { .. } => return,
+    };
+
+    members.push((format!("BKGeo{} {}", geometry_name(geocmd), name), value));
+    members.extend(extra);
+    for child in children {
+        c_geometry(child, members);
+    }
+}
+
+fn c_identifier(name: &str) -> String {
+    let mut ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(r: u8, g: u8, b: u8) -> Vertex {
+        Vertex {
+            position: Vector3 { x: 0, y: 0, z: 0 },
+            flag: 0,
+            uv: Vector2 { x: 0.0, y: 0.0 },
+            r, g, b, a: 0xFF,
+            normal: None,
+        }
+    }
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 0.01)
+    }
+
+    // read then written back, the same bytes
+    fn command(bytes: [u8; 8]) -> F3dex {
+        let command = read_command(&mut &bytes[..]).unwrap();
+        let mut written = vec![];
+        write_command(&mut written, &command).unwrap();
+        assert_eq!(written, bytes, "{:?}", command);
+        command
+    }
+
+    fn model(geometry: Vec<Geometry>) -> Model {
+        let zero = || Vector3 { x: 0, y: 0, z: 0 };
+        Model {
+            textures: vec![],
+            commands: vec![F3dex::Triangle1 { v1: 0, v2: 1, v3: 2 }, F3dex::EndDisplayList],
+            vertex_data: VertexData {
+                min_coord: zero(), max_coord: zero(), centre_coord: zero(), local_norm: 0, global_norm: 0,
+                vertices: vec![vertex(0x12, 0x34, 0x56)],
+            },
+            collisions: None,
+            geometry,
+            unk14: None,
+            unk20: None,
+            unk28: vec![],
+            mesh_list: vec![],
+            geometry_type: 0,
+            unk30: 0,
+            unk34: 1.0,
+            unk_display_list: 0,
+            animation_list: None,
+            animated_textures: vec![],
+        }
+    }
+
+    #[test]
+    fn c_source() {
+        let model = model(vec![
+            Geometry::Sort {
+                pos1: Vector3 { x: 0.0, y: 1.5, z: -2.0 }, pos2: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+                draw_only_nearest: true, unk1: 0, unk2: 0x20,
+            },
+            Geometry::Selector {
+                selector: 1, indices: vec![1, -2], garbage: vec![],
+                commands: vec![Geometry::LoadDisplayList { len: 0x10, offset: 0, tri_count: 1 }],
+            },
+            Geometry::Group0x0f {
+                len: 0x20, header: vec![0xAB, 0xCD],
+                commands: vec![Geometry::Bone { address: 0x1234, len: 8, id: 2, unk: 0 }],
+            },
+            Geometry::Unknown0x10 { len: 0, unk1: 0, unk2: 0 },
+        ]);
+
+        let filename = std::env::temp_dir().join(format!("bk_level_{}_model.c", std::process::id()));
+        model.write_c(filename.to_str().unwrap(), "1 level").unwrap();
+        let c = std::fs::read_to_string(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert!(c.contains("typedef struct {\n    u32 cmd;\n    u32 len;\n    u16 child_count;\n    u16 selector;\n} BKGeoSelector;"));
+        assert!(c.contains("Gfx _1_level_gfx[] = {\n    {{0xBF000000, 0x00000204}}, /* Triangle1 { v1: 0, v2: 1, v3: 2 } */\n    {{0xB8000000, 0x00000000}}, /* EndDisplayList */\n};"));
+        assert!(c.contains("Vtx _1_level_vtx[] = {\n    {{{0, 0, 0}, 0x0, {0, 0}, {0x12, 0x34, 0x56, 0xFF}}},\n};"));
+
+        let geometry = [
+            "struct {",
+            "    BKGeoSort cmd_0;",
+            "    BKGeoSelector cmd_1;",
+            "    s32 cmd_1_indices[2];",
+            "    BKGeoLoadDisplayList cmd_2;",
+            "    BKGeoGroup0x0f cmd_3;",
+            "    u16 cmd_3_header_size;",
+            "    u8 cmd_3_header[2];",
+            "    BKGeoBone cmd_4;",
+            "} _1_level_geometry = {",
+            "    {0x1, 0, {0.0f, 1.5f, -2.0f}, {0.0f, 0.0f, 0.0f}, 1, 0, 0x20},",
+            "    {0xC, 0, 2, 1},",
+            "    {1, -2},",
+            "    {0x3, 16, 0, 1, 0},",
+            "    {0xF, 32},",
+            "    12,",
+            "    {0xAB, 0xCD},",
+            "    {0x2, 0x1234, 8, 2, 0},",
+            "};",
+        ].join("\n");
+        assert!(c.ends_with(&format!("\n{}\n", geometry)), "{}", c);
+    }
+
+    #[test]
+    fn baked_lights() {
+        // a texture-only combiner doesn't use the vertex colours
+        let zero = CombinerEquation { a: CombinerInput::Zero, b: CombinerInput::Zero, c: CombinerInput::Zero, d: CombinerInput::Zero };
+        let texture = CombinerEquation { d: CombinerInput::Texel0, ..zero };
+        let cycle = CombinerCycle { colour: texture, alpha: texture };
+        let mut model = model(vec![]);
+        model.vertex_data.vertices = vec![vertex(0x10, 0x20, 0x30), vertex(0x10, 0x20, 0x30), vertex(0x10, 0x20, 0x30)];
+        model.commands = vec![
+            F3dex::SetCombine(Combiner { cycle1: cycle, cycle2: cycle }),
+            F3dex::Vertex { index: 0, count: 3, address: 0 },
+            F3dex::Triangle1 { v1: 0, v2: 1, v3: 2 },
+            F3dex::EndDisplayList,
+        ];
+        let light: Lighting = serde_yaml::from_str("{ position: { x: 0, y: 0, z: 0 }, unk: { x: 100, y: 200 }, colours: { x: 64, y: 0, z: 255 } }").unwrap();
+
+        let output_dir = std::env::temp_dir().join(format!("bk_level_{}_lights", std::process::id()));
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let output_dir = output_dir.to_str().unwrap();
+
+        let unlit = model.build_gltf(output_dir, &[]);
+        assert!(!unlit.meshes[0].primitives[0].attributes.contains_key("COLOR_0"));
+
+        let lit = model.build_gltf(output_dir, &[light]);
+        let attributes = &lit.meshes[0].primitives[0].attributes;
+        assert_eq!(lit.accessors[attributes["COLOR_0"]].byte_offset, 20);
+        let buffer = std::fs::read(format!("{}/buffer0.bin", output_dir)).unwrap();
+        assert_eq!(buffer[20..24], [0x10 + 64, 0x20, 0xFF, 0xFF]);
+
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(command([0x01, 0x05, 0x00, 0x40, 0x06, 0x00, 0x12, 0x30]),
+            F3dex::Matrix { projection: true, load: false, push: true, size: 0x40, address: 0x06001230 });
+        command([0x01, 0x02, 0x00, 0x40, 0x80, 0x12, 0x34, 0x50]);
+        assert_eq!(command([0x03, 0x86, 0x00, 0x10, 0x06, 0x00, 0x00, 0x80]),
+            F3dex::MoveMem { index: 0x86, size: 0x10, address: 0x06000080 });
+        assert_eq!(command([0xB0, 0x00, 0xF0, 0x06, 0x00, 0x00, 0x12, 0x34]), F3dex::BranchZ { vertex: 3, z: 0x1234 });
+        assert_eq!(command([0xB2, 0x14, 0x00, 0x0A, 0x12, 0x34, 0x56, 0x78]),
+            F3dex::ModifyVertex { vertex: 5, location: 0x14, value: 0x12345678 });
+        assert_eq!(command([0xB3, 0x00, 0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF]), F3dex::RdpHalf2(0xDEADBEEF));
+        assert_eq!(command([0xB4, 0x00, 0x00, 0x00, 0x06, 0x00, 0x10, 0x00]), F3dex::RdpHalf1(0x06001000));
+        assert_eq!(command([0xBC, 0x00, 0x00, 0x02, 0x80, 0x00, 0x00, 0x40]),
+            F3dex::MoveWord { index: 2, offset: 0, value: 0x80000040 });
+        command([0xBC, 0x00, 0x08, 0x08, 0x19, 0x00, 0xE8, 0x00]);
+        assert_eq!(command([0xBE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1E]), F3dex::CullDisplayList { vstart: 0, vend: 15 });
+        assert_eq!(command([0xE8, 0, 0, 0, 0, 0, 0, 0]), F3dex::RdpTileSync);
+        assert_eq!(command([0xE9, 0, 0, 0, 0, 0, 0, 0]), F3dex::RdpFullSync);
+        assert_eq!(command([0xF7, 0x00, 0x00, 0x00, 0xFF, 0xFE, 0xFF, 0xFE]), F3dex::SetFillColor(0xFFFEFFFE));
+        assert_eq!(command([0xF8, 0x00, 0x00, 0x00, 0x10, 0x20, 0x30, 0xFF]), F3dex::SetFogColor { r: 0x10, g: 0x20, b: 0x30, a: 0xFF });
+        assert_eq!(command([0xF9, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80]), F3dex::SetBlendColor { r: 0, g: 0, b: 0, a: 0x80 });
+        assert_eq!(command([0xFA, 0x00, 0x7F, 0x80, 0xFF, 0x80, 0x40, 0xC0]),
+            F3dex::SetPrimColor { min_level: 0x7F, lod_fraction: 0x80, r: 0xFF, g: 0x80, b: 0x40, a: 0xC0 });
+        assert_eq!(command([0xFB, 0x00, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44]), F3dex::SetEnvColor { r: 0x11, g: 0x22, b: 0x33, a: 0x44 });
+    }
+
+    #[test]
+    fn normals() {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        for normal in [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [s, -s, 0.0], [0.48, 0.6, -0.64]] {
+            let mut v = vertex(0, 0, 0);
+            v.set_normal(normal);
+            assert!(close(v.normal().unwrap(), normal), "{:?} -> {:?}", normal, v.normal());
+        }
+
+        // -1 is encoded as -127, -128 reads as -1 too
+        let mut v = vertex(0, 0, 0);
+        v.set_normal([0.0, 0.0, -1.0]);
+        assert_eq!((v.r, v.g, v.b), (0, 0, 0x81));
+        assert_eq!(vertex(0, 0x80, 0).normal(), Some([0.0, -1.0, 0.0]));
+        assert_eq!(vertex(0x80, 0x80, 0x80).normal().map(|n| close(n, [-0.577, -0.577, -0.577])), Some(true));
+
+        // not scaled before encoding
+        let mut v = vertex(0, 0, 0);
+        v.set_normal([0.0, 5.0, 0.0]);
+        assert_eq!((v.r, v.g, v.b), (0, 0x7F, 0));
+
+        // zero normals have no direction
+        assert_eq!(vertex(0, 0, 0).normal(), None);
+        let mut v = vertex(1, 2, 3);
+        v.set_normal([0.0, 0.0, 0.0]);
+        assert_eq!((v.r, v.g, v.b), (0, 0, 0));
+    }
+
+    #[test]
+    fn diff_tolerance() {
+        let old = model(vec![]);
+        let mut new = model(vec![]);
+        new.vertex_data.vertices[0].position = Vector3 { x: 3, y: 0, z: -4 };
+
+        let moved = |new: &Model, tolerance| old.diff(new, tolerance).vertices;
+        assert_eq!(moved(&new, 0.0), [Change {
+            change: ChangeKind::Moved, what: "vertex 0".to_string(), position: Some([3.0, 0.0, -4.0]), delta: Some([3.0, 0.0, -4.0]), fields: vec![],
+        }]);
+        assert_eq!(moved(&new, 4.9).len(), 1);
+        // up to the tolerance, included
+        assert!(moved(&new, 5.0).is_empty());
+        assert!(old.diff(&new, 5.0).is_empty());
+
+        // other changes are still reported
+        new.vertex_data.vertices[0].a = 0x80;
+        let changes = moved(&new, 5.0);
+        assert_eq!((changes[0].change, changes[0].delta, changes[0].fields.len()), (ChangeKind::Modified, None, 1));
+        assert_eq!(changes[0].fields[0].field, "rgba[3]");
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{ Serialize, Deserialize };
+use std::collections::HashMap;
+
+const BUILTIN_NAMES: &str = include_str!("object_names.yaml");
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ObjectNames {
+    #[serde(default)]
+    pub complex: HashMap<u16, String>,
+    #[serde(default)]
+    pub small: HashMap<u16, String>,
+}
+
+impl ObjectNames {
+    pub fn builtin() -> ObjectNames {
+        serde_yaml::from_str(BUILTIN_NAMES).unwrap()
+    }
+
+    // entries of the user file replace the built-in ones with the same ID
+    pub fn load(filename: Option<&str>) -> ObjectNames {
+        let mut names = ObjectNames::builtin();
+
+        if let Some(filename) = filename {
+            let f = std::fs::File::open(filename).unwrap();
+            let user: ObjectNames = serde_yaml::from_reader(f).unwrap();
+            names.complex.extend(user.complex);
+            names.small.extend(user.small);
+        }
+
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::ObjectDefinitions;
+
+    // every named ID is decoded as the kind of object its name is looked up for
+    #[test]
+    fn builtin_names() {
+        let names = ObjectNames::builtin();
+        let definitions = ObjectDefinitions::builtin();
+        assert_eq!(names.complex.get(&0x0046).map(|name| name.as_str()), Some("Jiggy"));
+        assert_eq!(names.small.get(&0x0164).map(|name| name.as_str()), Some("Note"));
+
+        for id in names.complex.keys() {
+            assert!(definitions.complex_class(*id).is_some(), "{:#06X}", id);
+        }
+        for id in names.small.keys() {
+            assert!(definitions.small_class(*id).is_some(), "{:#06X}", id);
+        }
+    }
+}
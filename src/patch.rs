@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::io;
+
+// IPS records can't start at this offset, it reads as the end of the patch
+const IPS_EOF: usize = 0x454F46;
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+// unchanged bytes shorter than a record header are included in the record
+const IPS_MERGE_GAP: usize = 5;
+
+// source positions are indexed every BPS_STRIDE bytes, a shifted copy is found within that many bytes
+const BPS_STRIDE: usize = 32;
+const BPS_KEY_SIZE: usize = 8;
+const BPS_MIN_MATCH: usize = 4;
+
+pub fn ips(source: &[u8], target: &[u8]) -> io::Result<Vec<u8>> {
+    if target.len() < source.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "IPS patches can't make a file smaller"));
+    }
+
+    let differs = |i: usize| source.get(i) != target.get(i);
+    let mut patch = b"PATCH".to_vec();
+
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+
+        let mut start = i;
+        if start == IPS_EOF {
+            start -= 1;
+        }
+        if start > IPS_MAX_OFFSET {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "IPS patches can't change bytes after 16 MB"));
+        }
+
+        // up to the last difference not followed by a longer run of unchanged bytes
+        let mut end = i + 1;
+        let mut j = end;
+        while j < target.len() && j - start < IPS_MAX_RECORD {
+            if differs(j) {
+                end = j + 1;
+            } else if j - end >= IPS_MERGE_GAP {
+                break;
+            }
+            j += 1;
+        }
+
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&target[start..end]);
+        i = end;
+    }
+
+    patch.extend_from_slice(b"EOF");
+    Ok(patch)
+}
+
+// the target is made of source reads (same position), source copies (from anywhere in the source),
+// and target reads (new bytes)
+pub fn bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut index: HashMap<&[u8], usize> = HashMap::new();
+    for j in (0..source.len().saturating_sub(BPS_KEY_SIZE - 1)).step_by(BPS_STRIDE) {
+        index.entry(&source[j..j + BPS_KEY_SIZE]).or_insert(j);
+    }
+
+    let match_length = |s: usize, t: usize| {
+        source[s.min(source.len())..].iter().zip(&target[t..]).take_while(|(a, b)| a == b).count()
+    };
+
+    let mut patch = b"BPS1".to_vec();
+    write_number(&mut patch, source.len() as u64);
+    write_number(&mut patch, target.len() as u64);
+    write_number(&mut patch, 0);
+
+    let mut literal_start = 0;
+    let mut source_relative = 0usize;
+    let mut i = 0;
+    while i < target.len() {
+        let read = match_length(i, i);
+        let (copy_from, copy) = [Some(source_relative), target.get(i..i + BPS_KEY_SIZE).and_then(|key| index.get(key).copied())]
+            .into_iter().flatten()
+            .map(|j| (j, match_length(j, i)))
+            .max_by_key(|(_, length)| *length)
+            .unwrap_or((0, 0));
+
+        if read >= BPS_MIN_MATCH && read >= copy {
+            write_target_read(&mut patch, &target[literal_start..i]);
+            write_number(&mut patch, (read as u64 - 1) << 2);
+            i += read;
+            literal_start = i;
+        } else if copy >= BPS_MIN_MATCH {
+            write_target_read(&mut patch, &target[literal_start..i]);
+            write_number(&mut patch, ((copy as u64 - 1) << 2) | 2);
+            let offset = copy_from as i64 - source_relative as i64;
+            write_number(&mut patch, (offset.unsigned_abs() << 1) | (offset < 0) as u64);
+            source_relative = copy_from + copy;
+            i += copy;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    write_target_read(&mut patch, &target[literal_start..]);
+
+    let mut checksums = vec![];
+    for data in [source, target] {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        checksums.push(crc.sum());
+    }
+    patch.extend_from_slice(&checksums[0].to_le_bytes());
+    patch.extend_from_slice(&checksums[1].to_le_bytes());
+
+    let mut crc = flate2::Crc::new();
+    crc.update(&patch);
+    patch.extend_from_slice(&crc.sum().to_le_bytes());
+    patch
+}
+
+fn write_target_read(patch: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        write_number(patch, ((bytes.len() as u64 - 1) << 2) | 1);
+        patch.extend_from_slice(bytes);
+    }
+}
+
+// 7 bits per byte, the last one has the high bit set
+fn write_number(patch: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | bits);
+            break;
+        }
+        patch.push(bits);
+        value -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(length: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        crc.sum()
+    }
+
+    fn apply_ips(source: &[u8], patch: &[u8]) -> Vec<u8> {
+        assert_eq!(&patch[0..5], b"PATCH");
+        let mut target = source.to_vec();
+        let mut i = 5;
+        while &patch[i..i + 3] != b"EOF" {
+            let offset = u32::from_be_bytes([0, patch[i], patch[i + 1], patch[i + 2]]) as usize;
+            let length = u16::from_be_bytes([patch[i + 3], patch[i + 4]]) as usize;
+            // no RLE records are written
+            assert!(length > 0);
+            if target.len() < offset + length {
+                target.resize(offset + length, 0);
+            }
+            target[offset..offset + length].copy_from_slice(&patch[i + 5..i + 5 + length]);
+            i += 5 + length;
+        }
+        assert_eq!(i + 3, patch.len());
+        target
+    }
+
+    fn read_number(patch: &[u8], i: &mut usize) -> u64 {
+        let (mut value, mut shift) = (0u64, 1u64);
+        loop {
+            let byte = patch[*i];
+            *i += 1;
+            value += (byte & 0x7F) as u64 * shift;
+            if byte & 0x80 != 0 {
+                return value;
+            }
+            shift <<= 7;
+            value += shift;
+        }
+    }
+
+    fn apply_bps(source: &[u8], patch: &[u8]) -> Vec<u8> {
+        assert_eq!(&patch[0..4], b"BPS1");
+        let footer = patch.len() - 12;
+        let checksum = |at: usize| u32::from_le_bytes(patch[at..at + 4].try_into().unwrap());
+        assert_eq!(checksum(footer + 8), crc32(&patch[..footer + 8]));
+        assert_eq!(checksum(footer), crc32(source));
+
+        let mut i = 4;
+        assert_eq!(read_number(patch, &mut i) as usize, source.len());
+        let target_size = read_number(patch, &mut i) as usize;
+        let metadata = read_number(patch, &mut i) as usize;
+        i += metadata;
+
+        let mut target: Vec<u8> = vec![];
+        let (mut source_relative, mut target_relative) = (0i64, 0i64);
+        while i < footer {
+            let data = read_number(patch, &mut i);
+            let length = (data >> 2) as usize + 1;
+            match data & 3 {
+                0 => {
+                    let start = target.len();
+                    target.extend_from_slice(&source[start..start + length]);
+                },
+                1 => {
+                    target.extend_from_slice(&patch[i..i + length]);
+                    i += length;
+                },
+                2 => {
+                    let offset = read_number(patch, &mut i);
+                    source_relative += if offset & 1 == 1 { -((offset >> 1) as i64) } else { (offset >> 1) as i64 };
+                    let start = source_relative as usize;
+                    target.extend_from_slice(&source[start..start + length]);
+                    source_relative += length as i64;
+                },
+                _ => {
+                    let offset = read_number(patch, &mut i);
+                    target_relative += if offset & 1 == 1 { -((offset >> 1) as i64) } else { (offset >> 1) as i64 };
+                    for _ in 0..length {
+                        target.push(target[target_relative as usize]);
+                        target_relative += 1;
+                    }
+                },
+            }
+        }
+
+        assert_eq!(target.len(), target_size);
+        assert_eq!(checksum(footer + 4), crc32(&target));
+        target
+    }
+
+    // like a patched ROM: a new checksum, an asset that grew and the following ones shifted
+    fn patched(source: &[u8]) -> Vec<u8> {
+        let mut target = source[..0x8000].to_vec();
+        target[0x10..0x18].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        target.extend(noise(0x300, 99));
+        target.extend_from_slice(&source[0x8000..source.len() - 0x300]);
+        target
+    }
+
+    #[test]
+    fn ips_round_trip() {
+        let source = noise(0x20000, 1);
+        let target = patched(&source);
+        assert_eq!(apply_ips(&source, &ips(&source, &target).unwrap()), target);
+
+        // bytes added at the end
+        let mut longer = source.clone();
+        longer.extend_from_slice(&[0xAB; 0x20]);
+        assert_eq!(apply_ips(&source, &ips(&source, &longer).unwrap()), longer);
+
+        assert_eq!(ips(&source, &source).unwrap(), b"PATCHEOF");
+        assert!(ips(&source, &source[1..]).is_err());
+    }
+
+    #[test]
+    fn ips_merges_small_gaps() {
+        let source = vec![0u8; 0x100];
+        let mut target = source.clone();
+        target[0x10] = 1;
+        target[0x14] = 1;
+        target[0x40] = 1;
+        let patch = ips(&source, &target).unwrap();
+        // two records: 0x10..0x15 and 0x40
+        assert_eq!(patch.len(), 5 + (5 + 5) + (5 + 1) + 3);
+        assert_eq!(apply_ips(&source, &patch), target);
+    }
+
+    #[test]
+    fn ips_record_at_eof_offset() {
+        let source = vec![0u8; IPS_EOF + 0x10];
+        let mut target = source.clone();
+        target[IPS_EOF] = 1;
+        let patch = ips(&source, &target).unwrap();
+        assert_eq!(&patch[5..8], &(IPS_EOF as u32 - 1).to_be_bytes()[1..]);
+        assert_eq!(apply_ips(&source, &patch), target);
+    }
+
+    #[test]
+    fn ips_too_big() {
+        let source = vec![0u8; IPS_MAX_OFFSET + 0x10];
+        let mut target = source.clone();
+        target[IPS_MAX_OFFSET + 1] = 1;
+        assert!(ips(&source, &target).is_err());
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let source = noise(0x20000, 1);
+        let target = patched(&source);
+        let patch = bps(&source, &target);
+        assert_eq!(apply_bps(&source, &patch), target);
+        // the shifted data is copied from the source, not stored in the patch
+        assert!(patch.len() < 0x800);
+
+        let unrelated = noise(0x1000, 2);
+        assert_eq!(apply_bps(&source, &bps(&source, &unrelated)), unrelated);
+        assert_eq!(apply_bps(&source, &bps(&source, &[])), Vec::<u8>::new());
+        assert_eq!(apply_bps(&[], &bps(&[], &unrelated)), unrelated);
+    }
+
+    #[test]
+    fn numbers() {
+        for value in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4080, u32::MAX as u64] {
+            let mut encoded = vec![];
+            write_number(&mut encoded, value);
+            let mut i = 0;
+            assert_eq!(read_number(&encoded, &mut i), value);
+            assert_eq!(i, encoded.len());
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{ self, Read, Take, Write };
+use byteorder::{ BigEndian, ReadBytesExt, WriteBytesExt };
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+// compressed assets start with this magic and their decompressed size, followed by a raw deflate stream
+pub const MAGIC: [u8; 2] = [0x11, 0x72];
+pub const HEADER_SIZE: usize = 6;
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data[0..2] == MAGIC
+}
+
+// reads the decompressed contents of a compressed asset
+pub struct Decoder<R: Read> {
+    inner: Take<DeflateDecoder<R>>,
+    size: u64,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(mut reader: R) -> io::Result<Decoder<R>> {
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a compressed asset"));
+        }
+
+        let size = reader.read_u32::<BigEndian>()? as u64;
+        Ok(Decoder {
+            inner: DeflateDecoder::new(reader).take(size),
+            size,
+        })
+    }
+
+    // of the decompressed contents, from the header
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        // the stream can't end before the size given in the header
+        if read == 0 && !buf.is_empty() && self.inner.limit() > 0 {
+            let message = format!("decompressed {} bytes instead of {}", self.size - self.inner.limit(), self.size);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+        }
+        Ok(read)
+    }
+}
+
+// writes the given number of bytes as a compressed asset
+pub struct Encoder<W: Write> {
+    inner: DeflateEncoder<W>,
+    size: u64,
+    written: u64,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(mut writer: W, size: usize) -> io::Result<Encoder<W>> {
+        let size = u32::try_from(size).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "asset too big to be compressed"))?;
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(size)?;
+
+        Ok(Encoder {
+            inner: DeflateEncoder::new(writer, Compression::best()),
+            size: size as u64,
+            written: 0,
+        })
+    }
+
+    // fails if fewer bytes than the size given in the header were written
+    pub fn finish(self) -> io::Result<W> {
+        if self.written != self.size {
+            let message = format!("wrote {} bytes instead of {}", self.written, self.size);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        self.inner.finish()
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() as u64 > self.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("more than the {} bytes given in the header", self.size)));
+        }
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoder = Decoder::new(data)?;
+    let mut output = Vec::with_capacity(decoder.size());
+    decoder.read_to_end(&mut output)?;
+    Ok(output)
+}
+
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(vec![], data.len())?;
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // incompressible, from a linear congruential generator
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x12345678u32;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data).unwrap();
+        assert!(is_compressed(&compressed));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(&[]);
+        round_trip(&[0x42]);
+        round_trip(&noise(0x1000));
+        round_trip(&b"Banjo-Kazooie ".repeat(0x10000));
+    }
+
+    #[test]
+    fn repetitive_data_shrinks() {
+        let data = vec![0u8; 0x10000];
+        assert!(compress(&data).unwrap().len() < 0x100);
+    }
+
+    #[test]
+    fn header() {
+        let compressed = compress(&vec![7u8; 0x12345]).unwrap();
+        assert_eq!(compressed[0..2], [0x11, 0x72]);
+        assert_eq!(compressed[2..6], [0x00, 0x01, 0x23, 0x45]);
+        assert_eq!(Decoder::new(compressed.as_slice()).unwrap().size(), 0x12345);
+    }
+
+    #[test]
+    fn not_compressed() {
+        assert!(!is_compressed(&[0x11, 0x72, 0, 0]));
+        assert!(!is_compressed(&[0x11, 0x73, 0, 0, 0, 0]));
+        let error = Decoder::new([0x11, 0x73, 0, 0, 0, 0].as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    // io::copy through readers and writers that only take a few bytes at a time
+    struct Trickle<T>(T);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf.len().min(3);
+            self.0.read(&mut buf[..length])
+        }
+    }
+
+    impl<W: Write> Write for Trickle<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let length = buf.len().min(5);
+            self.0.write(&buf[..length])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let data = [noise(0x800), vec![0xAA; 0x800]].concat();
+
+        let mut encoder = Trickle(Encoder::new(vec![], data.len()).unwrap());
+        io::copy(&mut Trickle(data.as_slice()), &mut encoder).unwrap();
+        let compressed = encoder.0.finish().unwrap();
+        assert_eq!(compressed, compress(&data).unwrap());
+
+        let mut decoder = Trickle(Decoder::new(Trickle(compressed.as_slice())).unwrap());
+        let mut output = vec![];
+        io::copy(&mut decoder, &mut Trickle(&mut output)).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let compressed = compress(&noise(0x1000)).unwrap();
+        let truncated = &compressed[..compressed.len() / 2];
+        let error = decompress(truncated).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn size_bigger_than_the_stream() {
+        let mut compressed = compress(&[1, 2, 3]).unwrap();
+        compressed[5] = 4;
+        let error = decompress(&compressed).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn short_write() {
+        let mut encoder = Encoder::new(vec![], 4).unwrap();
+        encoder.write_all(&[1, 2, 3]).unwrap();
+        let error = encoder.finish().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn long_write() {
+        let mut encoder = Encoder::new(vec![], 4).unwrap();
+        encoder.write_all(&[1, 2, 3]).unwrap();
+        let error = encoder.write_all(&[4, 5]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        encoder.write_all(&[4]).unwrap();
+        encoder.finish().unwrap();
+    }
+}
@@ -0,0 +1,830 @@
+use serde::{ Serialize, Deserialize };
+
+// decoded values of G_SETOTHERMODE_L/H and G_SETCOMBINE.
+// every field can be encoded back into the exact same bits.
+
+// G_SETGEOMETRYMODE flags
+pub const G_CULL_BACK: u32 = 0x00002000;
+pub const G_LIGHTING: u32 = 0x00020000;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum AlphaCompare {
+    None,
+    Threshold,
+    Reserved,
+    Dither,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum ZSource {
+    Pixel,
+    Primitive,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum CoverageDest {
+    Clamp,
+    Wrap,
+    Full,
+    Save,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum ZMode {
+    Opaque,
+    Interpenetrating,
+    Translucent,
+    Decal,
+}
+
+// P and M inputs of the blender
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum BlenderColour {
+    Input,
+    Memory,
+    Blend,
+    Fog,
+}
+
+// A input of the blender
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum BlenderAlpha {
+    Input,
+    Fog,
+    Shade,
+    Zero,
+}
+
+// B input of the blender
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum BlenderInverseAlpha {
+    OneMinusA,
+    Memory,
+    One,
+    Zero,
+}
+
+// (p * a + m * b) / (a + b)
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct BlenderCycle {
+    pub p: BlenderColour,
+    pub a: BlenderAlpha,
+    pub m: BlenderColour,
+    pub b: BlenderInverseAlpha,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct RenderMode {
+    pub anti_aliasing: bool,
+    pub z_compare: bool,
+    pub z_update: bool,
+    pub image_read: bool,
+    pub clear_on_coverage: bool,
+    pub coverage_dest: CoverageDest,
+    pub z_mode: ZMode,
+    pub coverage_x_alpha: bool,
+    pub alpha_coverage_select: bool,
+    pub force_blend: bool,
+    pub tex_edge: bool,
+    pub cycle1: BlenderCycle,
+    pub cycle2: BlenderCycle,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Default, Clone)]
+pub struct OtherModeL {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpha_compare: Option<AlphaCompare>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub z_source: Option<ZSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render_mode: Option<RenderMode>,
+    // bits set outside of the range of the command, written back as they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unused_bits: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum AlphaDither {
+    Pattern,
+    NotPattern,
+    Noise,
+    Disable,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum RgbDither {
+    MagicSquare,
+    Bayer,
+    Noise,
+    Disable,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum TextureConvert {
+    Conv,
+    FiltConv,
+    Filt,
+    Other(u8),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum TextureFilter {
+    Point,
+    Reserved,
+    Bilerp,
+    Average,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum TextureLut {
+    None,
+    Reserved,
+    Rgba16,
+    Ia16,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum TextureDetail {
+    Clamp,
+    Sharpen,
+    Detail,
+    Reserved,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum CycleType {
+    OneCycle,
+    TwoCycle,
+    Copy,
+    Fill,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Default, Clone)]
+pub struct OtherModeH {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blend_mask: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpha_dither: Option<AlphaDither>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rgb_dither: Option<RgbDither>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combine_key: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_convert: Option<TextureConvert>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_filter: Option<TextureFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_lut: Option<TextureLut>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_lod: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_detail: Option<TextureDetail>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_perspective: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cycle_type: Option<CycleType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour_dither: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_primitive: Option<bool>,
+    // bits set outside of the range of the command or in bits 24-31, written back as they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unused_bits: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum CombinerInput {
+    Combined,
+    Texel0,
+    Texel1,
+    Primitive,
+    Shade,
+    Environment,
+    One,
+    Zero,
+    Noise,
+    Center,
+    K4,
+    Scale,
+    CombinedAlpha,
+    Texel0Alpha,
+    Texel1Alpha,
+    PrimitiveAlpha,
+    ShadeAlpha,
+    EnvironmentAlpha,
+    LodFraction,
+    PrimLodFraction,
+    K5,
+    // a value the hardware reads as zero but that isn't the usual G_CCMUX_0
+    Other(u8),
+}
+
+// (a - b) * c + d
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct CombinerEquation {
+    pub a: CombinerInput,
+    pub b: CombinerInput,
+    pub c: CombinerInput,
+    pub d: CombinerInput,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct CombinerCycle {
+    pub colour: CombinerEquation,
+    pub alpha: CombinerEquation,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct Combiner {
+    pub cycle1: CombinerCycle,
+    pub cycle2: CombinerCycle,
+}
+
+fn get_bits(value: u32, shift: u32, width: u32) -> u32 {
+    (value >> shift) & ((1 << width) - 1)
+}
+
+fn mask(shift: u8, length: u8) -> u32 {
+    let bits = if length >= 32 { u32::MAX } else { (1u32 << length) - 1 };
+    bits.checked_shl(shift as u32).unwrap_or(0)
+}
+
+// replaces the bits set by a G_SETOTHERMODE_L/H command in the current other mode
+pub fn update_other_mode(current: u32, shift: u8, length: u8, mode: u32) -> u32 {
+    (current & !mask(shift, length)) + (mode & mask(shift, length))
+}
+
+// does the field [field_shift, field_shift + field_width) intersect the bits set by the command?
+fn overlaps(shift: u8, length: u8, field_shift: u32, field_width: u32) -> bool {
+    (field_shift < shift as u32 + length as u32) && (shift as u32) < field_shift + field_width
+}
+
+fn nonzero(bits: u32) -> Option<u32> {
+    if bits != 0 { Some(bits) } else { None }
+}
+
+fn bool_bit(value: bool, shift: u32) -> u32 {
+    if value { 1 << shift } else { 0 }
+}
+
+impl AlphaCompare {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::None,
+            1 => Self::Threshold,
+            2 => Self::Reserved,
+            _ => Self::Dither,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl ZSource {
+    fn from_bits(bits: u32) -> Self {
+        if bits == 0 { Self::Pixel } else { Self::Primitive }
+    }
+
+    fn bits(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl CoverageDest {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Clamp,
+            1 => Self::Wrap,
+            2 => Self::Full,
+            _ => Self::Save,
+        }
+    }
+}
+
+impl ZMode {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Opaque,
+            1 => Self::Interpenetrating,
+            2 => Self::Translucent,
+            _ => Self::Decal,
+        }
+    }
+}
+
+impl BlenderColour {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Input,
+            1 => Self::Memory,
+            2 => Self::Blend,
+            _ => Self::Fog,
+        }
+    }
+}
+
+impl BlenderAlpha {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Input,
+            1 => Self::Fog,
+            2 => Self::Shade,
+            _ => Self::Zero,
+        }
+    }
+}
+
+impl BlenderInverseAlpha {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::OneMinusA,
+            1 => Self::Memory,
+            2 => Self::One,
+            _ => Self::Zero,
+        }
+    }
+}
+
+impl BlenderCycle {
+    // cycle 1 lives 2 bits above cycle 2
+    fn from_bits(render_mode: u32, shift: u32) -> Self {
+        Self {
+            p: BlenderColour::from_bits(get_bits(render_mode, shift + 12, 2)),
+            a: BlenderAlpha::from_bits(get_bits(render_mode, shift + 8, 2)),
+            m: BlenderColour::from_bits(get_bits(render_mode, shift + 4, 2)),
+            b: BlenderInverseAlpha::from_bits(get_bits(render_mode, shift, 2)),
+        }
+    }
+
+    fn bits(&self, shift: u32) -> u32 {
+        ((self.p as u32) << (shift + 12))
+            + ((self.a as u32) << (shift + 8))
+            + ((self.m as u32) << (shift + 4))
+            + ((self.b as u32) << shift)
+    }
+}
+
+impl RenderMode {
+    fn from_bits(mode: u32) -> Self {
+        Self {
+            anti_aliasing: get_bits(mode, 3, 1) != 0,
+            z_compare: get_bits(mode, 4, 1) != 0,
+            z_update: get_bits(mode, 5, 1) != 0,
+            image_read: get_bits(mode, 6, 1) != 0,
+            clear_on_coverage: get_bits(mode, 7, 1) != 0,
+            coverage_dest: CoverageDest::from_bits(get_bits(mode, 8, 2)),
+            z_mode: ZMode::from_bits(get_bits(mode, 10, 2)),
+            coverage_x_alpha: get_bits(mode, 12, 1) != 0,
+            alpha_coverage_select: get_bits(mode, 13, 1) != 0,
+            force_blend: get_bits(mode, 14, 1) != 0,
+            tex_edge: get_bits(mode, 15, 1) != 0,
+            cycle1: BlenderCycle::from_bits(mode, 18),
+            cycle2: BlenderCycle::from_bits(mode, 16),
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        bool_bit(self.anti_aliasing, 3)
+            + bool_bit(self.z_compare, 4)
+            + bool_bit(self.z_update, 5)
+            + bool_bit(self.image_read, 6)
+            + bool_bit(self.clear_on_coverage, 7)
+            + ((self.coverage_dest as u32) << 8)
+            + ((self.z_mode as u32) << 10)
+            + bool_bit(self.coverage_x_alpha, 12)
+            + bool_bit(self.alpha_coverage_select, 13)
+            + bool_bit(self.force_blend, 14)
+            + bool_bit(self.tex_edge, 15)
+            + self.cycle1.bits(18)
+            + self.cycle2.bits(16)
+    }
+}
+
+impl OtherModeL {
+    pub fn decode(shift: u8, length: u8, mode: u32) -> Self {
+        let mut other_mode = Self {
+            unused_bits: nonzero(mode & !mask(shift, length)),
+            ..Self::default()
+        };
+        if overlaps(shift, length, 0, 2) {
+            other_mode.alpha_compare = Some(AlphaCompare::from_bits(get_bits(mode, 0, 2)));
+        }
+        if overlaps(shift, length, 2, 1) {
+            other_mode.z_source = Some(ZSource::from_bits(get_bits(mode, 2, 1)));
+        }
+        if overlaps(shift, length, 3, 29) {
+            other_mode.render_mode = Some(RenderMode::from_bits(mode));
+        }
+
+        other_mode
+    }
+
+    pub fn encode(&self, shift: u8, length: u8) -> u32 {
+        let mut mode = 0;
+        if let Some(alpha_compare) = &self.alpha_compare {
+            mode += alpha_compare.bits();
+        }
+        if let Some(z_source) = &self.z_source {
+            mode += z_source.bits() << 2;
+        }
+        if let Some(render_mode) = &self.render_mode {
+            mode += render_mode.bits();
+        }
+
+        (mode & mask(shift, length)) | self.unused_bits.unwrap_or(0)
+    }
+}
+
+impl AlphaDither {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Pattern,
+            1 => Self::NotPattern,
+            2 => Self::Noise,
+            _ => Self::Disable,
+        }
+    }
+}
+
+impl RgbDither {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::MagicSquare,
+            1 => Self::Bayer,
+            2 => Self::Noise,
+            _ => Self::Disable,
+        }
+    }
+}
+
+impl TextureConvert {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Conv,
+            5 => Self::FiltConv,
+            6 => Self::Filt,
+            _ => Self::Other(bits as u8),
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        match self {
+            Self::Conv => 0,
+            Self::FiltConv => 5,
+            Self::Filt => 6,
+            Self::Other(bits) => *bits as u32,
+        }
+    }
+}
+
+impl TextureFilter {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Point,
+            1 => Self::Reserved,
+            2 => Self::Bilerp,
+            _ => Self::Average,
+        }
+    }
+}
+
+impl TextureLut {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::None,
+            1 => Self::Reserved,
+            2 => Self::Rgba16,
+            _ => Self::Ia16,
+        }
+    }
+}
+
+impl TextureDetail {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::Clamp,
+            1 => Self::Sharpen,
+            2 => Self::Detail,
+            _ => Self::Reserved,
+        }
+    }
+}
+
+impl CycleType {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::OneCycle,
+            1 => Self::TwoCycle,
+            2 => Self::Copy,
+            _ => Self::Fill,
+        }
+    }
+}
+
+impl OtherModeH {
+    pub fn decode(shift: u8, length: u8, mode: u32) -> Self {
+        // bits 24-31 aren't used
+        let unused_bits = nonzero(mode & (!mask(shift, length) | 0xFF000000));
+
+        let field = |field_shift: u32, field_width: u32| {
+            if overlaps(shift, length, field_shift, field_width) {
+                Some(get_bits(mode, field_shift, field_width))
+            } else {
+                None
+            }
+        };
+
+        Self {
+            blend_mask: field(0, 4).map(|b| b as u8),
+            alpha_dither: field(4, 2).map(AlphaDither::from_bits),
+            rgb_dither: field(6, 2).map(RgbDither::from_bits),
+            combine_key: field(8, 1).map(|b| b != 0),
+            texture_convert: field(9, 3).map(TextureConvert::from_bits),
+            texture_filter: field(12, 2).map(TextureFilter::from_bits),
+            texture_lut: field(14, 2).map(TextureLut::from_bits),
+            texture_lod: field(16, 1).map(|b| b != 0),
+            texture_detail: field(17, 2).map(TextureDetail::from_bits),
+            texture_perspective: field(19, 1).map(|b| b != 0),
+            cycle_type: field(20, 2).map(CycleType::from_bits),
+            colour_dither: field(22, 1).map(|b| b != 0),
+            one_primitive: field(23, 1).map(|b| b != 0),
+            unused_bits,
+        }
+    }
+
+    pub fn encode(&self, shift: u8, length: u8) -> u32 {
+        let mut mode = 0;
+        if let Some(v) = self.blend_mask { mode += v as u32; }
+        if let Some(v) = self.alpha_dither { mode += (v as u32) << 4; }
+        if let Some(v) = self.rgb_dither { mode += (v as u32) << 6; }
+        if let Some(v) = self.combine_key { mode += bool_bit(v, 8); }
+        if let Some(v) = self.texture_convert { mode += v.bits() << 9; }
+        if let Some(v) = self.texture_filter { mode += (v as u32) << 12; }
+        if let Some(v) = self.texture_lut { mode += (v as u32) << 14; }
+        if let Some(v) = self.texture_lod { mode += bool_bit(v, 16); }
+        if let Some(v) = self.texture_detail { mode += (v as u32) << 17; }
+        if let Some(v) = self.texture_perspective { mode += bool_bit(v, 19); }
+        if let Some(v) = self.cycle_type { mode += (v as u32) << 20; }
+        if let Some(v) = self.colour_dither { mode += bool_bit(v, 22); }
+        if let Some(v) = self.one_primitive { mode += bool_bit(v, 23); }
+
+        (mode & mask(shift, length) & 0x00FFFFFF) | self.unused_bits.unwrap_or(0)
+    }
+}
+
+// which operand of the equation a value comes from. each one
+// has its own width and its own list of valid inputs.
+#[derive(Debug, Copy, Clone)]
+enum CombinerSlot {
+    ColourA,
+    ColourB,
+    ColourC,
+    ColourD,
+    AlphaA,
+    AlphaB,
+    AlphaC,
+    AlphaD,
+}
+
+impl CombinerSlot {
+    fn zero(&self) -> u8 {
+        match self {
+            Self::ColourA | Self::ColourB => 15,
+            Self::ColourC => 31,
+            _ => 7,
+        }
+    }
+}
+
+impl CombinerInput {
+    fn from_bits(slot: CombinerSlot, bits: u32) -> Self {
+        let bits = bits as u8;
+        if bits == slot.zero() {
+            return Self::Zero;
+        }
+
+        match (slot, bits) {
+            (CombinerSlot::AlphaC, 0) => Self::LodFraction,
+            (_, 0) => Self::Combined,
+            (_, 1) => Self::Texel0,
+            (_, 2) => Self::Texel1,
+            (_, 3) => Self::Primitive,
+            (_, 4) => Self::Shade,
+            (_, 5) => Self::Environment,
+            (CombinerSlot::ColourB, 6) => Self::Center,
+            (CombinerSlot::ColourC, 6) => Self::Scale,
+            (CombinerSlot::AlphaC, 6) => Self::PrimLodFraction,
+            (_, 6) => Self::One,
+            (CombinerSlot::ColourA, 7) => Self::Noise,
+            (CombinerSlot::ColourB, 7) => Self::K4,
+            (CombinerSlot::ColourC, 7) => Self::CombinedAlpha,
+            (CombinerSlot::ColourC, 8) => Self::Texel0Alpha,
+            (CombinerSlot::ColourC, 9) => Self::Texel1Alpha,
+            (CombinerSlot::ColourC, 10) => Self::PrimitiveAlpha,
+            (CombinerSlot::ColourC, 11) => Self::ShadeAlpha,
+            (CombinerSlot::ColourC, 12) => Self::EnvironmentAlpha,
+            (CombinerSlot::ColourC, 13) => Self::LodFraction,
+            (CombinerSlot::ColourC, 14) => Self::PrimLodFraction,
+            (CombinerSlot::ColourC, 15) => Self::K5,
+            _ => Self::Other(bits),
+        }
+    }
+
+    fn bits(&self, slot: CombinerSlot) -> u32 {
+        let bits = match (slot, self) {
+            (_, Self::Zero) => slot.zero(),
+            (_, Self::Other(bits)) => *bits,
+            (CombinerSlot::AlphaC, Self::LodFraction) => 0,
+            (CombinerSlot::AlphaC, Self::PrimLodFraction) => 6,
+            (CombinerSlot::AlphaC, Self::Combined) => panic!("{:?} can't be used in {:?}", self, slot),
+            (_, Self::Combined) => 0,
+            (_, Self::Texel0) => 1,
+            (_, Self::Texel1) => 2,
+            (_, Self::Primitive) => 3,
+            (_, Self::Shade) => 4,
+            (_, Self::Environment) => 5,
+            (CombinerSlot::ColourB, Self::Center) => 6,
+            (CombinerSlot::ColourC, Self::Scale) => 6,
+            (CombinerSlot::ColourA | CombinerSlot::ColourD | CombinerSlot::AlphaA
+                | CombinerSlot::AlphaB | CombinerSlot::AlphaD, Self::One) => 6,
+            (CombinerSlot::ColourA, Self::Noise) => 7,
+            (CombinerSlot::ColourB, Self::K4) => 7,
+            (CombinerSlot::ColourC, Self::CombinedAlpha) => 7,
+            (CombinerSlot::ColourC, Self::Texel0Alpha) => 8,
+            (CombinerSlot::ColourC, Self::Texel1Alpha) => 9,
+            (CombinerSlot::ColourC, Self::PrimitiveAlpha) => 10,
+            (CombinerSlot::ColourC, Self::ShadeAlpha) => 11,
+            (CombinerSlot::ColourC, Self::EnvironmentAlpha) => 12,
+            (CombinerSlot::ColourC, Self::LodFraction) => 13,
+            (CombinerSlot::ColourC, Self::PrimLodFraction) => 14,
+            (CombinerSlot::ColourC, Self::K5) => 15,
+            _ => panic!("{:?} can't be used in {:?}", self, slot),
+        };
+
+        bits as u32
+    }
+}
+
+impl Combiner {
+    // [aaaa] [ccccc] [eee] [ggg] [iiii] [kkkkk] [bbbb] [jjjj] [mmm] [ooo] [ddd] [fff] [hhh] [lll] [nnn] [ppp]
+    pub fn decode(w0: u32, w1: u32) -> Self {
+        Self {
+            cycle1: CombinerCycle {
+                colour: CombinerEquation {
+                    a: CombinerInput::from_bits(CombinerSlot::ColourA, get_bits(w0, 20, 4)),
+                    b: CombinerInput::from_bits(CombinerSlot::ColourB, get_bits(w1, 28, 4)),
+                    c: CombinerInput::from_bits(CombinerSlot::ColourC, get_bits(w0, 15, 5)),
+                    d: CombinerInput::from_bits(CombinerSlot::ColourD, get_bits(w1, 15, 3)),
+                },
+                alpha: CombinerEquation {
+                    a: CombinerInput::from_bits(CombinerSlot::AlphaA, get_bits(w0, 12, 3)),
+                    b: CombinerInput::from_bits(CombinerSlot::AlphaB, get_bits(w1, 12, 3)),
+                    c: CombinerInput::from_bits(CombinerSlot::AlphaC, get_bits(w0, 9, 3)),
+                    d: CombinerInput::from_bits(CombinerSlot::AlphaD, get_bits(w1, 9, 3)),
+                },
+            },
+            cycle2: CombinerCycle {
+                colour: CombinerEquation {
+                    a: CombinerInput::from_bits(CombinerSlot::ColourA, get_bits(w0, 5, 4)),
+                    b: CombinerInput::from_bits(CombinerSlot::ColourB, get_bits(w1, 24, 4)),
+                    c: CombinerInput::from_bits(CombinerSlot::ColourC, get_bits(w0, 0, 5)),
+                    d: CombinerInput::from_bits(CombinerSlot::ColourD, get_bits(w1, 6, 3)),
+                },
+                alpha: CombinerEquation {
+                    a: CombinerInput::from_bits(CombinerSlot::AlphaA, get_bits(w1, 21, 3)),
+                    b: CombinerInput::from_bits(CombinerSlot::AlphaB, get_bits(w1, 3, 3)),
+                    c: CombinerInput::from_bits(CombinerSlot::AlphaC, get_bits(w1, 18, 3)),
+                    d: CombinerInput::from_bits(CombinerSlot::AlphaD, get_bits(w1, 0, 3)),
+                },
+            },
+        }
+    }
+
+    // returns the lower 24 bits of the first word and the second word
+    pub fn encode(&self) -> (u32, u32) {
+        let c1 = &self.cycle1;
+        let c2 = &self.cycle2;
+
+        let w0 = (c1.colour.a.bits(CombinerSlot::ColourA) << 20)
+            + (c1.colour.c.bits(CombinerSlot::ColourC) << 15)
+            + (c1.alpha.a.bits(CombinerSlot::AlphaA) << 12)
+            + (c1.alpha.c.bits(CombinerSlot::AlphaC) << 9)
+            + (c2.colour.a.bits(CombinerSlot::ColourA) << 5)
+            + c2.colour.c.bits(CombinerSlot::ColourC);
+        let w1 = (c1.colour.b.bits(CombinerSlot::ColourB) << 28)
+            + (c2.colour.b.bits(CombinerSlot::ColourB) << 24)
+            + (c2.alpha.a.bits(CombinerSlot::AlphaA) << 21)
+            + (c2.alpha.c.bits(CombinerSlot::AlphaC) << 18)
+            + (c1.colour.d.bits(CombinerSlot::ColourD) << 15)
+            + (c1.alpha.b.bits(CombinerSlot::AlphaB) << 12)
+            + (c1.alpha.d.bits(CombinerSlot::AlphaD) << 9)
+            + (c2.colour.d.bits(CombinerSlot::ColourD) << 6)
+            + (c2.alpha.b.bits(CombinerSlot::AlphaB) << 3)
+            + c2.alpha.d.bits(CombinerSlot::AlphaD);
+
+        (w0, w1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // G_SETOTHERMODE_L/H commands: the shift and length from the first word, the mode
+    fn round_trip_l(shift: u8, length: u8, mode: u32) -> OtherModeL {
+        let decoded = OtherModeL::decode(shift, length, mode);
+        assert_eq!(decoded.encode(shift, length), mode, "{:?}", decoded);
+        decoded
+    }
+
+    fn round_trip_h(shift: u8, length: u8, mode: u32) -> OtherModeH {
+        let decoded = OtherModeH::decode(shift, length, mode);
+        assert_eq!(decoded.encode(shift, length), mode, "{:?}", decoded);
+        decoded
+    }
+
+    #[test]
+    fn other_mode_l() {
+        // G_RM_AA_ZB_OPA_SURF, G_RM_AA_ZB_OPA_SURF2
+        let mode = round_trip_l(3, 29, 0x00552078);
+        let render_mode = mode.render_mode.unwrap();
+        assert!(render_mode.anti_aliasing && render_mode.z_compare && render_mode.z_update);
+        assert_eq!(render_mode.z_mode, ZMode::Opaque);
+        assert_eq!((render_mode.cycle1.m, render_mode.cycle1.b), (BlenderColour::Memory, BlenderInverseAlpha::Memory));
+        assert_eq!(mode.alpha_compare, None);
+
+        // G_AC_THRESHOLD, G_ZS_PRIM
+        assert_eq!(round_trip_l(0, 2, 0x00000001).alpha_compare, Some(AlphaCompare::Threshold));
+        assert_eq!(round_trip_l(2, 1, 0x00000004).z_source, Some(ZSource::Primitive));
+        assert_eq!(round_trip_l(2, 1, 0x00000000).z_source, Some(ZSource::Pixel));
+
+        // G_RM_ZB_XLU_SURF, G_RM_ZB_XLU_SURF2
+        round_trip_l(3, 29, 0x00504DD8);
+        // a single bit of the render mode, the whole word
+        round_trip_l(4, 1, 0x00000010);
+        round_trip_l(0, 32, 0xC8112078);
+
+        // bits outside of the range are kept
+        let mode = round_trip_l(0, 2, 0x00552079);
+        assert_eq!(mode.unused_bits, Some(0x00552078));
+        assert_eq!(round_trip_l(3, 29, 0x00552078).unused_bits, None);
+    }
+
+    #[test]
+    fn other_mode_h() {
+        // G_CYC_1CYCLE, G_CYC_2CYCLE, G_TP_PERSP, G_TF_BILERP, G_TC_FILT
+        assert_eq!(round_trip_h(20, 2, 0x00000000).cycle_type, Some(CycleType::OneCycle));
+        assert_eq!(round_trip_h(20, 2, 0x00100000).cycle_type, Some(CycleType::TwoCycle));
+        assert_eq!(round_trip_h(19, 1, 0x00080000).texture_perspective, Some(true));
+        assert_eq!(round_trip_h(12, 2, 0x00002000).texture_filter, Some(TextureFilter::Bilerp));
+        assert_eq!(round_trip_h(9, 3, 0x00000C00).texture_convert, Some(TextureConvert::Filt));
+        assert_eq!(round_trip_h(9, 3, 0x00000600).texture_convert, Some(TextureConvert::Other(3)));
+
+        let mode = round_trip_h(12, 2, 0x00002000);
+        assert_eq!((mode.cycle_type, mode.texture_lut, mode.unused_bits), (None, None, None));
+
+        // several fields, the whole word
+        round_trip_h(4, 8, 0x000000F0);
+        round_trip_h(0, 24, 0x00CAFC5F);
+        round_trip_h(0, 32, 0x00102C00);
+
+        // bits outside of the range, or above 23, are kept
+        assert_eq!(round_trip_h(20, 2, 0x00182000).unused_bits, Some(0x00082000));
+        assert_eq!(round_trip_h(0, 32, 0xAB102C00).unused_bits, Some(0xAB000000));
+    }
+
+    #[test]
+    fn combiner() {
+        // G_CC_SHADE, G_CC_SHADE
+        let shade = Combiner::decode(0xFCFFFFFF, 0xFFFE793C);
+        let equation = CombinerEquation { a: CombinerInput::Zero, b: CombinerInput::Zero, c: CombinerInput::Zero, d: CombinerInput::Shade };
+        assert_eq!(shade.cycle1, CombinerCycle { colour: equation, alpha: equation });
+        assert_eq!(shade.encode(), (0x00FFFFFF, 0xFFFE793C));
+
+        // G_CC_MODULATERGBA, G_CC_MODULATERGBA
+        let modulate = Combiner::decode(0xFC121824, 0xFF33FFFF);
+        assert_eq!(modulate.cycle1.colour.a, CombinerInput::Texel0);
+        assert_eq!(modulate.cycle1.colour.c, CombinerInput::Shade);
+        assert_eq!(modulate.cycle1.alpha.c, CombinerInput::Shade);
+        assert_eq!(modulate.encode(), (0x00121824, 0xFF33FFFF));
+
+        // unusual values read as zero, e.g. 8 in the A input
+        let other = Combiner::decode(0x00800000, 0x00000000);
+        assert_eq!(other.cycle1.colour.a, CombinerInput::Other(8));
+        assert_eq!(other.cycle1.alpha.c, CombinerInput::LodFraction);
+        assert_eq!(other.encode(), (0x00800000, 0x00000000));
+
+        // every bit pattern
+        let mut state = 0x2468ACE1u32;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            state
+        };
+        for _ in 0..1000 {
+            let (w0, w1) = (next() & 0x00FFFFFF, next() ^ (next() << 16));
+            assert_eq!(Combiner::decode(w0, w1).encode(), (w0, w1));
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use std::io;
+use byteorder::{ BigEndian, ByteOrder };
+
+use crate::rarezip;
+
+// table offset in the US 1.0 ROM, the table is looked for elsewhere in other versions
+const US_ASSET_TABLE: usize = 0x5E90;
+const HEADER_SIZE: usize = 0x1000;
+const TABLE_SEARCH_END: usize = 0x100000;
+
+// the checksum covers the first megabyte after the header
+const CHECKSUM_START: usize = 0x1000;
+const CHECKSUM_LENGTH: usize = 0x100000;
+const BOOT_CODE: std::ops::Range<usize> = 0x40..0x1000;
+
+// expanded ROMs are a whole number of megabytes
+const ROM_SIZE_STEP: usize = 0x100000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Endianness {
+    BigEndian,    // .z64
+    ByteSwapped,  // .v64
+    LittleEndian, // .n64
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssetType {
+    Model,
+    Setup,
+    Animation,
+    Unknown,
+}
+
+impl AssetType {
+    // detected from the decompressed contents
+    pub fn detect(data: &[u8]) -> AssetType {
+        if data.len() >= 4 && BigEndian::read_u32(data) == 0x0B {
+            AssetType::Model
+        } else if data.len() >= 26 && BigEndian::read_u16(data) == 0x0101 {
+            AssetType::Setup
+        } else if is_animation(data) {
+            AssetType::Animation
+        } else {
+            AssetType::Unknown
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AssetType::Model => "model.bin",
+            AssetType::Setup => "lvl_setup.bin",
+            AssetType::Animation => "anim.bin",
+            AssetType::Unknown => "bin",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AssetEntry {
+    pub offset: usize, // in the ROM
+    pub size: usize,
+    pub compressed: bool,
+    pub flags: u16,
+}
+
+// the boot code used to compute the checksum
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cic {
+    Cic6101,
+    Cic6102,
+    Cic6103,
+    Cic6105,
+    Cic6106,
+}
+
+impl Cic {
+    // detected from the CRC32 of the boot code
+    fn detect(data: &[u8]) -> Option<Cic> {
+        let mut crc = flate2::Crc::new();
+        crc.update(&data[BOOT_CODE]);
+        match crc.sum() {
+            0x6170A4A1 => Some(Cic::Cic6101),
+            0x90BB6CB5 => Some(Cic::Cic6102),
+            0x0B050EE0 => Some(Cic::Cic6103),
+            0x98BC2C86 => Some(Cic::Cic6105),
+            0xACC8580A => Some(Cic::Cic6106),
+            _ => None,
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        match self {
+            Cic::Cic6101 | Cic::Cic6102 => 0xF8CA4DDC,
+            Cic::Cic6103 => 0xA3886759,
+            Cic::Cic6105 => 0xDF26F436,
+            Cic::Cic6106 => 0x1FEA617A,
+        }
+    }
+}
+
+// the ROM in big-endian (.z64) byte order, with its asset table
+#[derive(Debug)]
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub endianness: Endianness,
+    pub table_offset: usize,
+    pub assets: Vec<AssetEntry>,
+}
+
+impl Rom {
+    pub fn read(filename: &str, table_offset: Option<usize>) -> io::Result<Rom> {
+        let mut data = std::fs::read(filename)?;
+        let endianness = endianness(&data)?;
+        normalize(&mut data, endianness);
+
+        let table_offset = match table_offset {
+            Some(offset) => offset,
+            None => find_asset_table(&data)
+                .ok_or_else(|| invalid_data("can't find the asset table, use --asset-table".to_string()))?,
+        };
+        let assets = read_asset_table(&data, table_offset)
+            .ok_or_else(|| invalid_data(format!("no valid asset table at {:#X}", table_offset)))?;
+
+        Ok(Rom { data, endianness, table_offset, assets })
+    }
+
+    // in the byte order it was read in
+    pub fn write(&self, filename: &str) -> io::Result<()> {
+        let mut data = self.data.clone();
+        normalize(&mut data, self.endianness);
+        std::fs::write(filename, data)
+    }
+
+    // the decompressed contents of an asset, by ID
+    pub fn asset(&self, id: usize) -> io::Result<Vec<u8>> {
+        let entry = self.assets.get(id)
+            .ok_or_else(|| invalid_data(format!("asset {:#X} doesn't exist, there are {:#X} assets", id, self.assets.len())))?;
+        let data = &self.data[entry.offset..entry.offset + entry.size];
+
+        if entry.compressed {
+            rarezip::decompress(data)
+        } else {
+            Ok(data.to_vec())
+        }
+    }
+
+    // the asset is compressed like the one it replaces. It's written in place if it fits,
+    // otherwise the following assets are shifted into the free space after the last one,
+    // or all of them are moved to the end of the ROM if there isn't enough
+    pub fn replace_asset(&mut self, id: usize, contents: &[u8]) -> io::Result<()> {
+        let entry = self.assets.get(id)
+            .ok_or_else(|| invalid_data(format!("asset {:#X} doesn't exist, there are {:#X} assets", id, self.assets.len())))?;
+        let (offset, size, compressed) = (entry.offset, entry.size, entry.compressed);
+
+        let (mut contents, padding) = if compressed {
+            let contents = if rarezip::is_compressed(contents) { contents.to_vec() } else { rarezip::compress(contents)? };
+            (contents, 0xAA)
+        } else {
+            (contents.to_vec(), 0x00)
+        };
+        while contents.len() % 8 != 0 {
+            contents.push(padding);
+        }
+
+        if compressed && contents.len() <= size {
+            contents.resize(size, padding);
+            self.data[offset..offset + size].copy_from_slice(&contents);
+            return Ok(());
+        }
+
+        let end = self.data_end();
+        let delta = contents.len() as isize - size as isize;
+        let new_end = (end as isize + delta) as usize;
+        if delta > 0 {
+            let free = self.data[end..].iter().take_while(|b| **b == 0x00 || **b == 0xFF).count();
+            if free < delta as usize {
+                self.move_assets(id, contents);
+                return Ok(());
+            }
+        }
+
+        let following = self.data[offset + size..end].to_vec();
+        self.data[offset..offset + contents.len()].copy_from_slice(&contents);
+        self.data[offset + contents.len()..new_end].copy_from_slice(&following);
+        if new_end < end {
+            self.data[new_end..end].fill(0);
+        }
+
+        // the offsets of the following assets, and of the end of the data
+        let count = self.assets.len() + 1;
+        for i in id + 1..count {
+            let position = self.table_offset + 8 + 8 * i;
+            let relative = BigEndian::read_u32(&self.data[position..position + 4]) as isize + delta;
+            BigEndian::write_u32(&mut self.data[position..position + 4], relative as u32);
+            if let Some(asset) = self.assets.get_mut(i) {
+                asset.offset = (asset.offset as isize + delta) as usize;
+            }
+        }
+        self.assets[id].size = contents.len();
+
+        Ok(())
+    }
+
+    // the sizes are the distances between the offsets, so the assets are moved together, to the
+    // free space at the end of the ROM, which is expanded if there isn't enough
+    fn move_assets(&mut self, id: usize, contents: Vec<u8>) {
+        let data_start = self.table_offset + 8 + 8 * (self.assets.len() + 1);
+        let used = self.data.iter().rposition(|b| *b != 0x00 && *b != 0xFF).map_or(0, |last| last + 1);
+        let start = used.next_multiple_of(16);
+
+        let mut moved = vec![];
+        let mut offsets = vec![];
+        for (i, asset) in self.assets.iter().enumerate() {
+            offsets.push(start + moved.len());
+            if i == id {
+                moved.extend_from_slice(&contents);
+            } else {
+                moved.extend_from_slice(&self.data[asset.offset..asset.offset + asset.size]);
+            }
+        }
+        offsets.push(start + moved.len());
+
+        if self.data.len() < start + moved.len() {
+            self.data.resize((start + moved.len()).next_multiple_of(ROM_SIZE_STEP), 0xFF);
+        }
+        let end = self.data_end();
+        self.data[self.assets[0].offset..end].fill(0);
+        self.data[start..start + moved.len()].copy_from_slice(&moved);
+
+        for (i, offset) in offsets.iter().enumerate() {
+            let position = self.table_offset + 8 + 8 * i;
+            BigEndian::write_u32(&mut self.data[position..position + 4], (offset - data_start) as u32);
+        }
+        for (i, asset) in self.assets.iter_mut().enumerate() {
+            asset.offset = offsets[i];
+            asset.size = offsets[i + 1] - offsets[i];
+        }
+    }
+
+    // where the last entry of the table points
+    fn data_end(&self) -> usize {
+        let last = self.assets.last().unwrap();
+        last.offset + last.size
+    }
+
+    // stored at 0x10 and 0x14 of the header
+    pub fn update_checksum(&mut self) -> io::Result<()> {
+        if self.data.len() < CHECKSUM_START + CHECKSUM_LENGTH {
+            return Err(invalid_data("the ROM is too small to have a checksum".to_string()));
+        }
+
+        let cic = Cic::detect(&self.data).unwrap_or_else(|| {
+            eprintln!("warning: unknown boot code, the checksum is computed for CIC-6102");
+            Cic::Cic6102
+        });
+
+        let seed = cic.seed();
+        let (mut t1, mut t2, mut t3, mut t4, mut t5, mut t6) = (seed, seed, seed, seed, seed, seed);
+        for i in (CHECKSUM_START..CHECKSUM_START + CHECKSUM_LENGTH).step_by(4) {
+            let d = BigEndian::read_u32(&self.data[i..i + 4]);
+            if t6.wrapping_add(d) < t6 {
+                t4 = t4.wrapping_add(1);
+            }
+            t6 = t6.wrapping_add(d);
+            t3 ^= d;
+            let r = d.rotate_left(d & 0x1F);
+            t5 = t5.wrapping_add(r);
+            if t2 > d {
+                t2 ^= r;
+            } else {
+                t2 ^= t6 ^ d;
+            }
+            if cic == Cic::Cic6105 {
+                t1 = t1.wrapping_add(BigEndian::read_u32(&self.data[0x750 + (i & 0xFF)..]) ^ d);
+            } else {
+                t1 = t1.wrapping_add(t5 ^ d);
+            }
+        }
+
+        let (crc1, crc2) = match cic {
+            Cic::Cic6103 => ((t6 ^ t4).wrapping_add(t3), (t5 ^ t2).wrapping_add(t1)),
+            Cic::Cic6106 => (t6.wrapping_mul(t4).wrapping_add(t3), t5.wrapping_mul(t2).wrapping_add(t1)),
+            _ => (t6 ^ t4 ^ t3, t5 ^ t2 ^ t1),
+        };
+        BigEndian::write_u32(&mut self.data[0x10..0x14], crc1);
+        BigEndian::write_u32(&mut self.data[0x14..0x18], crc2);
+
+        Ok(())
+    }
+}
+
+// detected from the first word of the header, 0x80371240 in big-endian
+pub fn endianness(data: &[u8]) -> io::Result<Endianness> {
+    match data.get(0..4) {
+        Some([0x80, 0x37, 0x12, 0x40]) => Ok(Endianness::BigEndian),
+        Some([0x37, 0x80, 0x40, 0x12]) => Ok(Endianness::ByteSwapped),
+        Some([0x40, 0x12, 0x37, 0x80]) => Ok(Endianness::LittleEndian),
+        _ => Err(invalid_data("not an N64 ROM".to_string())),
+    }
+}
+
+pub fn normalize(data: &mut [u8], order: Endianness) {
+    match order {
+        Endianness::BigEndian => {},
+        Endianness::ByteSwapped => data.chunks_exact_mut(2).for_each(|word| word.swap(0, 1)),
+        Endianness::LittleEndian => data.chunks_exact_mut(4).for_each(|word| word.reverse()),
+    }
+}
+
+// the table is a count, a padding word, and 8 bytes per asset: its offset from the end of the table,
+// whether it's compressed, and flags. The last entry marks the end of the data
+fn read_asset_table(data: &[u8], table_offset: usize) -> Option<Vec<AssetEntry>> {
+    let count = BigEndian::read_u32(data.get(table_offset..table_offset + 4)?) as usize;
+    if !(2..=0x10000).contains(&count) {
+        return None;
+    }
+
+    let data_start = table_offset + 8 + 8 * count;
+    let table = data.get(table_offset + 8..data_start)?;
+    let entries: Vec<(usize, u16, u16)> = table.chunks_exact(8)
+        .map(|entry| (BigEndian::read_u32(&entry[0..4]) as usize, BigEndian::read_u16(&entry[4..6]), BigEndian::read_u16(&entry[6..8])))
+        .collect();
+
+    // the assets start right after the table, unless they have been moved
+    if !entries[0].0.is_multiple_of(8) || data_start + entries[count - 1].0 > data.len() {
+        return None;
+    }
+
+    let mut assets = vec![];
+    for pair in entries.windows(2) {
+        let (offset, compressed, flags) = pair[0];
+        let (next, _, _) = pair[1];
+        if next < offset || compressed > 1 {
+            return None;
+        }
+
+        let asset = AssetEntry { offset: data_start + offset, size: next - offset, compressed: compressed == 1, flags };
+        let contents = data.get(asset.offset..asset.offset + asset.size)?;
+        if asset.compressed && asset.size > 0 && !rarezip::is_compressed(contents) {
+            return None;
+        }
+        assets.push(asset);
+    }
+
+    Some(assets)
+}
+
+fn find_asset_table(data: &[u8]) -> Option<usize> {
+    if read_asset_table(data, US_ASSET_TABLE).is_some() {
+        return Some(US_ASSET_TABLE);
+    }
+
+    (HEADER_SIZE..data.len().min(TABLE_SEARCH_END)).step_by(4)
+        .find(|offset| read_asset_table(data, *offset).is_some())
+}
+
+// the header, then sections of a bone/transformation and its keyframes, up to the end of the file
+fn is_animation(data: &[u8]) -> bool {
+    if data.len() < 8 {
+        return false;
+    }
+    let start_frame = BigEndian::read_u16(&data[0..2]);
+    let end_frame = BigEndian::read_u16(&data[2..4]);
+    let section_count = BigEndian::read_u16(&data[4..6]) as usize;
+    if start_frame > end_frame || section_count == 0 || BigEndian::read_u16(&data[6..8]) != 0 {
+        return false;
+    }
+
+    let mut position = 8;
+    for _ in 0..section_count {
+        let Some(section) = data.get(position..position + 4) else { return false };
+        if section[1] & 0xF > 8 {
+            return false;
+        }
+        let value_count = BigEndian::read_u16(&section[2..4]) as usize;
+        position += 4 + 4 * value_count;
+    }
+
+    // up to the alignment padding
+    position <= data.len() && data.len() - position < 16 && data[position..].iter().all(|b| *b == 0)
+}
+
+// hexadecimal with 0x, decimal otherwise
+pub fn parse_number(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| e.to_string())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: usize = 0x2000;
+
+    fn model() -> Vec<u8> {
+        let mut data = vec![0x00, 0x00, 0x00, 0x0B];
+        data.extend((0..0x40u8).map(|i| i % 7));
+        data
+    }
+
+    fn setup() -> Vec<u8> {
+        let mut data = vec![0x01, 0x01];
+        data.extend(vec![0x03; 0x30]);
+        data
+    }
+
+    // one section of one keyframe, padded
+    fn animation() -> Vec<u8> {
+        vec![0, 1, 0, 10, 0, 1, 0, 0, 0x00, 0x16, 0, 1, 0, 5, 0, 64, 0, 0, 0, 0, 0, 0, 0, 0]
+    }
+
+    // compressed assets are padded with 0xAA, uncompressed ones with zeros
+    fn stored(contents: &[u8], compressed: bool) -> Vec<u8> {
+        let (mut data, padding) = if compressed { (rarezip::compress(contents).unwrap(), 0xAA) } else { (contents.to_vec(), 0x00) };
+        while data.len() % 8 != 0 {
+            data.push(padding);
+        }
+        data
+    }
+
+    // the header, the asset table at table_offset and the assets, followed by free space
+    fn build_rom(size: usize, table_offset: usize, assets: &[(Vec<u8>, bool)]) -> Vec<u8> {
+        let mut data = vec![0u8; size];
+        data[0..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+
+        let count = assets.len() + 1;
+        BigEndian::write_u32(&mut data[table_offset..], count as u32);
+        let data_start = table_offset + 8 + 8 * count;
+        let mut offset = 0;
+        for (i, (contents, compressed)) in assets.iter().enumerate() {
+            let entry = table_offset + 8 + 8 * i;
+            BigEndian::write_u32(&mut data[entry..], offset as u32);
+            BigEndian::write_u16(&mut data[entry + 4..], *compressed as u16);
+            BigEndian::write_u16(&mut data[entry + 6..], i as u16);
+
+            let contents = stored(contents, *compressed);
+            data[data_start + offset..data_start + offset + contents.len()].copy_from_slice(&contents);
+            offset += contents.len();
+        }
+        // the end marker
+        BigEndian::write_u32(&mut data[table_offset + 8 + 8 * assets.len()..], offset as u32);
+        data
+    }
+
+    fn assets() -> Vec<(Vec<u8>, bool)> {
+        vec![(model(), true), (setup(), true), (animation(), false), (vec![1, 2, 3], false)]
+    }
+
+    fn rom(table_offset: usize) -> Rom {
+        let data = build_rom(0x10000, table_offset, &assets());
+        let assets = read_asset_table(&data, table_offset).unwrap();
+        Rom { data, endianness: Endianness::BigEndian, table_offset, assets }
+    }
+
+    fn temp_file(name: &str) -> String {
+        std::env::temp_dir().join(format!("bk_level_{}_{}", std::process::id(), name)).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn byte_orders() {
+        let z64 = build_rom(0x10000, TABLE, &assets());
+        let mut v64 = z64.clone();
+        v64.chunks_exact_mut(2).for_each(|word| word.swap(0, 1));
+        let mut n64 = z64.clone();
+        n64.chunks_exact_mut(4).for_each(|word| word.reverse());
+
+        for (data, order, extension) in [(&z64, Endianness::BigEndian, "z64"), (&v64, Endianness::ByteSwapped, "v64"), (&n64, Endianness::LittleEndian, "n64")] {
+            assert_eq!(endianness(data).unwrap(), order);
+            let mut normalized = data.clone();
+            normalize(&mut normalized, order);
+            assert_eq!(normalized, z64);
+
+            // written back in the order it was read in
+            let (input, output) = (temp_file(&format!("in.{}", extension)), temp_file(&format!("out.{}", extension)));
+            std::fs::write(&input, data).unwrap();
+            let rom = Rom::read(&input, None).unwrap();
+            assert_eq!(rom.endianness, order);
+            assert_eq!(rom.data, z64);
+            assert_eq!(rom.asset(1).unwrap(), setup());
+            rom.write(&output).unwrap();
+            assert_eq!(&std::fs::read(&output).unwrap(), data);
+            std::fs::remove_file(input).unwrap();
+            std::fs::remove_file(output).unwrap();
+        }
+
+        assert!(endianness(&[0x12, 0x34, 0x56, 0x78]).is_err());
+        assert!(endianness(&[0x80, 0x37]).is_err());
+    }
+
+    #[test]
+    fn asset_table() {
+        let data = build_rom(0x10000, TABLE, &assets());
+        let table = read_asset_table(&data, TABLE).unwrap();
+        assert_eq!(table.len(), 4);
+
+        let data_start = TABLE + 8 + 8 * 5;
+        assert_eq!(table[0].offset, data_start);
+        assert!(table[0].compressed && table[1].compressed);
+        assert!(!table[2].compressed && !table[3].compressed);
+        assert_eq!(table[2].size, 24);
+        assert_eq!(table[3].size, 8);
+        assert_eq!(table[3].flags, 3);
+        for pair in table.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].size, pair[1].offset);
+            assert_eq!(pair[0].size % 8, 0);
+        }
+
+        // not a table: a zero count, or offsets going backwards
+        assert!(read_asset_table(&data, TABLE + 4).is_none());
+        assert!(read_asset_table(&data, US_ASSET_TABLE).is_none());
+    }
+
+    #[test]
+    fn find_table() {
+        let data = build_rom(0x10000, TABLE, &assets());
+        assert_eq!(find_asset_table(&data), Some(TABLE));
+
+        let data = build_rom(0x10000, US_ASSET_TABLE, &assets());
+        assert_eq!(find_asset_table(&data), Some(US_ASSET_TABLE));
+
+        let data = build_rom(0x10000, TABLE, &[]);
+        assert_eq!(find_asset_table(&data), None);
+    }
+
+    #[test]
+    fn decompressed_assets() {
+        let rom = rom(TABLE);
+        assert_eq!(rom.asset(0).unwrap(), model());
+        assert_eq!(rom.asset(1).unwrap(), setup());
+        assert_eq!(rom.asset(2).unwrap(), animation());
+        assert_eq!(rom.asset(3).unwrap(), vec![1, 2, 3, 0, 0, 0, 0, 0]);
+        assert!(rom.asset(4).is_err());
+    }
+
+    #[test]
+    fn asset_types() {
+        assert_eq!(AssetType::detect(&model()), AssetType::Model);
+        assert_eq!(AssetType::detect(&setup()), AssetType::Setup);
+        assert_eq!(AssetType::detect(&animation()), AssetType::Animation);
+        assert_eq!(AssetType::detect(&[1, 2, 3]), AssetType::Unknown);
+
+        // a transformation that doesn't exist, or keyframes past the end of the data
+        let mut invalid = animation();
+        invalid[9] = 0x1F;
+        assert_eq!(AssetType::detect(&invalid), AssetType::Unknown);
+        let mut truncated = animation();
+        truncated[11] = 8;
+        assert_eq!(AssetType::detect(&truncated), AssetType::Unknown);
+        // a setup header too short to be one
+        assert_eq!(AssetType::detect(&[0x01, 0x01, 0, 0]), AssetType::Unknown);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x5E90"), Ok(0x5E90));
+        assert_eq!(parse_number("12"), Ok(12));
+        assert!(parse_number("0xZZ").is_err());
+    }
+
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x12345678u32;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    // the table read back from the ROM matches the entries kept in memory
+    fn check_table(rom: &Rom) {
+        let table = read_asset_table(&rom.data, rom.table_offset).unwrap();
+        assert_eq!(table.len(), rom.assets.len());
+        for (read, kept) in table.iter().zip(&rom.assets) {
+            assert_eq!((read.offset, read.size, read.compressed, read.flags), (kept.offset, kept.size, kept.compressed, kept.flags));
+        }
+    }
+
+    #[test]
+    fn replace_in_place() {
+        let mut rom = rom(TABLE);
+        let before = rom.data.clone();
+        let size = rom.assets[0].size;
+
+        rom.replace_asset(0, &model()[..8]).unwrap();
+        assert_eq!(rom.asset(0).unwrap(), &model()[..8]);
+        assert_eq!(rom.assets[0].size, size);
+        assert_eq!(rom.data[rom.assets[1].offset..], before[rom.assets[1].offset..]);
+        check_table(&rom);
+    }
+
+    #[test]
+    fn replace_and_grow() {
+        let mut rom = rom(TABLE);
+        let offsets: Vec<usize> = rom.assets.iter().map(|asset| asset.offset).collect();
+        let contents = noise(0x200);
+
+        rom.replace_asset(1, &contents).unwrap();
+        let delta = rom.assets[1].size as isize - (offsets[2] - offsets[1]) as isize;
+        assert!(delta > 0);
+        assert_eq!(rom.assets[1].size % 8, 0);
+        assert_eq!(rom.assets[0].offset, offsets[0]);
+        assert_eq!(rom.assets[2].offset as isize, offsets[2] as isize + delta);
+        assert_eq!(rom.assets[3].offset as isize, offsets[3] as isize + delta);
+        check_table(&rom);
+
+        assert_eq!(rom.asset(0).unwrap(), model());
+        assert_eq!(rom.asset(1).unwrap(), contents);
+        assert_eq!(rom.asset(2).unwrap(), animation());
+        assert_eq!(rom.asset(3).unwrap(), vec![1, 2, 3, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn replace_and_shrink() {
+        let mut rom = rom(TABLE);
+        let end = rom.data_end();
+
+        rom.replace_asset(2, &[9; 8]).unwrap();
+        assert_eq!(rom.assets[2].size, 8);
+        assert_eq!(rom.data_end(), end - 16);
+        assert!(rom.data[end - 16..end].iter().all(|b| *b == 0));
+        check_table(&rom);
+        assert_eq!(rom.asset(2).unwrap(), vec![9; 8]);
+        assert_eq!(rom.asset(3).unwrap(), vec![1, 2, 3, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn replace_with_compressed_contents() {
+        let mut rom = rom(TABLE);
+        let compressed = rarezip::compress(&noise(0x40)).unwrap();
+        rom.replace_asset(0, &compressed).unwrap();
+        assert_eq!(rom.asset(0).unwrap(), noise(0x40));
+        assert_eq!(rom.data[rom.assets[0].offset..rom.assets[0].offset + compressed.len()], compressed);
+    }
+
+    #[test]
+    fn replace_with_exact_free_space() {
+        let mut grown = rom(TABLE);
+        let end = grown.data_end();
+        grown.replace_asset(1, &noise(0x200)).unwrap();
+        let delta = grown.data_end() - end;
+
+        let mut rom = rom(TABLE);
+        rom.data[end + delta..].fill(0x55);
+        rom.replace_asset(1, &noise(0x200)).unwrap();
+        assert!(rom.data[..end + delta] == grown.data[..end + delta]);
+        assert!(rom.data[end + delta..].iter().all(|b| *b == 0x55));
+    }
+
+    #[test]
+    fn replace_without_free_space() {
+        // code after the assets, with too little padding in between
+        let mut rom = rom(TABLE);
+        let end = rom.data_end();
+        rom.data[end + 0x10..].fill(0x55);
+        let code = rom.data[end..].to_vec();
+        let sizes: Vec<usize> = rom.assets.iter().map(|asset| asset.size).collect();
+
+        rom.replace_asset(1, &noise(0x200)).unwrap();
+        assert_eq!(rom.data.len(), 0x100000);
+        assert_eq!(rom.assets[0].offset, 0x10000);
+        assert_eq!(rom.data[end..0x10000], code);
+        assert!(rom.data[TABLE + 8 + 8 * 5..end].iter().all(|b| *b == 0));
+        assert_eq!((rom.assets[0].size, rom.assets[2].size, rom.assets[3].size), (sizes[0], sizes[2], sizes[3]));
+        check_table(&rom);
+
+        assert_eq!(rom.asset(0).unwrap(), model());
+        assert_eq!(rom.asset(1).unwrap(), noise(0x200));
+        assert_eq!(rom.asset(2).unwrap(), animation());
+        assert_eq!(rom.asset(3).unwrap(), vec![1, 2, 3, 0, 0, 0, 0, 0]);
+
+        // then grown into the padding of the expanded ROM
+        rom.replace_asset(3, &noise(0x100)).unwrap();
+        assert_eq!(rom.data.len(), 0x100000);
+        assert_eq!(rom.assets[0].offset, 0x10000);
+        check_table(&rom);
+        assert_eq!(rom.asset(1).unwrap(), noise(0x200));
+        assert_eq!(rom.asset(3).unwrap(), noise(0x100));
+
+        assert!(rom.replace_asset(7, &[]).is_err());
+    }
+
+    #[test]
+    fn checksum() {
+        // without a known boot code, the checksum of a CIC-6102 ROM
+        let mut data = vec![0u8; CHECKSUM_START + CHECKSUM_LENGTH];
+        data[0..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        data[CHECKSUM_START..].copy_from_slice(&noise(CHECKSUM_LENGTH));
+        let mut rom = Rom { data, endianness: Endianness::BigEndian, table_offset: TABLE, assets: vec![] };
+
+        rom.update_checksum().unwrap();
+        assert_eq!(BigEndian::read_u32(&rom.data[0x10..0x14]), 0x92851331);
+        assert_eq!(BigEndian::read_u32(&rom.data[0x14..0x18]), 0x403E45EC);
+
+        let mut small = self::rom(TABLE);
+        assert!(small.update_checksum().is_err());
+    }
+}
@@ -1,15 +1,59 @@
 use crate::types::*;
-use byteorder::{ ReadBytesExt, WriteBytesExt, BigEndian };
+use crate::gltf;
+use crate::names::ObjectNames;
+use crate::definitions::{ ObjectDefinitions, ComplexClass, SmallClass };
+use crate::layouts::{ self, ObjectLayouts, Fields, COMPLEX_PAYLOAD_SIZE, SMALL_PAYLOAD_SIZE };
+use byteorder::{ ReadBytesExt, WriteBytesExt, BigEndian, LittleEndian };
 use serde::{ Serialize, Deserialize };
+use std::collections::{ HashMap, HashSet };
 use std::fs::File;
+use std::io::Write;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct SetupFile {
     pub cameras: Vec<Camera>,
+    // computed from the voxels positions when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extents: Option<GridExtents>,
+    // voxels can be omitted, they are written as missing
     pub voxels: Vec<Voxel>,
     pub lightings: Vec<Lighting>,
 }
 
+// lowest and highest voxel positions on each axis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridExtents {
+    pub negative: Vector3<i32>,
+    pub positive: Vector3<i32>,
+}
+
+// objects listed with their world coordinates only, the voxels are rebuilt from them
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlatSetupFile {
+    #[serde(default = "default_voxel_size")]
+    pub voxel_size: i32,
+    pub cameras: Vec<Camera>,
+    pub complex_objects: Vec<ComplexObject>,
+    pub small_objects: Vec<SmallObject>,
+    // small objects without known coordinates stay in their voxel
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unplaced_objects: Vec<UnplacedObject>,
+    pub lightings: Vec<Lighting>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnplacedObject {
+    pub voxel: Vector3<i32>,
+    pub object: SmallObject,
+}
+
+// size of a voxel in world units
+pub const VOXEL_SIZE: i32 = 1000;
+
+fn default_voxel_size() -> i32 {
+    VOXEL_SIZE
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Camera {
     Type0 { id: u16 },
@@ -21,39 +65,46 @@ pub enum Camera {
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum SmallObject {
-    Sprite { object: u16, size: u16, x: u16, y: u16, z: u16, unk0: u8, unk1: u8, unk2: u8, unk3: u8, unk4: u8 },
-    Static { object: u16, y_rot: u8, xz_rot: u8, x: u16, y: u16, z: u16, size: u8, unk0: u8, unk1: u8 },
-    Unknown { object: u16, unk0: u8, unk1: u8, unk2: u8, unk3: u8, unk4: u8, unk5: u8, unk6: u8, unk7: u8, unk8: u8, unk9: u8, unk10: u8 },
+    Sprite { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, object: u16, size: u16, x: u16, y: u16, z: u16, unk0: u8, unk1: u8, unk2: u8, unk3: u8, unk4: u8 },
+    Static { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, object: u16, y_rot: u8, xz_rot: u8, x: u16, y: u16, z: u16, size: u8, unk0: u8, unk1: u8 },
+    Unknown { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>, object: u16, unk0: u8, unk1: u8, unk2: u8, unk3: u8, unk4: u8, unk5: u8, unk6: u8, unk7: u8, unk8: u8, unk9: u8, unk10: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")] fields: Option<Fields>, },
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum ComplexObject {
-    Actor { x: u16, y: u16, z: u16,
+    Actor { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>,
+        x: u16, y: u16, z: u16,
         script: u16, object: u16, unk_0a: u8,
         unk_0b: u8, rotation: u16, unk_0d: u8,
         size: u16, current: u16, next: u16,
         end_indicator: u8 },
-    Timed { x: u16, y: u16, z: u16,
+    Timed { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>,
+        x: u16, y: u16, z: u16,
         script: u16, object: u16, unk_0a: u8,
         unk_0b: u8, timer: u8, unk_0d: u8,
         unk_0e: u8, unk_0f: u8,
         current: u16, next: u16, end_indicator: u8 },
-    Script { x: u16, y: u16, z: u16,
+    Script { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>,
+        x: u16, y: u16, z: u16,
         script: u16, object: u16, unk_0a: u8,
         unk_0b: u8, unk_0c: u8, unk_0d: u8,
         unk_0e: u8, unk_0f: u8,
         current: u16, next: u16, end_indicator: u8 },
-    Radius { x: u16, y: u16, z: u16,
+    Radius { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>,
+        x: u16, y: u16, z: u16,
         radius: u16, object: u8, associated: u16,
         unk_0a: u8, unk_0b: u8, unk_0c: u8,
         unk_0d: u8, unk_0e: u8, unk_0f: u8,
         current: u16, next: u16,
         end_indicator: u8 },
-    Unknown { x: u16, y: u16, z: u16,
+    Unknown { #[serde(default, skip_serializing_if = "Option::is_none")] name: Option<String>,
+        x: u16, y: u16, z: u16,
         script: u16, object: u16, unk_0a: u8,
         unk_0b: u8, unk_0c: u8, unk_0d: u8,
         unk_0e: u8, unk_0f: u8,
-        current: u16, next: u16, end_indicator: u8 },
+        current: u16, next: u16, end_indicator: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")] fields: Option<Fields>, },
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -71,7 +122,203 @@ pub struct Lighting {
     colours: Vector3<u32>,
 }
 
-fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
+impl Lighting {
+    pub fn position(&self) -> [f32; 3] {
+        [self.position.x, self.position.y, self.position.z]
+    }
+
+    // the two floats look like the inner and outer radius
+    pub fn radii(&self) -> (f32, f32) {
+        (self.unk.x.min(self.unk.y), self.unk.x.max(self.unk.y))
+    }
+
+    pub fn colour(&self) -> [f32; 3] {
+        [self.colours.x, self.colours.y, self.colours.z].map(|c| (c.min(255) as f32) / 255.0)
+    }
+
+    // full colour inside the inner radius, fading out linearly up to the outer radius
+    pub fn light_at(&self, position: [f32; 3]) -> [f32; 3] {
+        let (inner, outer) = self.radii();
+        let dx = position[0] - self.position.x;
+        let dy = position[1] - self.position.y;
+        let dz = position[2] - self.position.z;
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+
+        let factor = if distance <= inner {
+            1.0
+        } else if distance >= outer {
+            0.0
+        } else {
+            (outer - distance) / (outer - inner)
+        };
+
+        self.colour().map(|c| c * factor)
+    }
+}
+
+impl Camera {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Camera::Type0 { .. } => "Type0",
+            Camera::Type1 { .. } => "Type1",
+            Camera::Type2 { .. } => "Type2",
+            Camera::Type3 { .. } => "Type3",
+            Camera::Type4 { .. } => "Type4",
+        }
+    }
+
+    pub fn id(&self) -> u16 {
+        match self {
+            Camera::Type0 { id }
+            | Camera::Type1 { id, .. }
+            | Camera::Type2 { id, .. }
+            | Camera::Type3 { id, .. }
+            | Camera::Type4 { id, .. } => *id,
+        }
+    }
+
+    // position and pitch/yaw/roll angles in degrees, only for the types that have them
+    pub fn placement(&self) -> Option<(&Vector3<f32>, &Vector3<f32>)> {
+        match self {
+            Camera::Type1 { position, angles, .. }
+            | Camera::Type2 { position, angles, .. }
+            | Camera::Type3 { position, angles, .. } => Some((position, angles)),
+            Camera::Type0 { .. } | Camera::Type4 { .. } => None,
+        }
+    }
+}
+
+impl ComplexObject {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ComplexObject::Actor { .. } => "Actor",
+            ComplexObject::Timed { .. } => "Timed",
+            ComplexObject::Script { .. } => "Script",
+            ComplexObject::Radius { .. } => "Radius",
+            ComplexObject::Unknown { .. } => "Unknown",
+        }
+    }
+
+    pub fn object_id(&self) -> u16 {
+        match self {
+            ComplexObject::Actor { object, .. }
+            | ComplexObject::Timed { object, .. }
+            | ComplexObject::Script { object, .. }
+            | ComplexObject::Unknown { object, .. } => *object,
+            ComplexObject::Radius { object, .. } => *object as u16,
+        }
+    }
+
+    // coordinates are signed in the game
+    pub fn position(&self) -> Vector3<i16> {
+        match self {
+            ComplexObject::Actor { x, y, z, .. }
+            | ComplexObject::Timed { x, y, z, .. }
+            | ComplexObject::Script { x, y, z, .. }
+            | ComplexObject::Radius { x, y, z, .. }
+            | ComplexObject::Unknown { x, y, z, .. } => Vector3 { x: *x as i16, y: *y as i16, z: *z as i16 },
+        }
+    }
+
+    // current and next node ids, and the end indicator
+    pub fn links(&self) -> (u16, u16, u8) {
+        match self {
+            ComplexObject::Actor { current, next, end_indicator, .. }
+            | ComplexObject::Timed { current, next, end_indicator, .. }
+            | ComplexObject::Script { current, next, end_indicator, .. }
+            | ComplexObject::Radius { current, next, end_indicator, .. }
+            | ComplexObject::Unknown { current, next, end_indicator, .. } => (*current, *next, *end_indicator),
+        }
+    }
+
+    pub fn name(&self) -> &Option<String> {
+        match self {
+            ComplexObject::Actor { name, .. }
+            | ComplexObject::Timed { name, .. }
+            | ComplexObject::Script { name, .. }
+            | ComplexObject::Radius { name, .. }
+            | ComplexObject::Unknown { name, .. } => name,
+        }
+    }
+
+    fn name_mut(&mut self) -> &mut Option<String> {
+        match self {
+            ComplexObject::Actor { name, .. }
+            | ComplexObject::Timed { name, .. }
+            | ComplexObject::Script { name, .. }
+            | ComplexObject::Radius { name, .. }
+            | ComplexObject::Unknown { name, .. } => name,
+        }
+    }
+
+    // radius objects use their own IDs, they aren't in the catalogue
+    pub fn catalogue_name<'a>(&self, names: &'a ObjectNames) -> Option<&'a String> {
+        match self {
+            ComplexObject::Radius { .. } => None,
+            _ => names.complex.get(&self.object_id()),
+        }
+    }
+}
+
+impl SmallObject {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SmallObject::Sprite { .. } => "Sprite",
+            SmallObject::Static { .. } => "Static",
+            SmallObject::Unknown { .. } => "Unknown",
+        }
+    }
+
+    pub fn object_id(&self) -> u16 {
+        match self {
+            SmallObject::Sprite { object, .. }
+            | SmallObject::Static { object, .. }
+            | SmallObject::Unknown { object, .. } => *object,
+        }
+    }
+
+    // unknown small objects don't have known coordinates
+    pub fn position(&self) -> Option<Vector3<i16>> {
+        match self {
+            SmallObject::Sprite { x, y, z, .. }
+            | SmallObject::Static { x, y, z, .. } => Some(Vector3 { x: *x as i16, y: *y as i16, z: *z as i16 }),
+            SmallObject::Unknown { .. } => None,
+        }
+    }
+
+    pub fn name(&self) -> &Option<String> {
+        match self {
+            SmallObject::Sprite { name, .. }
+            | SmallObject::Static { name, .. }
+            | SmallObject::Unknown { name, .. } => name,
+        }
+    }
+
+    fn name_mut(&mut self) -> &mut Option<String> {
+        match self {
+            SmallObject::Sprite { name, .. }
+            | SmallObject::Static { name, .. }
+            | SmallObject::Unknown { name, .. } => name,
+        }
+    }
+
+    pub fn catalogue_name<'a>(&self, names: &'a ObjectNames) -> Option<&'a String> {
+        names.small.get(&self.object_id())
+    }
+}
+
+impl Voxel {
+    fn empty(position: Vector3<i32>) -> Voxel {
+        Voxel {
+            position,
+            complex_objects: vec![],
+            small_objects: vec![],
+            missing: false,
+        }
+    }
+}
+
+fn read_voxel(f: &mut File, position: Vector3<i32>, definitions: &ObjectDefinitions) -> std::io::Result<Voxel> {
     let mut complex_objects = vec![];
     let mut small_objects = vec![];
     let mut missing = false;
@@ -104,27 +351,31 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                 let current = (c << 4) + (cn >> 12);
                 let next = cn & 0x0FFF;
 
-                if ACTORS_ID.contains(&object) {
+                let class = definitions.complex_class(object);
+                if class == Some(ComplexClass::Actor) {
                     let rotation = (unk_0c as u16) * 2;
                     let size = ((unk_0e as u16) << 8) + (unk_0f as u16);
 
                     complex_objects.push(ComplexObject::Actor {
+                        name: None,
                         x, y, z,
                         script, object, unk_0a, unk_0b,
                         rotation, unk_0d, size,
                         current, next, end_indicator
                     });
-                } else if TIMERS_ID.contains(&object) {
+                } else if class == Some(ComplexClass::Timed) {
                     let timer = unk_0c;
 
                     complex_objects.push(ComplexObject::Timed {
+                        name: None,
                         x, y, z,
                         script, object, unk_0a, unk_0b,
                         timer, unk_0d, unk_0e, unk_0f,
                         current, next, end_indicator
                     });
-                } else if SCRIPTS_ID.contains(&object) {
+                } else if class == Some(ComplexClass::Script) {
                     complex_objects.push(ComplexObject::Script {
+                        name: None,
                         x, y, z,
                         script, object, unk_0a, unk_0b,
                         unk_0c, unk_0d, unk_0e, unk_0f,
@@ -134,26 +385,25 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                     let associated = object;
                     let radius = (script >> 8) * 2;
                     let object = (script & 0xFF) as u8;
-                    match object {
-                        0x06 | 0x08 | 0x0E | 0x12 | 0x14 | 0x4C | 0x4D | 0x86 | 0x88 | 0x8E | 0x92 | 0x94 => {
-                            complex_objects.push(ComplexObject::Radius {
-                                x, y, z,
-                                radius, object, associated,
-                                unk_0a, unk_0b, unk_0c,
-                                unk_0d, unk_0e, unk_0f,
-                                current, next,
-                                end_indicator
-                            });
-                        },
-                        _ => {
-                            complex_objects.push(ComplexObject::Unknown {
-                                x, y, z,
-                                script, object: associated, unk_0a, unk_0b,
-                                unk_0c, unk_0d, unk_0e, unk_0f, current,
-                                next, end_indicator
-                            });
-                        }
-                    };
+                    if definitions.is_radius(object) {
+                        complex_objects.push(ComplexObject::Radius {
+                            name: None,
+                            x, y, z,
+                            radius, object, associated,
+                            unk_0a, unk_0b, unk_0c,
+                            unk_0d, unk_0e, unk_0f,
+                            current, next,
+                            end_indicator
+                        });
+                    } else {
+                        complex_objects.push(ComplexObject::Unknown {
+                            name: None,
+                            x, y, z,
+                            script, object: associated, unk_0a, unk_0b,
+                            unk_0c, unk_0d, unk_0e, unk_0f, current,
+                            next, end_indicator, fields: None
+                        });
+                    }
                 }
             }
 
@@ -173,7 +423,8 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                 let unk0 = (object & 0xF) as u8;
                 let object = object >> 4;
 
-                if SPRITES_ID.contains(&object) {
+                let class = definitions.small_class(object);
+                if class == Some(SmallClass::Sprite) {
                     let size = f.read_u16::<BigEndian>()?;
                     let unk1 = (size >> 10) as u8;
                     let unk2 = (size & 0b11) as u8;
@@ -184,8 +435,8 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                     let unk3 = f.read_u8()?;
                     let unk4 = f.read_u8()?;
 
-                    small_objects.push(SmallObject::Sprite { object, size, x, y, z, unk0, unk1, unk2, unk3, unk4 });
-                } else if STATICS_ID.contains(&object) {
+                    small_objects.push(SmallObject::Sprite { name: None, object, size, x, y, z, unk0, unk1, unk2, unk3, unk4 });
+                } else if class == Some(SmallClass::Static) {
                     let y_rot = f.read_u8()?;
                     let xz_rot = f.read_u8()?;
                     let x = f.read_u16::<BigEndian>()?;
@@ -194,7 +445,7 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                     let size = f.read_u8()?;
                     let unk1 = f.read_u8()?;
 
-                    small_objects.push(SmallObject::Static { object, y_rot, xz_rot, x, y, z, size, unk0, unk1 });
+                    small_objects.push(SmallObject::Static { name: None, object, y_rot, xz_rot, x, y, z, size, unk0, unk1 });
                 } else {
                     let unk1 = f.read_u8()?;
                     let unk2 = f.read_u8()?;
@@ -208,8 +459,9 @@ fn read_voxel(f: &mut File, position: Vector3<i32>) -> std::io::Result<Voxel> {
                     let unk10 = f.read_u8()?;
 
                     small_objects.push(SmallObject::Unknown {
+                        name: None,
                         object, unk0, unk1, unk2, unk3, unk4, unk5,
-                        unk6, unk7, unk8, unk9, unk10 });
+                        unk6, unk7, unk8, unk9, unk10, fields: None });
                 }
             }
         }
@@ -257,7 +509,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
 
             for complex in &voxel.complex_objects {
                 match complex {
-                    ComplexObject::Actor { x, y, z, script, object, unk_0a, unk_0b, rotation, unk_0d, size, current, next, end_indicator } => {
+                    ComplexObject::Actor { x, y, z, script, object, unk_0a, unk_0b, rotation, unk_0d, size, current, next, end_indicator, .. } => {
                         nx = *x;
                         ny = *y;
                         nz = *z;
@@ -273,7 +525,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         nnext = *next;
                         nend = *end_indicator;
                     },
-                    ComplexObject::Timed { x, y, z, script, object, unk_0a, unk_0b, timer, unk_0d, unk_0e, unk_0f, current, next, end_indicator } => {
+                    ComplexObject::Timed { x, y, z, script, object, unk_0a, unk_0b, timer, unk_0d, unk_0e, unk_0f, current, next, end_indicator, .. } => {
                         nx = *x;
                         ny = *y;
                         nz = *z;
@@ -289,7 +541,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         nnext = *next;
                         nend = *end_indicator;
                     },
-                    ComplexObject::Script { x, y, z, script, object, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator } => {
+                    ComplexObject::Script { x, y, z, script, object, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator, .. } => {
                         nx = *x;
                         ny = *y;
                         nz = *z;
@@ -305,7 +557,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         nnext = *next;
                         nend = *end_indicator;
                     },
-                    ComplexObject::Radius { x, y, z, radius, object, associated, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator } => {
+                    ComplexObject::Radius { x, y, z, radius, object, associated, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator, .. } => {
                         nx = *x;
                         ny = *y;
                         nz = *z;
@@ -321,7 +573,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         nnext = *next;
                         nend = *end_indicator;
                     },
-                    ComplexObject::Unknown { x, y, z, script, object, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator } => {
+                    ComplexObject::Unknown { x, y, z, script, object, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, current, next, end_indicator, .. } => {
                         nx = *x;
                         ny = *y;
                         nz = *z;
@@ -367,7 +619,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
 
             for small in &voxel.small_objects {
                 match small {
-                    SmallObject::Sprite { object, size, x, y, z, unk0, unk1, unk2, unk3, unk4 } => {
+                    SmallObject::Sprite { object, size, x, y, z, unk0, unk1, unk2, unk3, unk4, .. } => {
                         let object =(*object << 4) + (*unk0 as u16);
                         let size = ((*unk1 as u16) << 10) + (*size << 2) + (*unk2 as u16);
                         f.write_u16::<BigEndian>(object)?;
@@ -378,7 +630,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         f.write_u8(*unk3)?;
                         f.write_u8(*unk4)?;
                     },
-                    SmallObject::Static { object, y_rot, xz_rot, x, y, z, size, unk0, unk1 } => {
+                    SmallObject::Static { object, y_rot, xz_rot, x, y, z, size, unk0, unk1, .. } => {
                         let object =(*object << 4) + (*unk0 as u16);
                         f.write_u16::<BigEndian>(object)?;
                         f.write_u8(*y_rot)?;
@@ -389,7 +641,7 @@ fn write_voxel(f: &mut File, voxel: &Voxel) -> std::io::Result<()> {
                         f.write_u8(*size)?;
                         f.write_u8(*unk1)?;
                     },
-                    SmallObject::Unknown { object, unk0, unk1, unk2, unk3, unk4, unk5, unk6, unk7, unk8, unk9, unk10 } => {
+                    SmallObject::Unknown { object, unk0, unk1, unk2, unk3, unk4, unk5, unk6, unk7, unk8, unk9, unk10, .. } => {
                         let object =(*object << 4) + (*unk0 as u16);
                         f.write_u16::<BigEndian>(object)?;
                         f.write_u8(*unk1)?;
@@ -478,16 +730,8 @@ fn write_camera(f: &mut File, camera: &Camera) -> std::io::Result<()> {
     Ok(())
 }
 
-// complex objects
-const ACTORS_ID: [u16; 351] = [0x0004, 0x0005, 0x0006, 0x0007, 0x0008, 0x0009, 0x000A, 0x000B, 0x000C, 0x000F, 0x0011, 0x0012, 0x001E, 0x0020, 0x0021, 0x0022, 0x0023, 0x0025, 0x0026, 0x0027, 0x0028, 0x0029, 0x002A, 0x002D, 0x002F, 0x0030, 0x0031, 0x003A, 0x003C, 0x003D, 0x003E, 0x0041, 0x0043, 0x0046, 0x0047, 0x0049, 0x0050, 0x0052, 0x0055, 0x0056, 0x0057, 0x005B, 0x005E, 0x005F, 0x0060, 0x0061, 0x0062, 0x0067, 0x0069, 0x0070, 0x0081, 0x0082, 0x0083, 0x0084, 0x0085, 0x0088, 0x008C, 0x00BC, 0x00C5, 0x00C6, 0x00C7, 0x00CA, 0x00CB, 0x00CC, 0x00CD, 0x00CE, 0x00D0, 0x00D1, 0x00D5, 0x00D7, 0x00E4, 0x00E6, 0x00E8, 0x00EE, 0x00EF, 0x00F0, 0x00F1, 0x00F2, 0x00F5, 0x00F6, 0x00F7, 0x00F9, 0x00FA, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0x0100, 0x0101, 0x0102, 0x0109, 0x010A, 0x010C, 0x010D, 0x010E, 0x0110, 0x0111, 0x0114, 0x0115, 0x0116, 0x0117, 0x0119, 0x011A, 0x011B, 0x011C, 0x011D, 0x011E, 0x01F9, 0x0120, 0x0121, 0x0123, 0x0124, 0x0129, 0x012B, 0x012E, 0x0130, 0x0131, 0x0132, 0x0133, 0x0134, 0x0135, 0x0137, 0x0139, 0x013A, 0x013B, 0x013E, 0x013F, 0x0142, 0x0143, 0x0144, 0x0145, 0x0146, 0x0147, 0x014D, 0x014E, 0x0150, 0x0151, 0x0152, 0x0153, 0x015F, 0x0160, 0x0161, 0x0162, 0x0163, 0x0167, 0x0168, 0x0169, 0x016A, 0x016B, 0x016C, 0x016D, 0x016F, 0x0181, 0x0182, 0x0185, 0x018B, 0x018F, 0x0191, 0x0192, 0x0194, 0x019E, 0x01A3, 0x01A4, 0x01BF, 0x01C0, 0x01C1, 0x01C2, 0x01C3, 0x01C4, 0x01C6, 0x01C8, 0x01C9, 0x01CA, 0x01CC, 0x01CD, 0x01D8, 0x01D9, 0x01DA, 0x01E2, 0x01E9, 0x01E3, 0x01E4, 0x01EA, 0x01EB, 0x01EC, 0x01ED, 0x01EE, 0x01EF, 0x01F0, 0x01F1, 0x01F2, 0x01F3, 0x01F6, 0x01F7, 0x01FD, 0x01FE, 0x01FA, 0x01FB, 0x01FC, 0x0203, 0x0204, 0x0206, 0x0208, 0x020B, 0x020D, 0x020E, 0x020F, 0x0210, 0x0211, 0x0212, 0x0213, 0x0214, 0x0215, 0x0216, 0x0217, 0x0218, 0x0219, 0x021A, 0x021B, 0x021D, 0x0221, 0x0222, 0x0223, 0x0226, 0x0227, 0x0229, 0x022B, 0x022C, 0x0230, 0x0231, 0x0234, 0x0235, 0x0236, 0x0237, 0x0239, 0x023B, 0x023C, 0x023D, 0x023F, 0x0243, 0x0246, 0x0247, 0x0248, 0x0256, 0x0257, 0x025B, 0x025C, 0x025D, 0x025E, 0x0266, 0x0267, 0x0268, 0x027A, 0x027B, 0x027C, 0x027D, 0x027E, 0x027F, 0x0280, 0x0285, 0x0286, 0x0287, 0x0288, 0x0289, 0x028A, 0x0292, 0x0296, 0x0297, 0x0299, 0x029C, 0x029D, 0x029F, 0x02A1, 0x02A2, 0x02A4, 0x02A6, 0x02A7, 0x02A8, 0x02A9, 0x02AA, 0x02AB, 0x02AC, 0x02DB, 0x02DE, 0x02E2, 0x02E3, 0x02E4, 0x02E5, 0x02E7, 0x02E8, 0x02E9, 0x02EA, 0x02F4, 0x02F5, 0x030D, 0x030F, 0x0311, 0x0312, 0x0315, 0x031A, 0x031D, 0x033A, 0x033B, 0x033C, 0x033D, 0x033F, 0x0340, 0x0348, 0x034D, 0x034E, 0x034F, 0x0350, 0x0354, 0x0355, 0x0356, 0x0357, 0x0361, 0x0362, 0x0363, 0x0364, 0x0367, 0x0365, 0x0366, 0x0368, 0x0369, 0x036A, 0x036B, 0x036C, 0x036D, 0x036E, 0x036F, 0x0370, 0x0372, 0x0375, 0x037A, 0x037B, 0x037D, 0x037E, 0x037F, 0x0380, 0x0381, 0x0383, 0x0387, 0x038B, 0x03B7, 0x03BC, 0x03BF, 0x03C0, 0x03C1, 0x03C2];
-const TIMERS_ID: [u16; 2] = [0x002C, 0x0065];
-const SCRIPTS_ID: [u16; 50] = [0x0000, 0x0001, 0x0002, 0x0002, 0x0012, 0x0015, 0x0016, 0x0017, 0x0013, 0x0037, 0x0071, 0x0072, 0x0075, 0x0076, 0x0076, 0x0077, 0x0078, 0x0079, 0x007A, 0x007B, 0x007C, 0x007D, 0x007E, 0x007F, 0x0103, 0x0104, 0x0105, 0x0106, 0x0149, 0x014A, 0x016E, 0x01B0, 0x01CF, 0x0349, 0x0373, 0x0373, 0x0373, 0x0373, 0x0373, 0x0373, 0x0376, 0x0379, 0x0379, 0x0379, 0x0379, 0x03B9, 0x03BA, 0x03BD, 0x03BE, 0x03C3];
-// small objects
-const SPRITES_ID: [u16; 23] = [0x000E, 0x0038, 0x0046, 0x0047, 0x0050, 0x0054, 0x0055, 0x0094, 0x0097, 0x00D6, 0x0121, 0x013F, 0x0140, 0x0141, 0x0145, 0x015F, 0x0160, 0x0161, 0x0162, 0x0163, 0x0164, 0x0165, 0x0166];
-const STATICS_ID: [u16; 28] = [0x0002, 0x0009, 0x000A, 0x000B, 0x000C, 0x000E, 0x000F, 0x0010, 0x0012, 0x0013, 0x0017, 0x001C, 0x0020, 0x0026, 0x0027, 0x002A, 0x002B, 0x002E, 0x0037, 0x0060, 0x0061, 0x0063, 0x0064, 0x006F, 0x0070, 0x0071, 0x0076, 0x007A];
-
 impl SetupFile {
-    pub fn read_bin(filename: &str) -> std::io::Result<SetupFile> {
+    pub fn read_bin(filename: &str, definitions: &ObjectDefinitions) -> std::io::Result<SetupFile> {
         let mut f = File::open(filename)?;
         let header = f.read_u16::<BigEndian>()?; assert_eq!(header, 0x0101);
         let negative_x_voxel_count = f.read_i32::<BigEndian>()?;
@@ -496,9 +740,11 @@ impl SetupFile {
         let positive_x_voxel_count = f.read_i32::<BigEndian>()?;
         let positive_y_voxel_count = f.read_i32::<BigEndian>()?;
         let positive_z_voxel_count = f.read_i32::<BigEndian>()?;
-        let x_voxel_count = negative_x_voxel_count.abs() + positive_x_voxel_count + 1;
-        let y_voxel_count = negative_y_voxel_count.abs() + positive_y_voxel_count + 1;
-        let z_voxel_count = negative_z_voxel_count.abs() + positive_z_voxel_count + 1;
+        let extents = GridExtents {
+            negative: Vector3 { x: negative_x_voxel_count, y: negative_y_voxel_count, z: negative_z_voxel_count },
+            positive: Vector3 { x: positive_x_voxel_count, y: positive_y_voxel_count, z: positive_z_voxel_count },
+        };
+        let voxels_count = extents.voxel_count()?;
 
         let mut cameras = vec![];
         let mut voxels = vec![];
@@ -508,14 +754,12 @@ impl SetupFile {
         let mut loc_y = negative_y_voxel_count;
         let mut loc_z = negative_z_voxel_count;
 
-        let voxels_count = x_voxel_count*y_voxel_count*z_voxel_count;
-
         for _ in 0..voxels_count {
             let voxel = read_voxel(&mut f, Vector3 {
                 x: loc_x,
                 y: loc_y,
                 z: loc_z,
-            })?;
+            }, definitions)?;
 
             voxels.push(voxel);
 
@@ -639,6 +883,7 @@ impl SetupFile {
 
         Ok(SetupFile {
             cameras,
+            extents: Some(extents),
             voxels,
             lightings,
         })
@@ -653,35 +898,31 @@ impl SetupFile {
         }
     }
 
-    pub fn write_bin(&self, filename: &str) -> std::io::Result<()> {
-        let mut f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(filename).unwrap();
+    pub fn write_bin(&self, filename: &str, allow_broken_links: bool) -> std::io::Result<()> {
+        let issues = self.validate(allow_broken_links);
+        for issue in &issues {
+            println!("{}", issue);
+        }
+        if issues.iter().any(|issue| issue.severity == Severity::Error) {
+            return Err(invalid_data(format!("{} can't be written, see the errors above", filename)));
+        }
 
-        let mut neg_x = 0;
-        let mut neg_y = 0;
-        let mut neg_z = 0;
-        let mut pos_x = 0;
-        let mut pos_y = 0;
-        let mut pos_z = 0;
+        let (extents, grid) = self.grid()?;
 
-        for voxel in &self.voxels {
-            neg_x = if voxel.position.x < neg_x { voxel.position.x } else { neg_x };
-            neg_y = if voxel.position.y < neg_y { voxel.position.y } else { neg_y };
-            neg_z = if voxel.position.z < neg_z { voxel.position.z } else { neg_z };
-            pos_x = if voxel.position.x > pos_x { voxel.position.x } else { pos_x };
-            pos_y = if voxel.position.y > pos_y { voxel.position.y } else { pos_y };
-            pos_z = if voxel.position.z > pos_z { voxel.position.z } else { pos_z };
-        }
+        let mut f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(filename).unwrap();
 
         f.write_u16::<BigEndian>(0x0101)?;
-        f.write_i32::<BigEndian>(neg_x)?;
-        f.write_i32::<BigEndian>(neg_y)?;
-        f.write_i32::<BigEndian>(neg_z)?;
-        f.write_i32::<BigEndian>(pos_x)?;
-        f.write_i32::<BigEndian>(pos_y)?;
-        f.write_i32::<BigEndian>(pos_z)?;
-
-        for voxel in &self.voxels {
-            write_voxel(&mut f, voxel)?;
+        f.write_i32::<BigEndian>(extents.negative.x)?;
+        f.write_i32::<BigEndian>(extents.negative.y)?;
+        f.write_i32::<BigEndian>(extents.negative.z)?;
+        f.write_i32::<BigEndian>(extents.positive.x)?;
+        f.write_i32::<BigEndian>(extents.positive.y)?;
+        f.write_i32::<BigEndian>(extents.positive.z)?;
+
+        for voxel in grid {
+            if let Some(voxel) = voxel {
+                write_voxel(&mut f, voxel)?;
+            }
             f.write_u8(1)?;
         }
         f.write_u8(0)?;
@@ -709,8 +950,988 @@ impl SetupFile {
         Ok(())
     }
 
+    // every voxel of the grid in x/y/z order, None for the ones omitted from the list
+    pub fn grid(&self) -> std::io::Result<(GridExtents, Vec<Option<&Voxel>>)> {
+        let extents = match &self.extents {
+            Some(extents) => extents.clone(),
+            None => GridExtents::containing(self.voxels.iter().map(|v| &v.position)),
+        };
+
+        let mut grid = vec![None; extents.voxel_count()?];
+        let mut previous = None;
+
+        for voxel in &self.voxels {
+            let index = match extents.index_of(&voxel.position) {
+                Some(index) => index,
+                None => return Err(invalid_data(format!("voxel {:?} is outside of the grid {:?}", voxel.position, extents))),
+            };
+
+            if previous.is_some_and(|previous| index <= previous) {
+                return Err(invalid_data(format!("voxel {:?} is duplicated or not in x/y/z order", voxel.position)));
+            }
+
+            grid[index] = Some(voxel);
+            previous = Some(index);
+        }
+
+        Ok((extents, grid))
+    }
+
+    // errors are values that can't be written back as is, and broken links between objects
+    // unless they are allowed, they are warnings then
+    pub fn validate(&self, allow_broken_links: bool) -> Vec<Issue> {
+        let mut issues = vec![];
+        let broken_link = |message| if allow_broken_links { Issue::warning(message) } else { Issue::error(message) };
+
+        if let Err(e) = self.grid() {
+            issues.push(Issue::error(e.to_string()));
+        }
+
+        let mut nodes = HashMap::new();
+
+        for voxel in &self.voxels {
+            let p = &voxel.position;
+            let location = format!("voxel ({}, {}, {})", p.x, p.y, p.z);
+
+            if voxel.missing && (!voxel.complex_objects.is_empty() || !voxel.small_objects.is_empty()) {
+                issues.push(Issue::error(format!("{}: missing voxels can't contain objects", location)));
+            }
+            if voxel.complex_objects.len() > u8::MAX as usize {
+                issues.push(Issue::error(format!("{}: {} complex objects, the maximum is 255", location, voxel.complex_objects.len())));
+            }
+            if voxel.small_objects.len() > u8::MAX as usize {
+                issues.push(Issue::error(format!("{}: {} small objects, the maximum is 255", location, voxel.small_objects.len())));
+            }
+
+            for (i, complex) in voxel.complex_objects.iter().enumerate() {
+                let location = format!("{}, complex object #{} ({} {:#06X})", location, i, complex.kind(), complex.object_id());
+                let (current, next, end_indicator) = complex.links();
+
+                check_range(&mut issues, &location, "current", current as u32, 0xFFF);
+                check_range(&mut issues, &location, "next", next as u32, 0xFFF);
+                match complex {
+                    ComplexObject::Actor { rotation, .. } => check_halved(&mut issues, &location, "rotation", *rotation),
+                    ComplexObject::Radius { radius, .. } => check_halved(&mut issues, &location, "radius", *radius),
+                    _ => {},
+                };
+
+                if end_indicator > 1 {
+                    issues.push(Issue::warning(format!("{}: unexpected end_indicator {}", location, end_indicator)));
+                }
+
+                if current != 0 {
+                    if let Some(previous) = nodes.insert(current, location.clone()) {
+                        issues.push(broken_link(format!("{}: node {} is already used by {}", location, current, previous)));
+                    }
+                }
+            }
+
+            for (i, small) in voxel.small_objects.iter().enumerate() {
+                let location = format!("{}, small object #{} ({} {:#06X})", location, i, small.kind(), small.object_id());
+
+                check_range(&mut issues, &location, "object", small.object_id() as u32, 0xFFF);
+                match small {
+                    SmallObject::Sprite { size, unk0, unk1, unk2, .. } => {
+                        check_range(&mut issues, &location, "unk0", *unk0 as u32, 0xF);
+                        check_range(&mut issues, &location, "size", *size as u32, 0xFF);
+                        check_range(&mut issues, &location, "unk1", *unk1 as u32, 0x3F);
+                        check_range(&mut issues, &location, "unk2", *unk2 as u32, 0x3);
+                    },
+                    SmallObject::Static { unk0, .. } | SmallObject::Unknown { unk0, .. } => {
+                        check_range(&mut issues, &location, "unk0", *unk0 as u32, 0xF);
+                    },
+                };
+            }
+        }
+
+        for voxel in &self.voxels {
+            for complex in &voxel.complex_objects {
+                let (_, next, _) = complex.links();
+                if next != 0 && !nodes.contains_key(&next) {
+                    let p = &voxel.position;
+                    issues.push(broken_link(format!("voxel ({}, {}, {}), {} {:#06X}: next node {} doesn't exist",
+                        p.x, p.y, p.z, complex.kind(), complex.object_id(), next)));
+                }
+            }
+        }
+
+        issues
+    }
+
+    // drops the missing voxels, they are filled back in when writing
+    pub fn make_sparse(&mut self) {
+        self.voxels.retain(|voxel| !voxel.missing);
+    }
+
+    // the bytes of unknown objects with a layout are decoded into named fields
+    pub fn decode_layouts(&mut self, layouts: &ObjectLayouts) {
+        for voxel in &mut self.voxels {
+            for complex in &mut voxel.complex_objects {
+                if let ComplexObject::Unknown { object, script, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, fields, .. } = complex {
+                    if let Some(layout) = layouts.complex.get(object) {
+                        let [s0, s1] = script.to_be_bytes();
+                        let payload = [s0, s1, *unk_0a, *unk_0b, *unk_0c, *unk_0d, *unk_0e, *unk_0f];
+                        *fields = Some(layouts::decode(layout, &payload));
+                    }
+                }
+            }
+
+            for small in &mut voxel.small_objects {
+                if let SmallObject::Unknown { object, unk1, unk2, unk3, unk4, unk5, unk6, unk7, unk8, unk9, unk10, fields, .. } = small {
+                    if let Some(layout) = layouts.small.get(object) {
+                        let payload = [*unk1, *unk2, *unk3, *unk4, *unk5, *unk6, *unk7, *unk8, *unk9, *unk10];
+                        *fields = Some(layouts::decode(layout, &payload));
+                    }
+                }
+            }
+        }
+    }
+
+    // the decoded fields replace the bits they cover in the raw bytes
+    pub fn encode_layouts(&mut self, layouts: &ObjectLayouts) -> std::io::Result<()> {
+        for voxel in &mut self.voxels {
+            let p = &voxel.position;
+
+            for complex in &mut voxel.complex_objects {
+                if let ComplexObject::Unknown { object, script, unk_0a, unk_0b, unk_0c, unk_0d, unk_0e, unk_0f, fields: Some(fields), .. } = complex {
+                    let location = format!("voxel ({}, {}, {}), Unknown {:#06X}", p.x, p.y, p.z, object);
+                    let layout = layouts.complex.get(object).ok_or_else(|| invalid_data(format!("{}: no layout for its fields", location)))?;
+
+                    let [s0, s1] = script.to_be_bytes();
+                    let mut payload: [u8; COMPLEX_PAYLOAD_SIZE] = [s0, s1, *unk_0a, *unk_0b, *unk_0c, *unk_0d, *unk_0e, *unk_0f];
+                    layouts::encode(layout, fields, &mut payload).map_err(|e| invalid_data(format!("{}: {}", location, e)))?;
+
+                    *script = u16::from_be_bytes([payload[0], payload[1]]);
+                    [*unk_0a, *unk_0b, *unk_0c, *unk_0d, *unk_0e, *unk_0f] = [payload[2], payload[3], payload[4], payload[5], payload[6], payload[7]];
+                }
+            }
+
+            for small in &mut voxel.small_objects {
+                if let SmallObject::Unknown { object, unk1, unk2, unk3, unk4, unk5, unk6, unk7, unk8, unk9, unk10, fields: Some(fields), .. } = small {
+                    let location = format!("voxel ({}, {}, {}), Unknown {:#06X}", p.x, p.y, p.z, object);
+                    let layout = layouts.small.get(object).ok_or_else(|| invalid_data(format!("{}: no layout for its fields", location)))?;
+
+                    let mut payload: [u8; SMALL_PAYLOAD_SIZE] = [*unk1, *unk2, *unk3, *unk4, *unk5, *unk6, *unk7, *unk8, *unk9, *unk10];
+                    layouts::encode(layout, fields, &mut payload).map_err(|e| invalid_data(format!("{}: {}", location, e)))?;
+
+                    [*unk1, *unk2, *unk3, *unk4, *unk5, *unk6, *unk7, *unk8, *unk9, *unk10] = payload;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn fill_names(&mut self, names: &ObjectNames) {
+        for voxel in &mut self.voxels {
+            for complex in &mut voxel.complex_objects {
+                *complex.name_mut() = complex.catalogue_name(names).cloned();
+            }
+
+            for small in &mut voxel.small_objects {
+                *small.name_mut() = small.catalogue_name(names).cloned();
+            }
+        }
+    }
+
+    // names are informative only, a mismatch means the ID was probably edited by mistake
+    pub fn check_names(&self, names: &ObjectNames) {
+        for voxel in &self.voxels {
+            for complex in &voxel.complex_objects {
+                check_name(complex.kind(), complex.object_id(), complex.name(), complex.catalogue_name(names), &voxel.position);
+            }
+
+            for small in &voxel.small_objects {
+                check_name(small.kind(), small.object_id(), small.name(), small.catalogue_name(names), &voxel.position);
+            }
+        }
+    }
+
+    pub fn write_yaml(&self, filename: &str) {
+        let f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(filename).unwrap();
+        serde_yaml::to_writer(f, &self).unwrap();
+    }
+
+    pub fn write_gltf(&self, output_dir: &str) {
+        let root = self.build_gltf(output_dir);
+        let writer = File::create(format!("{}/setup.gltf", output_dir)).unwrap();
+        serde_json::to_writer_pretty(writer, &root).unwrap();
+
+        self.write_dot(&format!("{}/paths.dot", output_dir)).unwrap();
+    }
+
+    // the path buffer is written to output_dir, the document referencing it is returned
+    pub fn build_gltf(&self, output_dir: &str) -> gltf::Gltf {
+        let mut root = gltf::Gltf {
+            asset: gltf::Asset {
+                version: "2.0".to_string(),
+                generator: "bk_level".to_string(),
+            },
+            accessors: vec![],
+            animations: vec![],
+            buffers: vec![],
+            buffer_views: vec![],
+            cameras: vec![],
+            images: vec![],
+            materials: vec![],
+            meshes: vec![],
+            nodes: vec![gltf::Node {
+                name: Some("objects".to_string()),
+                ..Default::default()
+            }],
+            samplers: vec![],
+            scenes: vec![gltf::Scene {
+                nodes: vec![0],
+            }],
+            textures: vec![],
+            extensions_used: vec![],
+            extensions: None,
+        };
+
+        for voxel in &self.voxels {
+            for complex in &voxel.complex_objects {
+                let position = complex.position();
+                let (rotation, scale) = match complex {
+                    ComplexObject::Actor { rotation, size, .. } => {
+                        let angle = (*rotation as f32).to_radians() / 2.0;
+                        let scale = (*size as f32) / 100.0;
+                        (Some([0.0, angle.sin(), 0.0, angle.cos()]), Some([scale, scale, scale]))
+                    },
+                    _ => (None, None),
+                };
+
+                let index = root.nodes.len();
+                root.nodes[0].children.push(index);
+                root.nodes.push(gltf::Node {
+                    name: Some(object_label(complex.kind(), complex.object_id(), complex.name())),
+                    translation: Some([position.x as f32, position.y as f32, position.z as f32]),
+                    rotation,
+                    scale,
+                    extras: Some(object_extras(complex.kind(), complex.object_id(), complex, &voxel.position)),
+                    ..Default::default()
+                });
+            }
+
+            for small in &voxel.small_objects {
+                let translation = small.position().map(|p| [p.x as f32, p.y as f32, p.z as f32]);
+
+                let index = root.nodes.len();
+                root.nodes[0].children.push(index);
+                root.nodes.push(gltf::Node {
+                    name: Some(object_label(small.kind(), small.object_id(), small.name())),
+                    translation,
+                    extras: Some(object_extras(small.kind(), small.object_id(), small, &voxel.position)),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let cameras_node = root.nodes.len();
+        root.scenes[0].nodes.push(cameras_node);
+        root.nodes.push(gltf::Node {
+            name: Some("cameras".to_string()),
+            ..Default::default()
+        });
+
+        for camera in &self.cameras {
+            if let Some((position, angles)) = camera.placement() {
+                let index = root.cameras.len();
+                root.cameras.push(gltf::Camera {
+                    name: Some(format!("camera {}", camera.id())),
+                    type_: "perspective".to_string(),
+                    perspective: gltf::Perspective {
+                        yfov: CAMERA_FOV.to_radians(),
+                        znear: CAMERA_NEAR,
+                        zfar: None,
+                    },
+                });
+
+                let fields = serde_json::to_value(camera).unwrap();
+                let fields = fields.as_object().and_then(|o| o.values().next()).cloned().unwrap_or(fields);
+
+                let node = root.nodes.len();
+                root.nodes[cameras_node].children.push(node);
+                root.nodes.push(gltf::Node {
+                    name: Some(format!("camera {} ({})", camera.id(), camera.kind())),
+                    camera: Some(index),
+                    translation: Some([position.x, position.y, position.z]),
+                    // yaw, then pitch, then roll, like the game builds its view matrix.
+                    // glTF cameras look down -Z like the game's
+                    rotation: Some(euler_rotation(angles)),
+                    extras: Some(serde_json::json!({
+                        "type": camera.kind(),
+                        "id": camera.id(),
+                        "fields": fields,
+                    })),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let lights_node = root.nodes.len();
+        root.scenes[0].nodes.push(lights_node);
+        root.nodes.push(gltf::Node {
+            name: Some("lights".to_string()),
+            ..Default::default()
+        });
+
+        let mut lights = vec![];
+        for (i, lighting) in self.lightings.iter().enumerate() {
+            let (inner, outer) = lighting.radii();
+            lights.push(gltf::Light {
+                name: Some(format!("light {}", i)),
+                type_: "point".to_string(),
+                color: lighting.colour(),
+                intensity: 1.0,
+                range: if outer > 0.0 { Some(outer) } else { None },
+            });
+
+            let node = root.nodes.len();
+            root.nodes[lights_node].children.push(node);
+            root.nodes.push(gltf::Node {
+                name: Some(format!("light {}", i)),
+                translation: Some([lighting.position.x, lighting.position.y, lighting.position.z]),
+                extensions: Some(gltf::NodeExtensions {
+                    khr_lights_punctual: gltf::NodeLight { light: i },
+                }),
+                extras: Some(serde_json::json!({
+                    "inner_radius": inner,
+                    "outer_radius": outer,
+                    "fields": lighting,
+                })),
+                ..Default::default()
+            });
+        }
+
+        if !lights.is_empty() {
+            root.extensions_used.push("KHR_lights_punctual".to_string());
+            root.extensions = Some(gltf::GltfExtensions {
+                khr_lights_punctual: gltf::LightsPunctual { lights },
+            });
+        }
+
+        // one line strip per path
+        let mut path_vertices = vec![];
+        let paths_node = root.nodes.len();
+        root.scenes[0].nodes.push(paths_node);
+        root.nodes.push(gltf::Node {
+            name: Some("paths".to_string()),
+            ..Default::default()
+        });
+
+        for path in self.paths() {
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            let byte_offset = path_vertices.len() as u32;
+
+            for complex in &path {
+                let p = complex.position();
+                for (i, value) in [p.x as f32, p.y as f32, p.z as f32].into_iter().enumerate() {
+                    min[i] = min[i].min(value);
+                    max[i] = max[i].max(value);
+                    path_vertices.write_f32::<LittleEndian>(value).unwrap();
+                }
+            }
+
+            let buffer_view = root.buffer_views.len();
+            root.buffer_views.push(gltf::BufferView {
+                buffer: 0,
+                byte_length: path_vertices.len() as u32 - byte_offset,
+                byte_offset,
+                byte_stride: Some(12),
+                target: Some(34962),
+            });
+
+            let accessor = root.accessors.len();
+            root.accessors.push(gltf::Accessor {
+                buffer_view,
+                byte_offset: 0,
+                component_type: 5126,
+                count: path.len(),
+                max: Some(max.to_vec()),
+                min: Some(min.to_vec()),
+                normalized: false,
+                type_: "VEC3".to_string(),
+            });
+
+            let mesh = root.meshes.len();
+            root.meshes.push(gltf::Mesh {
+                primitives: vec![gltf::Primitive {
+                    attributes: HashMap::from([("POSITION".to_string(), accessor)]),
+                    material: None,
+                    mode: Some(gltf::PRIMITIVE_LINE_STRIP),
+                }],
+            });
+
+            let (first, _, _) = path[0].links();
+            let (last, _, _) = path[path.len() - 1].links();
+            let index = root.nodes.len();
+            root.nodes[paths_node].children.push(index);
+            root.nodes.push(gltf::Node {
+                name: Some(format!("path {} -> {}", first, last)),
+                mesh: Some(mesh),
+                ..Default::default()
+            });
+        }
+
+        if !path_vertices.is_empty() {
+            root.buffers.push(gltf::Buffer {
+                byte_length: path_vertices.len() as u32,
+                uri: "paths.bin".to_string(),
+            });
+
+            std::fs::write(format!("{}/paths.bin", output_dir), &path_vertices).unwrap();
+        }
+
+        root
+    }
+
+    // chains of complex objects linked by their current and next node ids,
+    // a path ends with the node it joins when it loops or merges into another one
+    pub fn paths(&self) -> Vec<Vec<&ComplexObject>> {
+        let nodes = self.nodes();
+        let referenced: HashSet<u16> = nodes.values().map(|complex| complex.links().1).collect();
+
+        let mut ids: Vec<u16> = nodes.keys().copied().collect();
+        ids.sort();
+
+        // the heads first, the nodes left after them are in loops
+        let heads = ids.iter().filter(|id| !referenced.contains(id));
+        let mut visited = HashSet::new();
+        let mut paths = vec![];
+
+        for &start in heads.chain(ids.iter()) {
+            if visited.contains(&start) {
+                continue;
+            }
+
+            let mut path = vec![];
+            let mut id = start;
+            loop {
+                let complex = nodes[&id];
+                path.push(complex);
+                visited.insert(id);
+
+                let (_, next, _) = complex.links();
+                match nodes.get(&next) {
+                    Some(next_complex) if visited.contains(&next) => {
+                        path.push(next_complex);
+                        break;
+                    },
+                    Some(_) => id = next,
+                    None => break,
+                }
+            }
+
+            if path.len() > 1 {
+                paths.push(path);
+            }
+        }
+
+        paths
+    }
+
+    // complex objects by node id, the first one wins if an id is used twice
+    fn nodes(&self) -> HashMap<u16, &ComplexObject> {
+        let mut nodes = HashMap::new();
+
+        for voxel in &self.voxels {
+            for complex in &voxel.complex_objects {
+                let (current, _, _) = complex.links();
+                if current != 0 {
+                    nodes.entry(current).or_insert(complex);
+                }
+            }
+        }
+
+        nodes
+    }
+
+    pub fn write_dot(&self, filename: &str) -> std::io::Result<()> {
+        let mut f = File::create(filename)?;
+        let nodes = self.nodes();
+
+        let mut ids: Vec<u16> = nodes.keys().copied().collect();
+        ids.sort();
+
+        writeln!(f, "digraph paths {{")?;
+
+        for id in &ids {
+            let complex = nodes[id];
+            let p = complex.position();
+            let label = object_label(complex.kind(), complex.object_id(), complex.name());
+            writeln!(f, "    n{} [label=\"{}\\n{}\\n({}, {}, {})\"];", id, id, label.replace('"', "\\\""), p.x, p.y, p.z)?;
+        }
+
+        for id in &ids {
+            let (_, next, _) = nodes[id].links();
+            if next == 0 {
+                continue;
+            }
+
+            if !nodes.contains_key(&next) {
+                writeln!(f, "    n{} [label=\"{}\\nmissing\", style=dashed];", next, next)?;
+            }
+            writeln!(f, "    n{} -> n{};", id, next)?;
+        }
+
+        writeln!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+impl GridExtents {
+    // the grid always contains the voxel at the origin
+    pub fn containing<'a>(positions: impl Iterator<Item = &'a Vector3<i32>>) -> GridExtents {
+        let mut negative = Vector3 { x: 0, y: 0, z: 0 };
+        let mut positive = Vector3 { x: 0, y: 0, z: 0 };
+
+        for position in positions {
+            negative = Vector3 { x: negative.x.min(position.x), y: negative.y.min(position.y), z: negative.z.min(position.z) };
+            positive = Vector3 { x: positive.x.max(position.x), y: positive.y.max(position.y), z: positive.z.max(position.z) };
+        }
+
+        GridExtents { negative, positive }
+    }
+
+    fn size(&self) -> Vector3<i32> {
+        Vector3 {
+            x: self.positive.x - self.negative.x + 1,
+            y: self.positive.y - self.negative.y + 1,
+            z: self.positive.z - self.negative.z + 1,
+        }
+    }
+
+    pub fn voxel_count(&self) -> std::io::Result<usize> {
+        let size = self.size();
+        if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+            return Err(invalid_data(format!("invalid grid extents {:?}", self)));
+        }
+
+        Ok((size.x as usize) * (size.y as usize) * (size.z as usize))
+    }
+
+    // z changes the fastest, like in the binary file
+    pub fn index_of(&self, position: &Vector3<i32>) -> Option<usize> {
+        let size = self.size();
+        let x = position.x - self.negative.x;
+        let y = position.y - self.negative.y;
+        let z = position.z - self.negative.z;
+
+        if x < 0 || y < 0 || z < 0 || x >= size.x || y >= size.y || z >= size.z {
+            return None;
+        }
+
+        Some(((x * size.y + y) * size.z + z) as usize)
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl FlatSetupFile {
+    pub fn read_yaml(filename: &str) -> Option<Self> {
+        let f = File::open(filename).unwrap_or_else(|_| panic!("Can't open {}", filename));
+        serde_yaml::from_reader(f).ok()
+    }
+
     pub fn write_yaml(&self, filename: &str) {
         let f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(filename).unwrap();
         serde_yaml::to_writer(f, &self).unwrap();
     }
+
+    pub fn from_setup(setup: SetupFile, voxel_size: i32) -> FlatSetupFile {
+        let mut complex_objects = vec![];
+        let mut small_objects = vec![];
+        let mut unplaced_objects = vec![];
+
+        for voxel in setup.voxels {
+            complex_objects.extend(voxel.complex_objects);
+
+            for small in voxel.small_objects {
+                if small.position().is_some() {
+                    small_objects.push(small);
+                } else {
+                    unplaced_objects.push(UnplacedObject {
+                        voxel: voxel.position.clone(),
+                        object: small,
+                    });
+                }
+            }
+        }
+
+        FlatSetupFile {
+            voxel_size,
+            cameras: setup.cameras,
+            complex_objects,
+            small_objects,
+            unplaced_objects,
+            lightings: setup.lightings,
+        }
+    }
+
+    pub fn into_setup(self) -> SetupFile {
+        let voxel_size = self.voxel_size;
+        let voxel_of = |position: Vector3<i16>| Vector3 {
+            x: (position.x as i32).div_euclid(voxel_size),
+            y: (position.y as i32).div_euclid(voxel_size),
+            z: (position.z as i32).div_euclid(voxel_size),
+        };
+
+        let mut placed_complex = vec![];
+        for complex in self.complex_objects {
+            placed_complex.push((voxel_of(complex.position()), complex));
+        }
+
+        let mut placed_small = vec![];
+        for small in self.small_objects {
+            let position = small.position().expect("small objects without coordinates must be in unplaced_objects");
+            placed_small.push((voxel_of(position), small));
+        }
+        for unplaced in self.unplaced_objects {
+            placed_small.push((unplaced.voxel, unplaced.object));
+        }
+
+        let extents = GridExtents::containing(placed_complex.iter().map(|(v, _)| v).chain(placed_small.iter().map(|(v, _)| v)));
+        let mut grid: Vec<Option<Voxel>> = (0..extents.voxel_count().unwrap()).map(|_| None).collect();
+
+        for (position, complex) in placed_complex {
+            let index = extents.index_of(&position).unwrap();
+            grid[index].get_or_insert_with(|| Voxel::empty(position)).complex_objects.push(complex);
+        }
+        for (position, small) in placed_small {
+            let index = extents.index_of(&position).unwrap();
+            grid[index].get_or_insert_with(|| Voxel::empty(position)).small_objects.push(small);
+        }
+
+        // voxels without objects are missing, so they are omitted
+        let voxels = grid.into_iter().flatten().collect();
+
+        SetupFile {
+            cameras: self.cameras,
+            extents: Some(extents),
+            voxels,
+            lightings: self.lightings,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(message: String) -> Issue {
+        Issue { severity: Severity::Error, message }
+    }
+
+    fn warning(message: String) -> Issue {
+        Issue { severity: Severity::Warning, message }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+// the value is packed in fewer bits than its type
+fn check_range(issues: &mut Vec<Issue>, location: &str, field: &str, value: u32, max: u32) {
+    if value > max {
+        issues.push(Issue::error(format!("{}: {} is {:#X}, the maximum is {:#X}", location, field, value, max)));
+    }
+}
+
+// the value is stored divided by 2 in a byte
+fn check_halved(issues: &mut Vec<Issue>, location: &str, field: &str, value: u16) {
+    if !value.is_multiple_of(2) || value / 2 > u8::MAX as u16 {
+        issues.push(Issue::error(format!("{}: {} is {}, it must be even and at most 510", location, field, value)));
+    }
+}
+
+fn check_name(kind: &str, id: u16, name: &Option<String>, expected: Option<&String>, voxel: &Vector3<i32>) {
+    if let Some(name) = name {
+        if expected != Some(name) {
+            let expected = expected.map(|e| e.as_str()).unwrap_or("no name");
+            eprintln!("warning: {} {:#06X} in voxel ({}, {}, {}) is named \"{}\" but the catalogue says \"{}\"",
+                kind, id, voxel.x, voxel.y, voxel.z, name, expected);
+        }
+    }
+}
+
+// the object's name when known, with its type and ID
+pub fn object_label(kind: &str, id: u16, name: &Option<String>) -> String {
+    match name {
+        Some(name) => format!("{} ({} {:#06X})", name, kind, id),
+        None => format!("{} {:#06X}", kind, id),
+    }
+}
+
+// the game's default field of view, in degrees
+const CAMERA_FOV: f32 = 40.0;
+const CAMERA_NEAR: f32 = 1.0;
+
+// the type, ID and raw fields of the object along with the voxel it's stored in
+fn object_extras<T: Serialize>(kind: &str, id: u16, object: &T, voxel: &Vector3<i32>) -> serde_json::Value {
+    let fields = serde_json::to_value(object).unwrap();
+    let fields = fields.as_object().and_then(|o| o.values().next()).cloned().unwrap_or(fields);
+
+    serde_json::json!({
+        "type": kind,
+        "id": id,
+        "voxel": voxel,
+        "fields": fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(current: u16, next: u16) -> ComplexObject {
+        ComplexObject::Actor {
+            name: None, x: 100, y: 200, z: 300, script: 0, object: 0x0046, unk_0a: 0,
+            unk_0b: 0, rotation: 90, unk_0d: 0, size: 100, current, next, end_indicator: 0,
+        }
+    }
+
+    fn sprite(object: u16) -> SmallObject {
+        SmallObject::Sprite { name: None, object, size: 50, x: 100, y: 200, z: 300, unk0: 0, unk1: 0, unk2: 0, unk3: 0, unk4: 0 }
+    }
+
+    fn voxel(x: i32, complex_objects: Vec<ComplexObject>, small_objects: Vec<SmallObject>) -> Voxel {
+        Voxel { position: Vector3 { x, y: 0, z: 0 }, complex_objects, small_objects, missing: false }
+    }
+
+    fn setup(voxels: Vec<Voxel>) -> SetupFile {
+        SetupFile { cameras: vec![], extents: None, voxels, lightings: vec![] }
+    }
+
+    fn messages(issues: &[Issue], severity: Severity) -> Vec<&str> {
+        issues.iter().filter(|issue| issue.severity == severity).map(|issue| issue.message.as_str()).collect()
+    }
+
+    #[test]
+    fn valid_file() {
+        let file = setup(vec![voxel(0, vec![actor(1, 2)], vec![sprite(0x0164)]), voxel(1, vec![actor(2, 0)], vec![])]);
+        assert!(file.validate(false).is_empty());
+    }
+
+    #[test]
+    fn values_out_of_range() {
+        let mut too_many = voxel(0, vec![], vec![]);
+        too_many.complex_objects = (0..256).map(|_| actor(0, 0)).collect();
+        too_many.small_objects = (0..256).map(|_| sprite(0x0164)).collect();
+        let issues = setup(vec![too_many]).validate(false);
+        assert_eq!(messages(&issues, Severity::Error), [
+            "voxel (0, 0, 0): 256 complex objects, the maximum is 255",
+            "voxel (0, 0, 0): 256 small objects, the maximum is 255",
+        ]);
+
+        let mut odd = actor(0x1000, 0x1000);
+        if let ComplexObject::Actor { rotation, .. } = &mut odd {
+            *rotation = 91;
+        }
+        let radius = ComplexObject::Radius {
+            name: None, x: 0, y: 0, z: 0, radius: 512, object: 0x06, associated: 0,
+            unk_0a: 0, unk_0b: 0, unk_0c: 0, unk_0d: 0, unk_0e: 0, unk_0f: 0, current: 0, next: 0, end_indicator: 0,
+        };
+        let mut big = sprite(0x1000);
+        if let SmallObject::Sprite { size, unk0, unk1, unk2, .. } = &mut big {
+            (*size, *unk0, *unk1, *unk2) = (0x100, 0x10, 0x40, 0x4);
+        }
+        let issues = setup(vec![voxel(0, vec![odd, radius], vec![big])]).validate(true);
+        let location = "voxel (0, 0, 0), complex object #0 (Actor 0x0046)";
+        let small = "voxel (0, 0, 0), small object #0 (Sprite 0x1000)";
+        assert_eq!(messages(&issues, Severity::Error), [
+            format!("{}: current is 0x1000, the maximum is 0xFFF", location),
+            format!("{}: next is 0x1000, the maximum is 0xFFF", location),
+            format!("{}: rotation is 91, it must be even and at most 510", location),
+            "voxel (0, 0, 0), complex object #1 (Radius 0x0006): radius is 512, it must be even and at most 510".to_string(),
+            format!("{}: object is 0x1000, the maximum is 0xFFF", small),
+            format!("{}: unk0 is 0x10, the maximum is 0xF", small),
+            format!("{}: size is 0x100, the maximum is 0xFF", small),
+            format!("{}: unk1 is 0x40, the maximum is 0x3F", small),
+            format!("{}: unk2 is 0x4, the maximum is 0x3", small),
+        ]);
+    }
+
+    #[test]
+    fn missing_voxels() {
+        let mut missing = voxel(0, vec![], vec![sprite(0x0164)]);
+        missing.missing = true;
+        let issues = setup(vec![missing]).validate(false);
+        assert_eq!(messages(&issues, Severity::Error), ["voxel (0, 0, 0): missing voxels can't contain objects"]);
+    }
+
+    #[test]
+    fn grid() {
+        let issues = setup(vec![voxel(1, vec![], vec![]), voxel(0, vec![], vec![])]).validate(false);
+        assert_eq!(messages(&issues, Severity::Error), ["voxel Vector3 { x: 0, y: 0, z: 0 } is duplicated or not in x/y/z order"]);
+
+        let mut file = setup(vec![voxel(5, vec![], vec![])]);
+        file.extents = Some(GridExtents { negative: Vector3 { x: 0, y: 0, z: 0 }, positive: Vector3 { x: 1, y: 1, z: 1 } });
+        assert_eq!(messages(&file.validate(false), Severity::Error).len(), 1);
+    }
+
+    #[test]
+    fn end_indicator() {
+        let mut object = actor(0, 0);
+        if let ComplexObject::Actor { end_indicator, .. } = &mut object {
+            *end_indicator = 2;
+        }
+        let issues = setup(vec![voxel(0, vec![object], vec![])]).validate(false);
+        assert!(messages(&issues, Severity::Error).is_empty());
+        assert_eq!(messages(&issues, Severity::Warning), ["voxel (0, 0, 0), complex object #0 (Actor 0x0046): unexpected end_indicator 2"]);
+    }
+
+    #[test]
+    fn duplicate_nodes() {
+        let file = setup(vec![voxel(0, vec![actor(3, 0)], vec![]), voxel(1, vec![actor(3, 0)], vec![])]);
+        let message = "voxel (1, 0, 0), complex object #0 (Actor 0x0046): node 3 is already used by voxel (0, 0, 0), complex object #0 (Actor 0x0046)";
+        assert_eq!(messages(&file.validate(false), Severity::Error), [message]);
+
+        let issues = file.validate(true);
+        assert!(messages(&issues, Severity::Error).is_empty());
+        assert_eq!(messages(&issues, Severity::Warning), [message]);
+
+        // 0 isn't a node
+        assert!(setup(vec![voxel(0, vec![actor(0, 0), actor(0, 0)], vec![])]).validate(false).is_empty());
+    }
+
+    #[test]
+    fn dangling_links() {
+        let file = setup(vec![voxel(0, vec![actor(1, 2)], vec![])]);
+        let message = "voxel (0, 0, 0), Actor 0x0046: next node 2 doesn't exist";
+        assert_eq!(messages(&file.validate(false), Severity::Error), [message]);
+
+        let issues = file.validate(true);
+        assert!(messages(&issues, Severity::Error).is_empty());
+        assert_eq!(messages(&issues, Severity::Warning), [message]);
+
+        // nothing is written with errors
+        let filename = std::env::temp_dir().join(format!("bk_level_{}_dangling.bin", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        assert!(file.write_bin(filename, false).is_err());
+        assert!(!std::path::Path::new(filename).exists());
+        file.write_bin(filename, true).unwrap();
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    fn at(mut object: ComplexObject, position: i16) -> ComplexObject {
+        if let ComplexObject::Actor { x, .. } = &mut object {
+            *x = position as u16;
+        }
+        object
+    }
+
+    #[test]
+    fn flat() {
+        let unknown = SmallObject::Unknown {
+            name: None, object: 0x0123, unk0: 1, unk1: 2, unk2: 3, unk3: 4, unk4: 5, unk5: 6, unk6: 7, unk7: 8, unk8: 9, unk9: 10, unk10: 11, fields: None,
+        };
+        let mut far = sprite(0x0164);
+        if let SmallObject::Sprite { x, .. } = &mut far {
+            *x = 2500;
+        }
+        let mut file = setup(vec![
+            voxel(-1, vec![at(actor(1, 2), -1), at(actor(2, 0), -1000)], vec![]),
+            voxel(0, vec![actor(3, 0)], vec![unknown]),
+            voxel(2, vec![], vec![far]),
+        ]);
+        file.extents = Some(GridExtents::containing(file.voxels.iter().map(|v| &v.position)));
+
+        // from a copy of the file
+        let flat = FlatSetupFile::from_setup(serde_yaml::from_str(&serde_yaml::to_string(&file).unwrap()).unwrap(), VOXEL_SIZE);
+        assert_eq!((flat.complex_objects.len(), flat.small_objects.len()), (3, 1));
+        assert_eq!(flat.unplaced_objects.len(), 1);
+        assert_eq!(flat.unplaced_objects[0].voxel, Vector3 { x: 0, y: 0, z: 0 });
+
+        // written as YAML, the voxel size defaults to a voxel
+        let yaml = serde_yaml::to_string(&flat).unwrap().replace("voxel_size: 1000\n", "");
+        let flat: FlatSetupFile = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(flat.voxel_size, VOXEL_SIZE);
+        assert_eq!(flat.into_setup(), file);
+
+        // objects go to the voxel containing them, the empty ones are omitted
+        let mut flat = FlatSetupFile::from_setup(setup(vec![voxel(0, vec![at(actor(0, 0), 2000)], vec![])]), 500);
+        flat.complex_objects.push(at(actor(0, 0), -501));
+        let rebuilt = flat.into_setup();
+        assert_eq!(rebuilt.extents, Some(GridExtents { negative: Vector3 { x: -2, y: 0, z: 0 }, positive: Vector3 { x: 4, y: 0, z: 0 } }));
+        let positions: Vec<i32> = rebuilt.voxels.iter().map(|v| v.position.x).collect();
+        assert_eq!(positions, [-2, 4]);
+        assert!(rebuilt.validate(false).is_empty());
+    }
+
+    fn extents(negative: [i32; 3], positive: [i32; 3]) -> GridExtents {
+        GridExtents {
+            negative: Vector3 { x: negative[0], y: negative[1], z: negative[2] },
+            positive: Vector3 { x: positive[0], y: positive[1], z: positive[2] },
+        }
+    }
+
+    #[test]
+    fn grid_extents() {
+        let positions = [Vector3 { x: 2, y: 3, z: 1 }, Vector3 { x: -1, y: 1, z: 4 }];
+        assert_eq!(GridExtents::containing(positions.iter()), extents([-1, 0, 0], [2, 3, 4]));
+        // the origin, even without voxels
+        assert_eq!(GridExtents::containing([].iter()), extents([0, 0, 0], [0, 0, 0]));
+
+        let grid = extents([-1, 0, -2], [1, 1, 2]);
+        assert_eq!(grid.voxel_count().unwrap(), 3 * 2 * 5);
+        // z changes the fastest, then y
+        assert_eq!(grid.index_of(&Vector3 { x: -1, y: 0, z: -2 }), Some(0));
+        assert_eq!(grid.index_of(&Vector3 { x: -1, y: 0, z: -1 }), Some(1));
+        assert_eq!(grid.index_of(&Vector3 { x: -1, y: 1, z: -2 }), Some(5));
+        assert_eq!(grid.index_of(&Vector3 { x: 0, y: 0, z: -2 }), Some(10));
+        assert_eq!(grid.index_of(&Vector3 { x: 1, y: 1, z: 2 }), Some(29));
+        assert_eq!(grid.index_of(&Vector3 { x: 2, y: 0, z: 0 }), None);
+        assert_eq!(grid.index_of(&Vector3 { x: 0, y: 0, z: -3 }), None);
+
+        assert!(extents([0, 0, 0], [-1, 0, 0]).voxel_count().is_err());
+    }
+
+    #[test]
+    fn sparse_voxels() {
+        let mut file = setup(vec![voxel(-1, vec![at(actor(1, 0), -1)], vec![]), voxel(1, vec![], vec![sprite(0x0164)])]);
+        file.voxels[1].position.z = 1;
+        file.extents = Some(extents([-2, 0, 0], [1, 0, 1]));
+
+        // the omitted voxels are None
+        let (grid_extents, grid) = file.grid().unwrap();
+        assert_eq!(grid_extents, extents([-2, 0, 0], [1, 0, 1]));
+        let present: Vec<usize> = grid.iter().enumerate().filter(|(_, v)| v.is_some()).map(|(i, _)| i).collect();
+        assert_eq!(present, [2, 7]);
+
+        // written back with the same extents, the missing voxels filled in
+        let filename = std::env::temp_dir().join(format!("bk_level_{}_sparse.bin", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        file.write_bin(filename, false).unwrap();
+        let mut read = SetupFile::read_bin(filename, &ObjectDefinitions::builtin()).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(read.extents, file.extents);
+        assert_eq!(read.voxels.len(), 8);
+        assert_eq!(read.voxels.iter().filter(|v| v.missing).count(), 6);
+        read.make_sparse();
+        let positions: Vec<[i32; 3]> = read.voxels.iter().map(|v| [v.position.x, v.position.y, v.position.z]).collect();
+        assert_eq!(positions, [[-1, 0, 0], [1, 0, 1]]);
+
+        // without extents, the grid contains the voxels
+        file.extents = None;
+        assert_eq!(file.grid().unwrap().0, extents([-1, 0, 0], [1, 0, 1]));
+    }
 }
@@ -2,7 +2,7 @@ use byteorder::WriteBytesExt;
 use byteorder::BigEndian;
 use byteorder::ReadBytesExt;
 use serde::{ Serialize, Deserialize };
-use std::fs::File;
+use std::io::{ Read, Write };
 
 #[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Vector3<T> {
@@ -17,76 +17,191 @@ pub struct Vector2<T> {
     pub y: T,
 }
 
-pub fn read_2_floats(f: &mut File) -> Vector2<f32> {
+pub fn read_2_floats(f: &mut impl Read) -> Vector2<f32> {
     let x = f.read_f32::<BigEndian>().unwrap();
     let y = f.read_f32::<BigEndian>().unwrap();
     Vector2 { x, y }
 }
 
-pub fn read_3_floats(f: &mut File) -> Vector3<f32> {
+pub fn read_3_floats(f: &mut impl Read) -> Vector3<f32> {
     let x = f.read_f32::<BigEndian>().unwrap();
     let y = f.read_f32::<BigEndian>().unwrap();
     let z = f.read_f32::<BigEndian>().unwrap();
     Vector3 { x, y, z }
 }
 
-pub fn read_3_u32(f: &mut File) -> Vector3<u32> {
+pub fn read_3_u32(f: &mut impl Read) -> Vector3<u32> {
     let x = f.read_u32::<BigEndian>().unwrap();
     let y = f.read_u32::<BigEndian>().unwrap();
     let z = f.read_u32::<BigEndian>().unwrap();
     Vector3 { x, y, z }
 }
 
-pub fn read_3_i16(f: &mut File) -> Vector3<i16> {
+pub fn read_3_i16(f: &mut impl Read) -> Vector3<i16> {
     let x = f.read_i16::<BigEndian>().unwrap();
     let y = f.read_i16::<BigEndian>().unwrap();
     let z = f.read_i16::<BigEndian>().unwrap();
     Vector3 { x, y, z }
 }
 
-pub fn read_2_i16(f: &mut File) -> Vector2<i16> {
+pub fn read_2_i16(f: &mut impl Read) -> Vector2<i16> {
     let x = f.read_i16::<BigEndian>().unwrap();
     let y = f.read_i16::<BigEndian>().unwrap();
     Vector2 { x, y }
 }
 
-pub fn read_3_u8(f: &mut File) -> Vector3<u8> {
+pub fn read_3_u8(f: &mut impl Read) -> Vector3<u8> {
     let x = f.read_u8().unwrap();
     let y = f.read_u8().unwrap();
     let z = f.read_u8().unwrap();
     Vector3 { x, y, z }
 }
 
-pub fn write_2_floats(f: &mut File, vec: &Vector2<f32>) {
+pub fn write_2_floats(f: &mut impl Write, vec: &Vector2<f32>) {
     f.write_f32::<BigEndian>(vec.x).unwrap();
     f.write_f32::<BigEndian>(vec.y).unwrap();
 }
 
-pub fn write_3_floats(f: &mut File, vec: &Vector3<f32>) {
+pub fn write_3_floats(f: &mut impl Write, vec: &Vector3<f32>) {
     f.write_f32::<BigEndian>(vec.x).unwrap();
     f.write_f32::<BigEndian>(vec.y).unwrap();
     f.write_f32::<BigEndian>(vec.z).unwrap();
 }
 
-pub fn write_3_i16(f: &mut File, vec: &Vector3<i16>) {
+pub fn write_3_i16(f: &mut impl Write, vec: &Vector3<i16>) {
     f.write_i16::<BigEndian>(vec.x).unwrap();
     f.write_i16::<BigEndian>(vec.y).unwrap();
     f.write_i16::<BigEndian>(vec.z).unwrap();
 }
 
-pub fn write_2_i16(f: &mut File, vec: &Vector2<i16>) {
+pub fn write_2_i16(f: &mut impl Write, vec: &Vector2<i16>) {
     f.write_i16::<BigEndian>(vec.x).unwrap();
     f.write_i16::<BigEndian>(vec.y).unwrap();
 }
 
-pub fn write_3_u32(f: &mut File, vec: &Vector3<u32>) {
+pub fn write_3_u32(f: &mut impl Write, vec: &Vector3<u32>) {
     f.write_u32::<BigEndian>(vec.x).unwrap();
     f.write_u32::<BigEndian>(vec.y).unwrap();
     f.write_u32::<BigEndian>(vec.z).unwrap();
 }
 
-pub fn write_3_u8(f: &mut File, vec: &Vector3<u8>) {
+pub fn write_3_u8(f: &mut impl Write, vec: &Vector3<u8>) {
     f.write_u8(vec.x).unwrap();
     f.write_u8(vec.y).unwrap();
     f.write_u8(vec.z).unwrap();
 }
+
+// a rotation by yaw, then pitch, then roll (in degrees), as a glTF quaternion
+pub fn euler_rotation(angles: &Vector3<f32>) -> [f32; 4] {
+    let pitch = axis_rotation([1.0, 0.0, 0.0], angles.x);
+    let yaw = axis_rotation([0.0, 1.0, 0.0], angles.y);
+    let roll = axis_rotation([0.0, 0.0, 1.0], angles.z);
+
+    quaternion_product(quaternion_product(yaw, pitch), roll)
+}
+
+// below this cosine of the pitch, yaw and roll turn around the same axis
+const GIMBAL_LOCK: f64 = 1e-4;
+
+// the angles (in degrees) of a quaternion, the inverse of euler_rotation. At a pitch of ±90°
+// only yaw - roll (or yaw + roll) is known, the yaw is kept at previous_yaw and the roll solved
+pub fn rotation_angles(q: [f32; 4], previous_yaw: f32) -> Vector3<f32> {
+    let norm = q.iter().map(|c| (*c as f64).powi(2)).sum::<f64>().sqrt();
+    let [x, y, z, w] = q.map(|c| c as f64 / norm);
+    let m00 = 1.0 - 2.0 * (y * y + z * z);
+    let m01 = 2.0 * (x * y - z * w);
+    let m02 = 2.0 * (x * z + y * w);
+    let m22 = 1.0 - 2.0 * (x * x + y * y);
+    let m12 = 2.0 * (y * z - x * w);
+    let m10 = 2.0 * (x * y + z * w);
+    let m11 = 1.0 - 2.0 * (x * x + z * z);
+
+    let cos_pitch = (m02 * m02 + m22 * m22).sqrt();
+    let pitch = (-m12).atan2(cos_pitch);
+    let (yaw, roll) = if cos_pitch < GIMBAL_LOCK {
+        let yaw = (previous_yaw as f64).to_radians();
+        let roll = if m12 < 0.0 { yaw - m01.atan2(m00) } else { (-m01).atan2(m00) - yaw };
+        (yaw, roll)
+    } else {
+        (m02.atan2(m22), m10.atan2(m11))
+    };
+
+    Vector3 {
+        x: pitch.to_degrees() as f32,
+        y: yaw.to_degrees() as f32,
+        z: roll.to_degrees() as f32,
+    }
+}
+
+fn axis_rotation(axis: [f32; 3], degrees: f32) -> [f32; 4] {
+    let half = degrees.to_radians() / 2.0;
+    [axis[0] * half.sin(), axis[1] * half.sin(), axis[2] * half.sin(), half.cos()]
+}
+
+fn quaternion_product(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    // the same rotation, as q and -q are
+    fn same_rotation(a: [f32; 4], b: [f32; 4]) -> bool {
+        let dot: f32 = (0..4).map(|i| a[i] * b[i]).sum();
+        close(dot.abs(), 1.0)
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let angles = [-179.0, -135.0, -90.0, -45.0, -10.0, 0.0, 30.0, 90.0, 120.0, 179.0];
+        for pitch in [-89.5, -60.0, -1.0, 0.0, 0.5, 45.0, 89.5] {
+            for yaw in angles {
+                for roll in angles {
+                    let input = Vector3 { x: pitch, y: yaw, z: roll };
+                    let output = rotation_angles(euler_rotation(&input), 0.0);
+                    assert!(close(output.x, pitch) && close(output.y, yaw) && close(output.z, roll), "{:?} -> {:?}", input, output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gimbal_lock() {
+        for pitch in [-90.0, 90.0] {
+            for yaw in [-120.0, 0.0, 45.0, 170.0] {
+                for roll in [-60.0, 0.0, 30.0, 150.0] {
+                    let input = Vector3 { x: pitch, y: yaw, z: roll };
+                    let q = euler_rotation(&input);
+
+                    // the previous yaw is kept, the roll gives the same rotation
+                    let output = rotation_angles(q, yaw);
+                    assert!(close(output.x, pitch) && close(output.y, yaw), "{:?} -> {:?}", input, output);
+                    let turns = (output.z - roll) / 360.0;
+                    assert!(close(turns, turns.round()), "{:?} -> {:?}", input, output);
+
+                    let output = rotation_angles(q, 10.0);
+                    assert!(close(output.y, 10.0));
+                    assert!(same_rotation(euler_rotation(&output), q), "{:?} -> {:?}", input, output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unnormalized_quaternion() {
+        let input = Vector3 { x: 20.0, y: -30.0, z: 40.0 };
+        let q = euler_rotation(&input).map(|c| c * 3.0);
+        let output = rotation_angles(q, 0.0);
+        assert!(close(output.x, 20.0) && close(output.y, -30.0) && close(output.z, 40.0));
+    }
+}